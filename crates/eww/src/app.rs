@@ -1,10 +1,11 @@
 use crate::{
     config,
-    daemon_response::DaemonResponseSender,
+    daemon_response::{DaemonResponseSender, ListenerInfo, ResponsePayload, VariableInfo, WindowInfo},
     display_backend, error_handling_ctx,
     gtk::prelude::{ContainerExt, CssProviderExt, GtkWindowExt, StyleContextExt, WidgetExt},
-    script_var_handler::ScriptVarHandlerHandle,
+    script_var_handler::{ListenProcessState, ScriptVarHandlerHandle},
     state::scope_graph::{ScopeGraph, ScopeGraphEvent, ScopeIndex},
+    widgets::CommandContext,
     EwwPaths, *,
 };
 use eww_shared_util::VarName;
@@ -29,6 +30,14 @@ use yuck::{
 pub enum DaemonCommand {
     NoOp,
     UpdateVars(Vec<(VarName, DynVal)>),
+    /// Update variables on behalf of a client, like `eww update`, which is told about any that couldn't be updated
+    SetVars {
+        mappings: Vec<(VarName, DynVal)>,
+        /// Whether to update all of the variables at once, or none of them if any can't be updated
+        atomic: bool,
+        sender: DaemonResponseSender,
+    },
+    UpdateListenProcessState(VarName, ListenProcessState),
     ReloadConfigAndCss(DaemonResponseSender),
     UpdateConfig(config::EwwConfig),
     UpdateCss(String),
@@ -52,7 +61,7 @@ pub enum DaemonCommand {
         sender: DaemonResponseSender,
     },
     KillServer,
-    CloseAll,
+    CloseAll(DaemonResponseSender),
     PrintState {
         all: bool,
         sender: DaemonResponseSender,
@@ -61,9 +70,15 @@ pub enum DaemonCommand {
         name: String,
         sender: DaemonResponseSender,
     },
+    Subscribe {
+        vars: Vec<VarName>,
+        windows: bool,
+        sender: DaemonResponseSender,
+    },
     PrintDebug(DaemonResponseSender),
     PrintGraph(DaemonResponseSender),
     PrintWindows(DaemonResponseSender),
+    PrintListeners(DaemonResponseSender),
 }
 
 #[derive(Debug, Clone)]
@@ -72,6 +87,17 @@ pub struct EwwWindow {
     pub definition: yuck::config::window_definition::WindowDefinition,
     pub scope_index: ScopeIndex,
     pub gtk_window: gtk::Window,
+    /// The index of the monitor the window was opened on, if one was given
+    pub monitor: Option<i32>,
+}
+
+/// A client of `eww subscribe`, which is sent a line whenever one of the variables it subscribed to changes.
+#[derive(Debug)]
+pub struct Subscription {
+    pub vars: HashSet<VarName>,
+    /// Whether the client is also interested in windows being opened and closed
+    pub windows: bool,
+    pub sender: DaemonResponseSender,
 }
 
 impl EwwWindow {
@@ -91,6 +117,12 @@ pub struct App {
 
     pub app_evt_send: UnboundedSender<DaemonCommand>,
     pub script_var_handler: ScriptVarHandlerHandle,
+    /// The state of the scripts of all currently running `deflisten` variables
+    pub listen_process_states: HashMap<VarName, ListenProcessState>,
+    /// The magic variables whose values have been computed since the config was loaded, and which are still in use.
+    /// All other magic variables are empty in the scope graph, and are only computed when a client asks for them.
+    pub initialized_inbuilt_vars: HashSet<VarName>,
+    pub subscriptions: Vec<Subscription>,
 
     pub paths: EwwPaths,
 }
@@ -102,6 +134,9 @@ impl std::fmt::Debug for App {
             .field("eww_config", &self.eww_config)
             .field("open_windows", &self.open_windows)
             .field("failed_windows", &self.failed_windows)
+            .field("listen_process_states", &self.listen_process_states)
+            .field("initialized_inbuilt_vars", &self.initialized_inbuilt_vars)
+            .field("subscriptions", &self.subscriptions)
             .field("paths", &self.paths)
             .finish()
     }
@@ -119,16 +154,34 @@ impl App {
                 }
                 DaemonCommand::UpdateVars(mappings) => {
                     for (var_name, new_value) in mappings {
-                        self.update_global_state(var_name, new_value);
+                        if let Err(err) = self.update_global_state(var_name, new_value) {
+                            error_handling_ctx::print_error(err);
+                        }
                     }
                 }
+                DaemonCommand::SetVars { mappings, atomic: true, sender } => {
+                    sender.respond_with_result(self.update_global_states(mappings))?;
+                }
+                DaemonCommand::SetVars { mappings, atomic: false, sender } => {
+                    let errors = mappings
+                        .into_iter()
+                        .map(|(var_name, new_value)| self.update_global_state(var_name, new_value))
+                        .filter_map(Result::err)
+                        .collect_vec();
+                    sender.respond_with_error_list(errors)?;
+                }
+                DaemonCommand::UpdateListenProcessState(var_name, state) => {
+                    self.listen_process_states.insert(var_name, state);
+                }
                 DaemonCommand::ReloadConfigAndCss(sender) => {
                     let mut errors = Vec::new();
 
+                    sender.send_progress("Reloading the configuration".to_string());
                     let config_result = config::read_from_file(&self.paths.get_yuck_path());
                     if let Err(e) = config_result.and_then(|new_config| self.load_config(new_config)) {
                         errors.push(e)
                     }
+                    sender.send_progress("Reloading the styles".to_string());
                     let css_result = crate::util::parse_scss_from_file(&self.paths.get_eww_scss_path());
                     if let Err(e) = css_result.and_then(|css| self.load_css(&css)) {
                         errors.push(e)
@@ -147,23 +200,24 @@ impl App {
                     self.stop_application();
                     let _ = crate::application_lifecycle::send_exit();
                 }
-                DaemonCommand::CloseAll => {
+                DaemonCommand::CloseAll(sender) => {
                     log::info!("Received close command, closing all windows");
-                    for (window_name, _window) in self.open_windows.clone() {
-                        self.close_window(&window_name)?;
-                    }
+                    let window_names = self.open_windows.keys().cloned().collect_vec();
+                    let errors = window_names.iter().map(|window_name| self.close_window(window_name)).filter_map(Result::err);
+                    sender.respond_with_error_list(errors)?;
                 }
                 DaemonCommand::OpenMany { windows, should_toggle, sender } => {
-                    let errors = windows
-                        .iter()
-                        .map(|w| {
-                            if should_toggle && self.open_windows.contains_key(w) {
-                                self.close_window(w)
-                            } else {
-                                self.open_window(w, None, None, None, None)
-                            }
-                        })
-                        .filter_map(Result::err);
+                    let mut errors = Vec::new();
+                    for w in &windows {
+                        let result = if should_toggle && self.open_windows.contains_key(w) {
+                            sender.send_progress(format!("Closing {}", w));
+                            self.close_window(w)
+                        } else {
+                            sender.send_progress(format!("Opening {}", w));
+                            self.open_window(w, None, None, None, None)
+                        };
+                        errors.extend(result.err());
+                    }
                     sender.respond_with_error_list(errors)?;
                 }
                 DaemonCommand::OpenWindow { window_name, pos, size, anchor, screen: monitor, should_toggle, sender } => {
@@ -185,26 +239,58 @@ impl App {
                     sender.respond_with_error_list(errors)?;
                 }
                 DaemonCommand::PrintState { all, sender } => {
-                    let scope_graph = self.scope_graph.borrow();
-                    let used_globals_names = scope_graph.currently_used_globals();
-                    let output = scope_graph
-                        .scope_at(scope_graph.root_index)
-                        .expect("No global scope in scopegraph")
-                        .data
-                        .iter()
-                        .filter(|(key, _)| all || used_globals_names.contains(*key))
-                        .map(|(key, value)| format!("{}: {}", key, value))
-                        .join("\n");
-                    sender.send_success(output)?
+                    self.with_inbuilt_values(self.globals(all), move |globals| {
+                        if sender.wants_json() {
+                            let variables = globals.iter().map(|(name, value)| VariableInfo::new(name, value)).collect();
+                            sender.send_structured(ResponsePayload::Variables { variables })
+                        } else {
+                            sender.send_success(globals.iter().map(|(name, value)| format!("{}: {}", name, value)).join("\n"))
+                        }
+                    });
                 }
                 DaemonCommand::GetVar { name, sender } => {
-                    let scope_graph = &*self.scope_graph.borrow();
-                    let vars = &scope_graph.scope_at(scope_graph.root_index).expect("No root scope in graph").data;
-                    match vars.get(name.as_str()) {
-                        Some(x) => sender.send_success(x.to_string())?,
+                    let name = VarName(name);
+                    let value = {
+                        let scope_graph = self.scope_graph.borrow();
+                        scope_graph.scope_at(scope_graph.root_index).expect("No root scope in graph").data.get(&name).cloned()
+                    };
+                    match value {
+                        Some(value) => self.with_inbuilt_values(vec![(name, value)], move |globals| {
+                            let (name, value) = &globals[0];
+                            if sender.wants_json() {
+                                sender.send_structured(ResponsePayload::Variable { variable: VariableInfo::new(name, value) })
+                            } else {
+                                sender.send_success(value.to_string())
+                            }
+                        }),
                         None => sender.send_failure(format!("Variable not found \"{}\"", name))?,
                     }
                 }
+                DaemonCommand::Subscribe { vars, windows, sender } => {
+                    let unknown = {
+                        let scope_graph = self.scope_graph.borrow();
+                        let globals = &scope_graph.scope_at(scope_graph.root_index).expect("No root scope in graph").data;
+                        vars.iter().find(|name| !globals.contains_key(*name)).cloned()
+                    };
+                    if vars.is_empty() && !windows {
+                        sender.send_failure("Nothing to subscribe to, please give the names of some variables".to_string())?;
+                    } else if let Some(unknown) = unknown {
+                        sender.send_failure(format!("Variable not found \"{}\"", unknown))?;
+                    } else {
+                        // magic variables nothing needed so far are started, and their first value is sent once it is computed
+                        let started_vars = self.start_uninitialized_inbuilt_vars(&vars);
+                        let scope_graph = self.scope_graph.borrow();
+                        let globals = &scope_graph.scope_at(scope_graph.root_index).expect("No root scope in graph").data;
+                        // start out with the current values, such that clients don't have to wait for the first change
+                        for name in vars.iter().filter(|name| !started_vars.contains(*name)) {
+                            sender.send_success(variable_event(name, &globals[name]))?;
+                        }
+                        self.subscriptions.push(Subscription { vars: vars.into_iter().collect(), windows, sender });
+                    }
+                }
+                DaemonCommand::PrintWindows(sender) if sender.wants_json() => {
+                    sender.send_structured(ResponsePayload::Windows { windows: self.window_infos() })?
+                }
                 DaemonCommand::PrintWindows(sender) => {
                     let output = self
                         .eww_config
@@ -217,6 +303,26 @@ impl App {
                         .join("\n");
                     sender.send_success(output)?
                 }
+                DaemonCommand::PrintListeners(sender) if sender.wants_json() => {
+                    sender.send_structured(ResponsePayload::Listeners { listeners: self.listener_infos() })?
+                }
+                DaemonCommand::PrintListeners(sender) => {
+                    let output = self
+                        .listen_process_states
+                        .iter()
+                        .sorted_by_key(|(name, _)| name.0.clone())
+                        .map(|(name, state)| format!("{}: {}", name, state))
+                        .join("\n");
+                    sender.send_success(output)?
+                }
+                DaemonCommand::PrintDebug(sender) if sender.wants_json() => {
+                    let windows = self.window_infos();
+                    let listeners = self.listener_infos();
+                    self.with_inbuilt_values(self.globals(true), move |globals| {
+                        let variables = globals.iter().map(|(name, value)| VariableInfo::new(name, value)).collect();
+                        sender.send_structured(ResponsePayload::Debug { windows, variables, listeners })
+                    });
+                }
                 DaemonCommand::PrintDebug(sender) => {
                     let output = format!("{:#?}", &self);
                     sender.send_success(output)?
@@ -230,6 +336,108 @@ impl App {
         }
     }
 
+    /// The global variables for `eww state`, sorted by name. Unless `all` is given, only the ones in use are included.
+    fn globals(&self, all: bool) -> Vec<(VarName, DynVal)> {
+        let scope_graph = self.scope_graph.borrow();
+        let used_globals_names = scope_graph.currently_used_globals();
+        let globals = &scope_graph.scope_at(scope_graph.root_index).expect("No global scope in scopegraph").data;
+        globals
+            .iter()
+            .filter(|(name, _)| all || used_globals_names.contains(*name))
+            .sorted_by_key(|(name, _)| name.0.clone())
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Call `respond` with the given globals, once the magic variables among them that nothing has needed so far, and which are
+    /// therefore still empty, have been computed. This way, clients reading them through `eww get` or `eww state` see their
+    /// actual values. Some of them take a while to compute, so this happens on a separate thread instead of blocking the UI.
+    fn with_inbuilt_values(
+        &self,
+        mut globals: Vec<(VarName, DynVal)>,
+        respond: impl FnOnce(Vec<(VarName, DynVal)>) -> Result<()> + Send + 'static,
+    ) {
+        let uninitialized_vars = globals
+            .iter()
+            .enumerate()
+            .filter(|(_, (name, _))| !self.initialized_inbuilt_vars.contains(name))
+            .filter_map(|(i, (name, _))| {
+                let var = self.eww_config.get_script_var(name).ok().filter(|var| config::script_var::is_inbuilt(var))?;
+                Some((i, var.clone(), self.eww_config.get_magic_vars().get(name).cloned()))
+            })
+            .collect_vec();
+        if uninitialized_vars.is_empty() {
+            crate::print_result_err!("while responding to a client", respond(globals));
+            return;
+        }
+        std::thread::spawn(move || {
+            for (i, var, magic_var) in uninitialized_vars {
+                log::debug!("Computing the value of {} on demand", var.name());
+                match config::script_var::initial_value(&var, magic_var.as_ref()) {
+                    Ok(value) => globals[i].1 = value,
+                    Err(err) => error_handling_ctx::print_error(err),
+                }
+            }
+            crate::print_result_err!("while responding to a client", respond(globals));
+        });
+    }
+
+    /// Start the magic variables among the given ones that nothing has needed so far, such that they are kept up to date
+    /// while a client is subscribed to them. Returns the names of the variables that were started.
+    fn start_uninitialized_inbuilt_vars(&mut self, names: &[VarName]) -> Vec<VarName> {
+        let mut started_vars = Vec::new();
+        for name in names {
+            let var = match self.eww_config.get_script_var(name) {
+                Ok(var) if config::script_var::is_inbuilt(var) => var,
+                _ => continue,
+            };
+            if self.initialized_inbuilt_vars.insert(name.clone()) {
+                self.script_var_handler.add(var.clone());
+                started_vars.push(name.clone());
+            }
+        }
+        started_vars
+    }
+
+    /// Stop the variables that neither a window nor a client of `eww subscribe` uses anymore.
+    fn stop_unused_vars(&mut self) {
+        let subscribed_vars = self.subscriptions.iter().flat_map(|sub| sub.vars.iter()).collect::<HashSet<_>>();
+        let unused_variables = self.scope_graph.borrow().currently_unused_globals();
+        for unused_var in unused_variables.into_iter().filter(|name| !subscribed_vars.contains(name)) {
+            log::debug!("stopping for {}", &unused_var);
+            self.script_var_handler.stop_for_variable(unused_var.clone());
+            self.listen_process_states.remove(&unused_var);
+            // nothing keeps its value up to date anymore, so it has to be computed again once it is needed
+            self.initialized_inbuilt_vars.remove(&unused_var);
+        }
+    }
+
+    /// All configured windows for `eww windows --json`, sorted by name.
+    fn window_infos(&self) -> Vec<WindowInfo> {
+        self.eww_config
+            .get_windows()
+            .iter()
+            .sorted_by_key(|(name, _)| name.to_string())
+            .map(|(name, window_def)| {
+                let open_window = self.open_windows.get(name);
+                WindowInfo {
+                    name: name.clone(),
+                    open: open_window.is_some(),
+                    failed: self.failed_windows.contains(name),
+                    monitor: open_window.map_or(window_def.monitor_number, |window| window.monitor),
+                }
+            })
+            .collect()
+    }
+
+    fn listener_infos(&self) -> Vec<ListenerInfo> {
+        self.listen_process_states
+            .iter()
+            .sorted_by_key(|(name, _)| name.0.clone())
+            .map(|(name, state)| ListenerInfo { name: name.0.clone(), state: state.to_string() })
+            .collect()
+    }
+
     fn stop_application(&mut self) {
         self.script_var_handler.stop_all();
         for (_, window) in self.open_windows.drain() {
@@ -238,27 +446,91 @@ impl App {
         gtk::main_quit();
     }
 
-    fn update_global_state(&mut self, fieldname: VarName, value: DynVal) {
+    /// Update a global variable, and start or stop the script vars whose `:run-while` depends on it.
+    /// Fails if the variable doesn't exist or can't be changed directly.
+    fn update_global_state(&mut self, fieldname: VarName, value: DynVal) -> Result<()> {
         let result = self.scope_graph.borrow_mut().update_global_value(&fieldname, value);
-        if let Err(err) = result {
-            error_handling_ctx::print_error(err);
+        self.handle_updated_globals(&[fieldname]);
+        result
+    }
+
+    /// Update several global variables at once, like [`Self::update_global_state`], such that the widgets using them are
+    /// only updated once. If any of them can't be updated, none of them are.
+    fn update_global_states(&mut self, mappings: Vec<(VarName, DynVal)>) -> Result<()> {
+        let var_names = mappings.iter().map(|(name, _)| name.clone()).unique().collect_vec();
+        self.scope_graph.borrow_mut().update_global_values(mappings)?;
+        self.handle_updated_globals(&var_names);
+        Ok(())
+    }
+
+    /// Tell subscribers about the globals that changed, and start or stop the script vars linked to the updated ones.
+    fn handle_updated_globals(&mut self, updated_vars: &[VarName]) {
+        let changed_globals = self.scope_graph.borrow_mut().take_changed_globals();
+        self.notify_subscribers(changed_globals);
+
+        let linked_vars = updated_vars
+            .iter()
+            .filter_map(|name| self.eww_config.get_run_while_link(name).ok())
+            .flatten()
+            .unique()
+            .cloned()
+            .collect::<Vec<_>>();
+        if !linked_vars.is_empty() {
+            // variables that no window uses stay stopped, regardless of their run-while condition
+            let used_globals = self.scope_graph.borrow().currently_used_globals();
+            let linked_vars = linked_vars.into_iter().filter(|name| used_globals.contains(name)).collect::<Vec<_>>();
+            for name in linked_vars {
+                let var = match self.eww_config.get_script_var(&name) {
+                    Ok(var) => var.clone(),
+                    Err(_) => continue,
+                };
+                match self.should_run(&var) {
+                    Ok(true) => self.script_var_handler.add(var),
+                    Ok(false) => {
+                        self.script_var_handler.stop_for_variable(name.clone());
+                        self.listen_process_states.remove(&name);
+                    }
+                    Err(err) => error_handling_ctx::print_error(err),
+                }
+            }
         }
+    }
 
-        if let Ok(linked_poll_vars) = self.eww_config.get_poll_var_link(&fieldname) {
-            linked_poll_vars.iter().filter_map(|name| self.eww_config.get_script_var(name).ok()).for_each(|var| {
-                if let ScriptVarDefinition::Poll(poll_var) = var {
-                    let scope_graph = self.scope_graph.borrow();
-                    let run_while = scope_graph
-                        .evaluate_simplexpr_in_scope(scope_graph.root_index, &poll_var.run_while_expr)
-                        .map(|v| v.as_bool());
-                    match run_while {
-                        Ok(Ok(true)) => self.script_var_handler.add(var.clone()),
-                        Ok(Ok(false)) => self.script_var_handler.stop_for_variable(poll_var.name.clone()),
-                        Ok(Err(err)) => error_handling_ctx::print_error(anyhow!(err)),
-                        Err(err) => error_handling_ctx::print_error(anyhow!(err)),
-                    };
+    /// Send the current values of the given variables to the `eww subscribe` clients that subscribed to them.
+    /// Clients that disconnected are removed, and the variables only they used are stopped.
+    fn notify_subscribers(&mut self, changed_vars: Vec<VarName>) {
+        if self.subscriptions.is_empty() {
+            return;
+        }
+        let subscription_count = self.subscriptions.len();
+        {
+            let scope_graph = self.scope_graph.borrow();
+            let globals = &scope_graph.scope_at(scope_graph.root_index).expect("No root scope in graph").data;
+            for name in changed_vars {
+                if let Some(value) = globals.get(&name) {
+                    let event = variable_event(&name, value);
+                    self.subscriptions.retain(|sub| !sub.vars.contains(&name) || sub.sender.send_success(event.clone()).is_ok());
                 }
-            });
+            }
+        }
+        if self.subscriptions.len() < subscription_count {
+            self.stop_unused_vars();
+        }
+    }
+
+    fn notify_window_subscribers(&mut self, window_name: &str, open: bool) {
+        let event = serde_json::json!({ "type": "window", "name": window_name, "open": open }).to_string();
+        self.subscriptions.retain(|sub| !sub.windows || sub.sender.send_success(event.clone()).is_ok());
+    }
+
+    /// Evaluate the `:run-while` condition of a script var. Variables without one should always run.
+    fn should_run(&self, var: &ScriptVarDefinition) -> Result<bool> {
+        match var.run_while_expr() {
+            Some(run_while_expr) => {
+                let scope_graph = self.scope_graph.borrow();
+                Ok(scope_graph.evaluate_simplexpr_in_scope(scope_graph.root_index, run_while_expr)?.as_bool()?)
+            }
+            None => Ok(true),
         }
     }
 
@@ -271,12 +543,8 @@ impl App {
         self.scope_graph.borrow_mut().remove_scope(eww_window.scope_index);
 
         eww_window.close();
-
-        let unused_variables = self.scope_graph.borrow().currently_unused_globals();
-        for unused_var in unused_variables {
-            log::debug!("stopping for {}", &unused_var);
-            self.script_var_handler.stop_for_variable(unused_var.clone());
-        }
+        self.notify_window_subscribers(window_name, false);
+        self.stop_unused_vars();
 
         Ok(())
     }
@@ -299,6 +567,8 @@ impl App {
             let mut window_def = self.eww_config.get_window(window_name)?.clone();
             window_def.geometry = window_def.geometry.map(|x| x.override_if_given(anchor, pos, size));
 
+            self.initialize_inbuilt_vars_of_window(window_name)?;
+
             let root_index = self.scope_graph.borrow().root_index;
 
             let window_scope = self.scope_graph.borrow_mut().register_new_scope(
@@ -308,9 +578,18 @@ impl App {
                 HashMap::new(),
             )?;
 
+            let monitor = monitor.or(window_def.monitor_number);
+            let command_context = CommandContext {
+                window_name: window_name.to_string(),
+                monitor,
+                script_env: self.eww_config.get_script_env().clone(),
+                placeholder_mode: self.eww_config.get_command_placeholders(),
+            };
+
             let root_widget = crate::widgets::build_widget::build_gtk_widget(
                 &mut *self.scope_graph.borrow_mut(),
                 Rc::new(self.eww_config.get_widget_definitions().clone()),
+                Rc::new(command_context),
                 window_scope,
                 window_def.widget.clone(),
                 None,
@@ -318,15 +597,24 @@ impl App {
 
             root_widget.style_context().add_class(&window_name.to_string());
 
-            let monitor_geometry = get_monitor_geometry(monitor.or(window_def.monitor_number))?;
+            let monitor_geometry = get_monitor_geometry(monitor)?;
 
-            let eww_window = initialize_window(monitor_geometry, root_widget, window_def, window_scope)?;
+            let eww_window = initialize_window(monitor_geometry, root_widget, window_def, window_scope, monitor)?;
 
             // initialize script var handlers for variables that where not used before opening this window.
             // TODO maybe this could be handled by having a track_newly_used_variables function in the scope tree?
-            for used_var in self.scope_graph.borrow().variables_used_in_self_or_subscopes_of(eww_window.scope_index) {
+            let used_vars = {
+                let scope_graph = self.scope_graph.borrow();
+                scope_graph
+                    .with_inputs_of_derived_globals(scope_graph.variables_used_in_self_or_subscopes_of(eww_window.scope_index))
+            };
+            for used_var in used_vars {
                 if let Ok(script_var) = self.eww_config.get_script_var(&used_var) {
-                    self.script_var_handler.add(script_var.clone());
+                    match self.should_run(script_var) {
+                        Ok(true) => self.script_var_handler.add(script_var.clone()),
+                        Ok(false) => {}
+                        Err(err) => error_handling_ctx::print_error(err),
+                    }
                 }
             }
 
@@ -337,6 +625,7 @@ impl App {
                 }
             });
             self.open_windows.insert(window_name.to_string(), eww_window);
+            self.notify_window_subscribers(window_name, true);
         };
 
         if let Err(err) = open_result {
@@ -347,24 +636,54 @@ impl App {
         }
     }
 
+    /// Compute the values of the magic variables the given window uses, which have not been needed before.
+    /// This has to happen before the widgets are built, as they would otherwise be built with empty values.
+    /// Afterwards, these variables are kept up to date by the script var handler, just like any other poll var.
+    fn initialize_inbuilt_vars_of_window(&mut self, window_name: &str) -> Result<()> {
+        for name in self.eww_config.inbuilt_vars_used_by_window(window_name)? {
+            if !self.initialized_inbuilt_vars.insert(name.clone()) {
+                continue;
+            }
+            log::debug!("Computing the initial value of {}", name);
+            let result: Result<_> = try {
+                let var = self.eww_config.get_script_var(&name)?;
+                let value = config::script_var::initial_value(var, self.eww_config.get_magic_vars().get(&name))?;
+                self.scope_graph.borrow_mut().update_global_value(&name, value)?;
+            };
+            if let Err(err) = result {
+                error_handling_ctx::print_error(err);
+            }
+        }
+        let changed_globals = self.scope_graph.borrow_mut().take_changed_globals();
+        self.notify_subscribers(changed_globals);
+        Ok(())
+    }
+
     /// Load the given configuration, reloading all script-vars and attempting to reopen all windows that where opened.
     pub fn load_config(&mut self, config: config::EwwConfig) -> Result<()> {
         log::info!("Reloading windows");
 
         self.script_var_handler.stop_all();
-        self.script_var_handler = script_var_handler::init(self.app_evt_send.clone());
+        self.script_var_handler = script_var_handler::init(self.app_evt_send.clone(), config.get_magic_vars().clone());
+        self.listen_process_states.clear();
 
         log::trace!("loading config: {:#?}", config);
 
         self.eww_config = config;
+        self.initialized_inbuilt_vars = self.eww_config.inbuilt_vars_used_by_globals();
         self.scope_graph.borrow_mut().clear(self.eww_config.generate_initial_state()?);
+        self.scope_graph.borrow_mut().register_derived_globals(self.eww_config.get_derived_globals().clone())?;
 
         let window_names: Vec<String> =
             self.open_windows.keys().cloned().chain(self.failed_windows.iter().cloned()).dedup().collect();
-        for window_name in &window_names {
-            self.open_window(window_name, None, None, None, None)?;
-        }
-        Ok(())
+        let open_result = window_names.iter().try_for_each(|window_name| self.open_window(window_name, None, None, None, None));
+
+        // all values have been reset, so subscribers need to be told about them again.
+        // This happens after the windows are opened, as the magic variables they use would otherwise be started here.
+        let subscribed_vars = self.subscriptions.iter().flat_map(|sub| sub.vars.iter().cloned()).unique().collect_vec();
+        let started_vars = self.start_uninitialized_inbuilt_vars(&subscribed_vars);
+        self.notify_subscribers(subscribed_vars.into_iter().filter(|name| !started_vars.contains(name)).collect());
+        open_result
     }
 
     pub fn load_css(&mut self, css: &str) -> Result<()> {
@@ -373,11 +692,17 @@ impl App {
     }
 }
 
+/// The line sent to `eww subscribe` clients when a variable changed.
+fn variable_event(name: &VarName, value: &DynVal) -> String {
+    serde_json::json!({ "type": "variable", "name": name.0, "value": value.0.to_json() }).to_string()
+}
+
 fn initialize_window(
     monitor_geometry: gdk::Rectangle,
     root_widget: gtk::Widget,
     window_def: WindowDefinition,
     window_scope: ScopeIndex,
+    monitor: Option<i32>,
 ) -> Result<EwwWindow> {
     let window = display_backend::initialize_window(&window_def, monitor_geometry)
         .with_context(|| format!("monitor {} is unavailable", window_def.monitor_number.unwrap()))?;
@@ -419,7 +744,13 @@ fn initialize_window(
 
     window.show_all();
 
-    Ok(EwwWindow { name: window_def.name.clone(), definition: window_def, gtk_window: window, scope_index: window_scope })
+    Ok(EwwWindow {
+        name: window_def.name.clone(),
+        definition: window_def,
+        gtk_window: window,
+        scope_index: window_scope,
+        monitor,
+    })
 }
 
 /// Apply the provided window-positioning rules to the window.
@@ -1,14 +1,15 @@
 use std::process::Stdio;
 
 use crate::{
-    daemon_response::DaemonResponse,
-    opts::{self, ActionClientOnly},
+    daemon_response::{DaemonResponse, ResponseFormat},
+    ipc_protocol::{self, Command, Handshake, Request},
+    opts::ActionClientOnly,
     EwwPaths,
 };
 use anyhow::*;
 use std::{
-    io::{Read, Write},
     os::unix::net::UnixStream,
+    time::{Duration, Instant},
 };
 
 pub fn handle_client_only_action(paths: &EwwPaths, action: ActionClientOnly) -> Result<()> {
@@ -24,27 +25,80 @@ pub fn handle_client_only_action(paths: &EwwPaths, action: ActionClientOnly) ->
     Ok(())
 }
 
+/// Connect to the daemon and send the given request, then wait until the daemon is done with it.
+/// Progress messages sent by the daemon in the meantime are passed to `handle_progress`.
+/// Returns the response from the daemon, or None for commands that the daemon doesn't respond to, like `kill`.
+/// Fails if the daemon takes longer than the given timeout, or closes the connection without responding to a command that
+/// it should have responded to.
+pub fn do_server_call(
+    stream: &mut UnixStream,
+    command: &Command,
+    format: ResponseFormat,
+    timeout: Option<Duration>,
+    mut handle_progress: impl FnMut(String),
+) -> Result<Option<DaemonResponse>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    set_read_deadline(stream, deadline)?;
+    send_request(stream, command, format)?;
+    loop {
+        set_read_deadline(stream, deadline)?;
+        let response = match ipc_protocol::read_message(stream) {
+            Ok(response) => response,
+            Err(err) if is_timeout(&err) => bail!(
+                "The daemon did not finish the command within {:?}. It may still be working on it, use --timeout to wait longer",
+                timeout.unwrap_or_default()
+            ),
+            Err(err) => return Err(err.context("Error reading response from server")),
+        };
+        match response {
+            Some(DaemonResponse::Progress(progress)) => handle_progress(progress),
+            None if command.has_response() => {
+                bail!("The daemon closed the connection without responding. Run `eww logs` to see what went wrong")
+            }
+            response => return Ok(response),
+        }
+    }
+}
 
-/// Connect to the daemon and send the given request.
-/// Returns the response from the daemon, or None if the daemon did not provide any useful response. An Ok(None) response does _not_ indicate failure.
-pub fn do_server_call(stream: &mut UnixStream, action: &opts::ActionWithServer) -> Result<Option<DaemonResponse>> {
-    log::debug!("Forwarding options to server");
-    stream.set_nonblocking(false).context("Failed to set stream to non-blocking")?;
+/// Make reads from the stream fail once the deadline has passed.
+fn set_read_deadline(stream: &UnixStream, deadline: Option<Instant>) -> Result<()> {
+    // a timeout of zero is rejected rather than failing right away, so the smallest one possible is used instead
+    let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1)));
+    stream.set_read_timeout(timeout).context("Failed to set read timeout")
+}
 
-    let message_bytes = bincode::serialize(&action)?;
+fn is_timeout(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|err| matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut))
+}
 
-    stream.write(&(message_bytes.len() as u32).to_be_bytes()).context("Failed to send command size header to IPC stream")?;
+/// Connect to the daemon and send the given streaming request, such as `eww subscribe`.
+/// Every response is passed to `handle_response`, until the daemon closes the connection or `handle_response` fails.
+pub fn do_streaming_server_call(
+    stream: &mut UnixStream,
+    command: &Command,
+    format: ResponseFormat,
+    mut handle_response: impl FnMut(DaemonResponse) -> Result<()>,
+) -> Result<()> {
+    send_request(stream, command, format)?;
+    while let Some(response) = ipc_protocol::read_message(stream).context("Error reading response from server")? {
+        handle_response(response)?;
+    }
+    Ok(())
+}
 
-    stream.write_all(&message_bytes).context("Failed to write command to IPC stream")?;
+/// Perform the handshake with the daemon and send it the request.
+fn send_request(stream: &mut UnixStream, command: &Command, format: ResponseFormat) -> Result<()> {
+    log::debug!("Forwarding options to server");
+    stream.set_nonblocking(false).context("Failed to set stream to non-blocking")?;
 
-    let mut buf = Vec::new();
-    stream.set_read_timeout(Some(std::time::Duration::from_millis(100))).context("Failed to set read timeout")?;
-    stream.read_to_end(&mut buf).context("Error reading response from server")?;
+    let client_handshake = Handshake::current();
+    ipc_protocol::write_message(stream, &client_handshake)?;
+    let daemon_handshake: Handshake = ipc_protocol::read_message(stream)
+        .context("Failed to read handshake from daemon")?
+        .context("The daemon closed the connection during the handshake. It may be running an older version of eww")?;
+    ipc_protocol::check_versions(&client_handshake, &daemon_handshake)?;
 
-    Ok(if buf.is_empty() {
-        None
-    } else {
-        let buf = bincode::deserialize(&buf)?;
-        Some(buf)
-    })
+    ipc_protocol::write_message(stream, &Request { command: command.clone(), format })
 }
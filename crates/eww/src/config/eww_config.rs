@@ -1,12 +1,26 @@
 use anyhow::*;
 use eww_shared_util::VarName;
-use std::{collections::HashMap, path::Path};
+use itertools::Itertools;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 use yuck::config::{
-    file_provider::YuckFiles, script_var_definition::ScriptVarDefinition, widget_definition::WidgetDefinition,
-    window_definition::WindowDefinition, Config,
+    file_provider::YuckFiles,
+    magic_var_definition::MagicVarDefinition,
+    script_var_definition::{ScriptEnvironment, ScriptVarDefinition},
+    settings::PlaceholderMode,
+    widget_definition::WidgetDefinition,
+    widget_use::WidgetUse,
+    window_definition::WindowDefinition,
+    Config,
 };
 
-use simplexpr::dynval::DynVal;
+use simplexpr::{
+    ast::meta_var_name,
+    dynval::{DynVal, ValueType},
+    SimplExpr,
+};
 
 use crate::error_handling_ctx;
 
@@ -25,9 +39,17 @@ pub struct EwwConfig {
     windows: HashMap<String, WindowDefinition>,
     initial_variables: HashMap<VarName, DynVal>,
     script_vars: HashMap<VarName, ScriptVarDefinition>,
+    /// The configuration of magic variables given with `defmagic`
+    magic_vars: HashMap<VarName, MagicVarDefinition>,
+    /// The variables defined via `defexpr`, ordered such that every expression only references the ones before it.
+    derived_globals: Vec<(VarName, SimplExpr)>,
 
-    // Links variable which affect state (active/inactive) of poll var to those poll variables
-    poll_var_links: HashMap<VarName, Vec<VarName>>,
+    // Links variable which affect state (active/inactive) of poll and listen vars to those variables
+    run_while_links: HashMap<VarName, Vec<VarName>>,
+
+    /// The shell, environment and working directory set in `defsettings`, used by the commands run from widgets
+    script_env: ScriptEnvironment,
+    command_placeholders: PlaceholderMode,
 }
 
 impl Default for EwwConfig {
@@ -37,7 +59,11 @@ impl Default for EwwConfig {
             windows: HashMap::new(),
             initial_variables: HashMap::new(),
             script_vars: HashMap::new(),
-            poll_var_links: HashMap::new(),
+            magic_vars: HashMap::new(),
+            derived_globals: Vec::new(),
+            run_while_links: HashMap::new(),
+            script_env: ScriptEnvironment::default(),
+            command_placeholders: PlaceholderMode::default(),
         }
     }
 }
@@ -50,41 +76,122 @@ impl EwwConfig {
         let config = Config::generate_from_main_file(files, path)?;
 
         // run some validations on the configuration
-        yuck::config::validate::validate(&config, super::inbuilt::get_inbuilt_vars().keys().cloned().collect())?;
+        let inbuilt_names =
+            super::inbuilt::get_inbuilt_vars().into_keys().flat_map(|name| [meta_var_name(&name), name]).collect();
+        yuck::config::validate::validate(&config, inbuilt_names)?;
+        let magic_var_types = super::inbuilt::get_inbuilt_vars().into_keys().map(|name| (name, ValueType::Object)).collect();
+        yuck::config::validate::typecheck(&config, magic_var_types, &crate::widgets::prop_types::WIDGET_PROP_TYPES)?;
 
-        let Config { widget_definitions, window_definitions, var_definitions, mut script_vars } = config;
-        script_vars.extend(crate::config::inbuilt::get_inbuilt_vars());
+        let Config {
+            widget_definitions,
+            window_definitions,
+            var_definitions,
+            mut script_vars,
+            magic_vars,
+            expr_definitions,
+            settings,
+        } = config;
+        for var in script_vars.values_mut() {
+            match var {
+                ScriptVarDefinition::Poll(var) => {
+                    var.timeout = var.timeout.or(settings.poll_timeout);
+                    var.script_env = std::mem::take(&mut var.script_env).with_defaults(&settings.script_env);
+                }
+                ScriptVarDefinition::Listen(var) => {
+                    var.script_env = std::mem::take(&mut var.script_env).with_defaults(&settings.script_env);
+                }
+                ScriptVarDefinition::File(_) | ScriptVarDefinition::Socket(_) => {}
+            }
+        }
+        script_vars.extend(crate::config::inbuilt::get_configured_inbuilt_vars(&magic_vars)?);
 
-        let mut poll_var_links = HashMap::<VarName, Vec<VarName>>::new();
-        script_vars
-            .iter()
-            .filter_map(|(_, var)| if let ScriptVarDefinition::Poll(poll_var) = var { Some(poll_var) } else { None })
-            .for_each(|var| {
-                var.run_while_var_refs
-                    .iter()
-                    .for_each(|name| poll_var_links.entry(name.clone()).or_default().push(var.name.clone()))
-            });
+        let derived_globals: Vec<(VarName, SimplExpr)> =
+            yuck::config::validate::expr_definitions_in_dependency_order(&expr_definitions)?
+                .into_iter()
+                .map(|def| (def.name.clone(), def.expr.clone()))
+                .collect();
+
+        // A script var whose run-while expression uses a defexpr needs to be re-checked whenever any input of that defexpr changes.
+        // As every defexpr only references the ones before it, the inputs of the defexprs it uses are already known.
+        let mut inputs_of: HashMap<&VarName, Vec<VarName>> = HashMap::new();
+        for (name, expr) in derived_globals.iter() {
+            let mut inputs = Vec::new();
+            for input in expr.collect_var_refs() {
+                inputs.extend(inputs_of.get(&input).cloned().unwrap_or_default());
+                inputs.push(input);
+            }
+            inputs_of.insert(name, inputs);
+        }
+
+        let mut run_while_links = HashMap::<VarName, Vec<VarName>>::new();
+        script_vars.values().for_each(|var| {
+            var.run_while_var_refs()
+                .iter()
+                .flat_map(|name| std::iter::once(name.clone()).chain(inputs_of.get(name).cloned().unwrap_or_default()))
+                .unique()
+                .for_each(|name| run_while_links.entry(name).or_default().push(var.name().clone()))
+        });
 
         Ok(EwwConfig {
             windows: window_definitions,
             widgets: widget_definitions,
-            initial_variables: var_definitions.into_iter().map(|(k, v)| (k, v.initial_value)).collect(),
+            initial_variables: var_definitions
+                .into_iter()
+                .map(|(k, v)| {
+                    let span = v.initial_value.1;
+                    (k, DynVal::from_string_or_json(v.initial_value.into_inner()).at(span))
+                })
+                .collect(),
             script_vars,
-            poll_var_links,
+            magic_vars,
+            derived_globals,
+            run_while_links,
+            script_env: settings.script_env,
+            command_placeholders: settings.command_placeholders.unwrap_or_default(),
         })
     }
 
+    /// Generate the initial values of all global variables.
+    /// Magic variables are only computed here if they are used outside of windows, see [`Self::inbuilt_vars_used_by_globals`].
+    /// All others start out empty, and are computed once a window that uses them is opened.
     // TODO this is kinda ugly
     pub fn generate_initial_state(&self) -> Result<HashMap<VarName, DynVal>> {
+        let inbuilt_vars_used_by_globals = self.inbuilt_vars_used_by_globals();
         let mut vars = self
             .script_vars
             .iter()
-            .map(|(name, var)| Ok((name.clone(), script_var::initial_value(var)?)))
+            .map(|(name, var)| {
+                if script_var::is_inbuilt(var) && !inbuilt_vars_used_by_globals.contains(name) {
+                    Ok((name.clone(), DynVal::from_string(String::new())))
+                } else {
+                    Ok((name.clone(), script_var::initial_value(var, self.magic_vars.get(name))?))
+                }
+            })
             .collect::<Result<HashMap<_, _>>>()?;
+        vars.extend(self.script_vars.keys().map(|name| (meta_var_name(name), script_var::ScriptVarMeta::default().to_dynval())));
         vars.extend(self.initial_variables.clone());
         Ok(vars)
     }
 
+    /// The magic variables that are referenced by a `defexpr` or by the `:run-while` of a `defpoll` or `deflisten`.
+    /// These are evaluated right when the config is loaded, so their values are needed from the start.
+    pub fn inbuilt_vars_used_by_globals(&self) -> HashSet<VarName> {
+        let run_while_var_refs = self.script_vars.values().flat_map(|var| var.run_while_var_refs().iter().cloned());
+        self.derived_globals
+            .iter()
+            .flat_map(|(_, expr)| expr.collect_var_refs())
+            .chain(run_while_var_refs)
+            .filter(|name| self.script_vars.get(name).map_or(false, script_var::is_inbuilt))
+            .collect()
+    }
+
+    /// The magic variables that may be used by the given window, including within the definitions of the widgets it uses.
+    pub fn inbuilt_vars_used_by_window(&self, window_name: &str) -> Result<HashSet<VarName>> {
+        let mut var_refs = HashSet::new();
+        collect_var_refs_in_widget(&self.widgets, &self.get_window(window_name)?.widget, &mut HashSet::new(), &mut var_refs);
+        Ok(var_refs.into_iter().filter(|name| self.script_vars.get(name).map_or(false, script_var::is_inbuilt)).collect())
+    }
+
     pub fn get_windows(&self) -> &HashMap<String, WindowDefinition> {
         &self.windows
     }
@@ -103,11 +210,50 @@ impl EwwConfig {
         self.script_vars.get(name).with_context(|| format!("No script var named '{}' exists", name))
     }
 
+    pub fn get_magic_vars(&self) -> &HashMap<VarName, MagicVarDefinition> {
+        &self.magic_vars
+    }
+
     pub fn get_widget_definitions(&self) -> &HashMap<String, WidgetDefinition> {
         &self.widgets
     }
 
-    pub fn get_poll_var_link(&self, name: &VarName) -> Result<&Vec<VarName>> {
-        self.poll_var_links.get(name).with_context(|| format!("{} does not links to any poll variable", name.0))
+    pub fn get_derived_globals(&self) -> &Vec<(VarName, SimplExpr)> {
+        &self.derived_globals
+    }
+
+    pub fn get_script_env(&self) -> &ScriptEnvironment {
+        &self.script_env
+    }
+
+    pub fn get_command_placeholders(&self) -> PlaceholderMode {
+        self.command_placeholders
+    }
+
+    pub fn get_run_while_link(&self, name: &VarName) -> Result<&Vec<VarName>> {
+        self.run_while_links.get(name).with_context(|| format!("{} does not links to any poll or listen variable", name.0))
+    }
+}
+
+/// Collect the variables referenced anywhere within a widget, following the definitions of any custom widgets it uses.
+/// This doesn't know about the arguments and `let` bindings within widgets, so it may also contain names that refer to those.
+fn collect_var_refs_in_widget<'a>(
+    defs: &'a HashMap<String, WidgetDefinition>,
+    widget: &'a WidgetUse,
+    visited_defs: &mut HashSet<&'a str>,
+    var_refs: &mut HashSet<VarName>,
+) {
+    for attr in widget.attrs.attrs.values() {
+        if let Ok(expr) = attr.value.as_simplexpr() {
+            var_refs.extend(expr.collect_var_refs());
+        }
+    }
+    if let Some(def) = defs.get(&widget.name) {
+        if visited_defs.insert(&def.name) {
+            collect_var_refs_in_widget(defs, &def.widget, visited_defs, var_refs);
+        }
+    }
+    for child in &widget.children {
+        collect_var_refs_in_widget(defs, child, visited_defs, var_refs);
     }
 }
@@ -1,9 +1,21 @@
 use std::{collections::HashMap, time::Duration};
 
-use simplexpr::{dynval::DynVal, SimplExpr};
-use yuck::config::script_var_definition::{PollScriptVar, ScriptVarDefinition, VarSource};
+use itertools::Itertools;
 
-use crate::config::system_stats::*;
+use simplexpr::{
+    dynval::{DynVal, Value},
+    functions::register_function,
+    SimplExpr,
+};
+use yuck::{
+    config::{
+        magic_var_definition::MagicVarDefinition,
+        script_var_definition::{PollScriptVar, ScriptEnvironment, ScriptVarDefinition, VarSource},
+    },
+    gen_diagnostic,
+};
+
+use crate::{config::system_stats::*, error::DiagError};
 use eww_shared_util::VarName;
 
 macro_rules! builtin_vars {
@@ -17,6 +29,8 @@ macro_rules! builtin_vars {
                 command: VarSource::Function($fun),
                 initial_value: None,
                 interval: $interval,
+                timeout: None,
+                script_env: ScriptEnvironment::default(),
                 name_span: eww_shared_util::span::Span::DUMMY,
             })
             ),*
@@ -27,19 +41,19 @@ pub fn get_inbuilt_vars() -> HashMap<VarName, ScriptVarDefinition> {
     builtin_vars! {Duration::new(2, 0),
         // @desc EWW_TEMPS - Heat of the components in Celcius
         // @prop { <name>: temperature }
-        "EWW_TEMPS" => || Ok(DynVal::from(get_temperatures())),
+        "EWW_TEMPS" => |_| Ok(DynVal::from_string_or_json(get_temperatures())),
 
         // @desc EWW_RAM - Information on ram and swap usage in kB.
         // @prop { total_mem, free_mem, total_swap, free_swap, available_mem, used_mem, used_mem_perc }
-        "EWW_RAM" => || Ok(DynVal::from(get_ram())),
+        "EWW_RAM" => |_| Ok(DynVal::from_string_or_json(get_ram())),
 
-        // @desc EWW_DISK - Information on on all mounted partitions (Might report inaccurately on some filesystems, like btrfs)\nExample: `{EWW_DISK["/"]}`
-        // @prop { <mount_point>: { name, total, free, used, used_perc } }
-        "EWW_DISK" => || Ok(DynVal::from(get_disks())),
+        // @desc EWW_DISK - Information on on all mounted partitions (Might report inaccurately on some filesystems, like btrfs). `read` and `write` are in bytes per second, `read_total` and `write_total` in bytes since boot, and only known on Linux\nExample: `{EWW_DISK["/"]}`
+        // @prop { <mount_point>: { name, total, free, used, used_perc, read, write, read_total, write_total } }
+        "EWW_DISK" => |_| Ok(DynVal::from_string_or_json(get_disks())),
 
         // @desc EWW_BATTERY - Battery capacity in procent of the main battery
         // @prop { <name>: { capacity, status } }
-        "EWW_BATTERY" => || Ok(DynVal::from(
+        "EWW_BATTERY" => |_| Ok(DynVal::from_string_or_json(
             match get_battery_capacity() {
                 Err(e) => {
                     log::error!("Couldn't get the battery capacity: {:?}", e);
@@ -51,10 +65,104 @@ pub fn get_inbuilt_vars() -> HashMap<VarName, ScriptVarDefinition> {
 
         // @desc EWW_CPU - Information on the CPU cores: frequency and usage (No MacOS support)
         // @prop { cores: [{ core, freq, usage }], avg }
-        "EWW_CPU" => || Ok(DynVal::from(get_cpus())),
+        "EWW_CPU" => |_| Ok(DynVal::from_string_or_json(get_cpus())),
+
+        // @desc EWW_NET - Bytes per second up/down, and the total bytes since boot, on all interfaces
+        // @prop { <name>: { NET_UP, NET_DOWN, NET_UP_TOTAL, NET_DOWN_TOTAL } }
+        "EWW_NET" => |_| Ok(DynVal::from(net()?)),
+
+        // @desc EWW_LOADAVG - System load averages and the number of currently running and total processes (Linux only, empty elsewhere)
+        // @prop { 1m, 5m, 15m, running, total }
+        "EWW_LOADAVG" => |_| Ok(DynVal::from(get_load_avg()?)),
+
+        // @desc EWW_UPTIME - Time since the system booted, both in total seconds and split up into days, hours and minutes (Linux only, empty elsewhere)
+        // @prop { seconds, days, hours, minutes }
+        "EWW_UPTIME" => |_| Ok(DynVal::from(get_uptime()?)),
+
+        // @desc EWW_PROCESSES - The processes using the most CPU and memory, five of each unless configured otherwise with `(defmagic EWW_PROCESSES :count 10)`. `cpu` is in percent of a single core, `mem` in kB. The CPU usage of a process is only known from the second time it is seen, so until then `cpu` is left out, and the process isn't listed in `by_cpu`.\nExample: `{EWW_PROCESSES.by_cpu[0].name}`
+        // @prop { by_cpu: [{ pid, name, cpu, mem }], by_mem: [{ pid, name, cpu, mem }] }
+        "EWW_PROCESSES" => |options| Ok(DynVal::from(get_top_processes(options.count.unwrap_or(DEFAULT_TOP_PROCESSES_COUNT)))),
+
+        // @desc EWW_BACKLIGHT - Brightness of all backlight devices in `/sys/class/backlight` (Linux only, empty elsewhere)
+        // @prop { <name>: { brightness, max_brightness, percent } }
+        "EWW_BACKLIGHT" => |_| Ok(DynVal::from(get_backlight()?)),
+
+        // @desc EWW_WIFI - Link quality, signal level and noise level in dBm of the wireless interfaces, as reported by `/proc/net/wireless` (Linux only, empty elsewhere)
+        // @prop { <interface>: { link, level, noise } }
+        "EWW_WIFI" => |_| Ok(DynVal::from(get_wifi()?)),
+
+        // @desc EWW_PRESSURE - Pressure stall information: the percentage of time tasks were stalled on a resource over the last 10, 60 and 300 seconds, and the total stall time in microseconds (Linux only, empty elsewhere)
+        // @prop { cpu: { some: { avg10, avg60, avg300, total }, full }, memory: { some, full }, io: { some, full } }
+        "EWW_PRESSURE" => |_| Ok(DynVal::from(get_pressure()?)),
+    }
+}
+
+/// The magic variables that contain an object with one entry per device, mount point or interface.
+/// Only these can be filtered with `:include` and `:exclude` in `defmagic`.
+const KEYED_MAGIC_VARS: &[&str] = &["EWW_TEMPS", "EWW_DISK", "EWW_BATTERY", "EWW_NET", "EWW_BACKLIGHT", "EWW_WIFI"];
+
+/// The magic variables that list the top entries of something, whose number can be configured with `:count` in `defmagic`.
+const COUNTED_MAGIC_VARS: &[&str] = &["EWW_PROCESSES"];
+
+/// Get the inbuilt vars, with the intervals given in `defmagic` applied to them.
+/// Their filters and other options are applied by the script var handler, see [`crate::config::var_source::create_poll_source`].
+pub fn get_configured_inbuilt_vars(
+    magic_vars: &HashMap<VarName, MagicVarDefinition>,
+) -> anyhow::Result<HashMap<VarName, ScriptVarDefinition>> {
+    let mut vars = get_inbuilt_vars();
+    for (name, magic_var) in magic_vars {
+        match vars.get_mut(name) {
+            Some(ScriptVarDefinition::Poll(var)) => {
+                if magic_var.filter.is_some() && !KEYED_MAGIC_VARS.contains(&name.0.as_str()) {
+                    return Err(anyhow::anyhow!(DiagError::new(gen_diagnostic! {
+                        msg = format!("`{}` can't be filtered with `:include` or `:exclude`", name),
+                        label = magic_var.span => "Filtered here",
+                        note = format!("Only these magic variables contain one entry per device: {}", KEYED_MAGIC_VARS.join(", ")),
+                    })));
+                }
+                if magic_var.options.count.is_some() && !COUNTED_MAGIC_VARS.contains(&name.0.as_str()) {
+                    return Err(anyhow::anyhow!(DiagError::new(gen_diagnostic! {
+                        msg = format!("`{}` doesn't accept `:count`", name),
+                        label = magic_var.span => "Configured here",
+                        note = format!("Only these magic variables list a number of entries: {}", COUNTED_MAGIC_VARS.join(", ")),
+                    })));
+                }
+                var.interval = magic_var.interval.unwrap_or(var.interval);
+            }
+            _ => {
+                return Err(anyhow::anyhow!(DiagError::new(gen_diagnostic! {
+                    msg = format!("Unknown magic variable `{}`", name),
+                    label = magic_var.name_span => "Configured here",
+                    note = format!("Available magic variables: {}", get_inbuilt_vars().keys().sorted().join(", ")),
+                })))
+            }
+        }
+    }
+    Ok(vars)
+}
 
-        // @desc EWW_NET - Bytes up/down on all interfaces
-        // @prop { <name>: { up, down } }
-        "EWW_NET" => || Ok(DynVal::from(net())),
+/// Register the expression functions that give direct access to system information.
+pub fn register_inbuilt_functions() {
+    let result: Result<(), simplexpr::functions::SignatureParseError> = try {
+        register_function(
+            "disk(mount_point: string) -> object",
+            "Information on the partition mounted at the given path: { name, total, free, used, used_perc, read_total, \
+             write_total }",
+            |args| {
+                let mount_point = args[0].value()?.as_string()?;
+                // the read and write rates are left to EWW_DISK, as computing them here would reset them
+                let disks = DynVal::from_string_or_json(get_disks_without_rates()).as_object()?;
+                Ok(DynVal::from(disks.get(&mount_point).cloned().unwrap_or(Value::Null)))
+            },
+        )?;
+        register_function(
+            "ram() -> object",
+            "Information on ram and swap usage in kB: { total_mem, free_mem, total_swap, free_swap, available_mem, used_mem, \
+             used_mem_perc }",
+            |_| Ok(DynVal::from_string_or_json(get_ram())),
+        )?;
+    };
+    if let Err(err) = result {
+        log::error!("Failed to register inbuilt expression functions: {}", err);
     }
 }
@@ -1,7 +1,9 @@
 pub mod eww_config;
 pub mod inbuilt;
+pub mod record_splitter;
 pub mod script_var;
 pub mod system_stats;
+pub mod var_source;
 pub mod window_definition;
 pub use eww_config::*;
 pub use script_var::*;
@@ -0,0 +1,160 @@
+use anyhow::*;
+use simplexpr::dynval::DynVal;
+use yuck::config::script_var_definition::ListenFraming;
+
+/// Splits the output of a `deflisten` script into separate values, as configured by its [`ListenFraming`].
+/// Output can be given in arbitrary chunks, a value is only produced once it is complete.
+pub struct RecordSplitter {
+    framing: ListenFraming,
+    regex: Option<regex::bytes::Regex>,
+    buffer: Vec<u8>,
+}
+
+impl RecordSplitter {
+    pub fn new(framing: &ListenFraming) -> Result<Self> {
+        let regex = match framing {
+            ListenFraming::Regex(regex) => Some(regex::bytes::Regex::new(regex)?),
+            _ => None,
+        };
+        Ok(RecordSplitter { framing: framing.clone(), regex, buffer: Vec::new() })
+    }
+
+    /// Add newly read output, returning all the values that have been completed by it.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Result<DynVal>> {
+        self.buffer.extend_from_slice(data);
+        self.take_records(false)
+    }
+
+    /// Return the values that remain in the buffer once the script has exited.
+    pub fn finish(&mut self) -> Vec<Result<DynVal>> {
+        self.take_records(true)
+    }
+
+    fn take_records(&mut self, at_end: bool) -> Vec<Result<DynVal>> {
+        match self.framing {
+            ListenFraming::Line => self.take_separated(b'\n', at_end),
+            ListenFraming::Nul => self.take_separated(b'\0', at_end),
+            ListenFraming::Json => self.take_json(at_end),
+            ListenFraming::Regex(_) => self.take_regex_matches(at_end),
+        }
+    }
+
+    fn take_separated(&mut self, separator: u8, at_end: bool) -> Vec<Result<DynVal>> {
+        let mut records = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|x| *x == separator) {
+            let record = self.buffer.drain(..=end).collect::<Vec<_>>();
+            records.push(Ok(record_to_value(&record[..end])));
+        }
+        if at_end && !self.buffer.iter().all(u8::is_ascii_whitespace) {
+            records.push(Ok(record_to_value(&std::mem::take(&mut self.buffer))));
+        }
+        records
+    }
+
+    fn take_json(&mut self, at_end: bool) -> Vec<Result<DynVal>> {
+        let mut records = Vec::new();
+        loop {
+            let mut stream = serde_json::Deserializer::from_slice(&self.buffer).into_iter::<serde_json::Value>();
+            let (consumed, done) = match stream.next() {
+                // A number at the very end of the buffer might still be continued by the next chunk of output
+                Some(Ok(serde_json::Value::Number(_))) if !at_end && stream.byte_offset() == self.buffer.len() => (0, true),
+                Some(Ok(value)) => {
+                    records.push(Ok(DynVal::from(value)));
+                    (stream.byte_offset(), false)
+                }
+                Some(Err(err)) if err.is_eof() && !at_end => (0, true),
+                Some(Err(err)) => {
+                    records.push(Err(anyhow!("Failed to parse output as json: {}", err)));
+                    // skip the rest of the invalid line, as there is no way to know where the next value starts otherwise
+                    match self.buffer.iter().position(|x| *x == b'\n') {
+                        Some(newline) => (newline + 1, false),
+                        None => (self.buffer.len(), true),
+                    }
+                }
+                None => (self.buffer.len(), true),
+            };
+            self.buffer.drain(..consumed);
+            if done {
+                break;
+            }
+        }
+        records
+    }
+
+    fn take_regex_matches(&mut self, at_end: bool) -> Vec<Result<DynVal>> {
+        let regex = self.regex.as_ref().expect("regex framing without a regex");
+        let mut records = Vec::new();
+        let mut consumed = 0;
+        for captures in regex.captures_iter(&self.buffer) {
+            let whole_match = captures.get(0).unwrap();
+            // A match that reaches the end of the buffer might still grow with the next chunk of output
+            if whole_match.end() == self.buffer.len() && !at_end {
+                break;
+            }
+            let record = captures.get(1).unwrap_or(whole_match);
+            records.push(Ok(record_to_value(record.as_bytes())));
+            consumed = whole_match.end();
+        }
+        if at_end {
+            consumed = self.buffer.len();
+        }
+        self.buffer.drain(..consumed);
+        records
+    }
+}
+
+fn record_to_value(record: &[u8]) -> DynVal {
+    let record = String::from_utf8_lossy(record);
+    DynVal::from_string_or_json(record.trim_end_matches(&['\n', '\r'][..]).to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn split(framing: ListenFraming, chunks: &[&str]) -> Vec<DynVal> {
+        let mut splitter = RecordSplitter::new(&framing).unwrap();
+        let mut values = Vec::new();
+        for chunk in chunks {
+            values.extend(splitter.push(chunk.as_bytes()));
+        }
+        values.extend(splitter.finish());
+        values.into_iter().map(|value| value.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_split_records() {
+        assert_eq!(
+            split(ListenFraming::Line, &["a\nb", "c\r\n", "d"]),
+            vec![DynVal::from("a"), DynVal::from("bc"), DynVal::from("d")]
+        );
+        assert_eq!(
+            split(ListenFraming::Nul, &["multiple\nlines\0b", "\0"]),
+            vec![DynVal::from("multiple\nlines"), DynVal::from("b")]
+        );
+        assert_eq!(
+            split(ListenFraming::Json, &["{\"a\":\n  1}\n1", "2 \"x\"", "[true]"]),
+            vec![
+                DynVal::from(serde_json::json!({"a": 1})),
+                DynVal::from(12),
+                DynVal::from("x"),
+                DynVal::from(serde_json::json!([true]))
+            ]
+        );
+        assert_eq!(
+            split(ListenFraming::Regex(r"v=(\d+)".to_string()), &["v=1 v=2", "3 v=4"]),
+            vec![DynVal::from(1), DynVal::from(23), DynVal::from(4)]
+        );
+    }
+
+    #[test]
+    fn test_invalid_json_is_skipped() {
+        let mut splitter = RecordSplitter::new(&ListenFraming::Json).unwrap();
+        let values = splitter.push(b"{nope}\n{\"a\": 1}\n");
+        assert!(values[0].is_err());
+        assert_eq!(
+            values[1..].iter().map(|x| x.as_ref().unwrap().clone()).collect::<Vec<_>>(),
+            vec![DynVal::from(serde_json::json!({"a": 1}))]
+        );
+    }
+}
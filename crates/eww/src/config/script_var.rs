@@ -1,11 +1,23 @@
-use std::process::Command;
+use std::{
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, SystemTime},
+};
 
 use anyhow::*;
 use codespan_reporting::diagnostic::Severity;
 use eww_shared_util::{Span, VarName};
+use nix::{
+    sys::signal,
+    unistd::{setpgid, Pid},
+};
 use simplexpr::dynval::DynVal;
 use yuck::{
-    config::script_var_definition::{ScriptVarDefinition, VarSource},
+    config::{
+        magic_var_definition::MagicVarDefinition,
+        script_var_definition::{PollScriptVar, ScriptEnvironment, ScriptVarDefinition, VarSource},
+    },
     gen_diagnostic,
 };
 
@@ -20,32 +32,167 @@ pub fn create_script_var_failed_warn(span: Span, var_name: &VarName, error_outpu
     })
 }
 
-pub fn initial_value(var: &ScriptVarDefinition) -> Result<DynVal> {
+/// Compute the value a variable starts out with.
+/// For magic variables, `magic_var` is their configuration from `defmagic`, whose options are passed to their function and
+/// whose filter is applied to the value.
+pub fn initial_value(var: &ScriptVarDefinition, magic_var: Option<&MagicVarDefinition>) -> Result<DynVal> {
     match var {
         ScriptVarDefinition::Poll(x) => match &x.initial_value {
             Some(value) => Ok(value.clone()),
             None => match &x.command {
-                VarSource::Function(f) => f()
+                VarSource::Function(f) => f(magic_var.map(|magic_var| magic_var.options).unwrap_or_default())
+                    .map(|value| match magic_var.and_then(|magic_var| magic_var.filter.as_ref()) {
+                        Some(filter) => filter.apply(value),
+                        None => value,
+                    })
                     .map_err(|err| anyhow!(err))
                     .with_context(|| format!("Failed to compute initial value for {}", &var.name())),
-                VarSource::Shell(span, command) => {
-                    run_command(command).map_err(|e| anyhow!(create_script_var_failed_warn(*span, var.name(), &e.to_string())))
-                }
+                VarSource::Shell(span, command) => run_command(command, &x.script_env, x.timeout)
+                    .map_err(|e| anyhow!(create_script_var_failed_warn(*span, var.name(), &e.to_string()))),
+                // native sources may need to wait for IO, so they are only read once the variable starts polling
+                VarSource::Native(..) => Ok(DynVal::from_string(String::new())),
             },
         },
 
         ScriptVarDefinition::Listen(var) => Ok(var.initial_value.clone()),
+        ScriptVarDefinition::File(var) => Ok(var.initial_value.clone()),
+        ScriptVarDefinition::Socket(var) => Ok(var.initial_value.clone()),
+    }
+}
+
+/// Whether the variable is one of the magic variables, whose values are computed by eww itself.
+pub fn is_inbuilt(var: &ScriptVarDefinition) -> bool {
+    matches!(var, ScriptVarDefinition::Poll(PollScriptVar { command: VarSource::Function(_), .. }))
+}
+
+/// The shell scripts are run with, unless they configure a different one with `:shell`.
+pub const DEFAULT_SHELL: &str = "/bin/sh";
+
+/// Create the command that runs a script in the shell, environment and working directory configured for it.
+pub fn script_command(script: &str, script_env: &ScriptEnvironment) -> Command {
+    let mut command = Command::new(script_env.shell.as_deref().unwrap_or(DEFAULT_SHELL));
+    command.arg("-c").arg(script).envs(&script_env.env);
+    if let Some(cwd) = &script_env.cwd {
+        command.current_dir(cwd);
     }
+    command
 }
 
-/// Run a command and get the output
-pub fn run_command(cmd: &str) -> Result<DynVal> {
+/// Run a command and get the output.
+/// If the command doesn't exit within the given timeout, its whole process group is killed.
+pub fn run_command(cmd: &str, script_env: &ScriptEnvironment, timeout: Option<Duration>) -> Result<DynVal> {
     log::debug!("Running command: {}", cmd);
-    let command = Command::new("/bin/sh").arg("-c").arg(cmd).output()?;
+    let child = unsafe {
+        script_command(cmd, script_env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .pre_exec(|| {
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                Ok(())
+            })
+            .spawn()?
+    };
+
+    let (exited_send, exited_recv) = mpsc::channel::<()>();
+    let watchdog = timeout.map(|timeout| {
+        let process_group = Pid::from_raw(child.id() as i32);
+        std::thread::spawn(move || {
+            let timed_out = exited_recv.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout);
+            if timed_out {
+                let _ = signal::killpg(process_group, signal::SIGKILL);
+            }
+            timed_out
+        })
+    });
+    let command = child.wait_with_output()?;
+    drop(exited_send);
+    if let (Some(watchdog), Some(timeout)) = (watchdog, timeout) {
+        if watchdog.join().unwrap_or(false) {
+            bail!("Killed after running for longer than the timeout of {:?}", timeout);
+        }
+    }
+
     if !command.status.success() {
-        bail!("Failed with output:\n{}", String::from_utf8(command.stderr)?);
+        return Err(CommandFailed { exit_code: command.status.code(), stderr: String::from_utf8(command.stderr)? }.into());
     }
     let output = String::from_utf8(command.stdout)?;
     let output = output.trim_matches('\n');
-    Ok(DynVal::from(output))
+    Ok(DynVal::from_string_or_json(output.to_string()))
+}
+
+/// The error returned by [`run_command`] when the command exited unsuccessfully.
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed with output:\n{}", stderr)]
+pub struct CommandFailed {
+    pub exit_code: Option<i32>,
+    pub stderr: String,
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// The health of a script var, as exposed to expressions through `meta(var)`.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptVarMeta {
+    /// Unix timestamp of the last successful update
+    pub updated: Option<u64>,
+    pub error: Option<String>,
+    pub exit_code: Option<i32>,
+    /// Whether the value is not being updated at the moment, as the last poll of a `defpoll` failed or timed out, the script of a
+    /// `deflisten` has exited or a `defsocket` lost its connection
+    pub stale: bool,
+}
+
+impl ScriptVarMeta {
+    pub fn update_succeeded(&mut self) {
+        self.updated = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok().map(|x| x.as_secs());
+        self.error = None;
+        self.stale = false;
+    }
+
+    pub fn update_failed(&mut self, err: &anyhow::Error) {
+        match err.downcast_ref::<CommandFailed>() {
+            Some(failed) => {
+                self.exit_code = failed.exit_code;
+                self.error = Some(failed.stderr.trim_end().to_string());
+            }
+            None => {
+                self.exit_code = None;
+                self.error = Some(err.to_string());
+            }
+        }
+    }
+
+    /// Like [`Self::update_failed`], for a `defpoll` that failed or timed out, whose value is thus outdated until the next
+    /// successful poll.
+    pub fn poll_failed(&mut self, err: &anyhow::Error) {
+        self.update_failed(err);
+        self.stale = true;
+    }
+
+    pub fn to_dynval(&self) -> DynVal {
+        DynVal::from(serde_json::json!({
+            "updated": self.updated,
+            "error": self.error.clone().unwrap_or_default(),
+            "exit_code": self.exit_code,
+            "stale": self.stale,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_failed_polls_are_stale() {
+        let mut meta = ScriptVarMeta::default();
+        meta.poll_failed(&anyhow!("Timed out after 1s"));
+        assert!(meta.stale);
+        assert_eq!(meta.error.as_deref(), Some("Timed out after 1s"));
+
+        meta.update_succeeded();
+        assert!(!meta.stale);
+        assert_eq!(meta.error, None);
+    }
 }
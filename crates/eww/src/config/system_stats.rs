@@ -2,35 +2,53 @@ use crate::util::IterAverage;
 use anyhow::*;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
-use std::{fs::read_to_string, sync::Mutex};
-use sysinfo::{ComponentExt, DiskExt, NetworkExt, NetworksExt, ProcessorExt, System, SystemExt};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::read_to_string,
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+use sysinfo::{ComponentExt, DiskExt, NetworkExt, NetworksExt, Pid, ProcessExt, ProcessorExt, System, SystemExt};
 
 static SYSTEM: Lazy<Mutex<System>> = Lazy::new(|| Mutex::new(System::new()));
 
 pub fn get_disks() -> String {
+    disks_with_io(disk_io_rates())
+}
+
+/// Like [`get_disks`], but without the read and write rates, so that the rates of `EWW_DISK` aren't affected by calling it.
+pub fn get_disks_without_rates() -> String {
+    disks_with_io(disk_io_totals())
+}
+
+/// The space of every mounted partition, together with the given io statistics of its device.
+fn disks_with_io(io: HashMap<String, Value>) -> String {
     let mut c = SYSTEM.lock().unwrap();
     c.refresh_disks_list();
 
-    format!(
-        "{{ {} }}",
-        c.get_disks()
-            .iter()
-            .map(|c| {
-                let total_space = c.get_total_space();
-                let available_space = c.get_available_space();
-                let used_space = total_space - available_space;
-                format!(
-                    r#""{}": {{"name": {:?}, "total": {}, "free": {}, "used": {}, "used_perc": {}}}"#,
-                    c.get_mount_point().display(),
-                    c.get_name(),
-                    total_space,
-                    available_space,
-                    used_space,
-                    (used_space as f32 / total_space as f32) * 100f32,
-                )
-            })
-            .join(",")
-    )
+    let mut disks = Map::new();
+    for disk in c.get_disks() {
+        let total_space = disk.get_total_space();
+        let available_space = disk.get_available_space();
+        let used_space = total_space - available_space;
+        let mut entry = json!({
+            "name": disk.get_name().to_string_lossy(),
+            "total": total_space,
+            "free": available_space,
+            "used": used_space,
+            "used_perc": (used_space as f32 / total_space as f32) * 100f32,
+        });
+        // /proc/diskstats lists devices by their kernel name, so symlinks like /dev/mapper/root need to be resolved first
+        let device =
+            std::fs::canonicalize(disk.get_name()).ok().and_then(|x| x.file_name().map(|x| x.to_string_lossy().to_string()));
+        if let Some(device_io) = device.and_then(|device| io.get(&device)) {
+            entry.as_object_mut().unwrap().extend(device_io.as_object().unwrap().clone());
+        }
+        disks.insert(disk.get_mount_point().display().to_string(), entry);
+    }
+    Value::Object(disks).to_string()
 }
 
 pub fn get_ram() -> String {
@@ -161,15 +179,394 @@ pub fn get_battery_capacity() -> Result<u8> {
     anyhow!("Eww doesn't support your OS for getting the battery capacity")
 }
 
-pub fn net() -> String {
+pub fn net() -> Result<Value> {
+    let interfaces = match read_optional("/proc/net/dev")? {
+        Some(content) => parse_net_dev(&content)?,
+        None => net_totals_from_sysinfo(),
+    };
+    let counters = interfaces
+        .iter()
+        .flat_map(|(name, (received, transmitted))| {
+            vec![(format!("{}/received", name), *received), (format!("{}/transmitted", name), *transmitted)]
+        })
+        .collect();
+    let rates = NET_RATES.lock().unwrap().update(Instant::now(), counters);
+    Ok(Value::Object(
+        interfaces
+            .into_iter()
+            .map(|(name, (received, transmitted))| {
+                let entry = json!({
+                    "NET_UP": rates[&format!("{}/transmitted", name)],
+                    "NET_DOWN": rates[&format!("{}/received", name)],
+                    "NET_UP_TOTAL": transmitted,
+                    "NET_DOWN_TOTAL": received,
+                });
+                (name, entry)
+            })
+            .collect(),
+    ))
+}
+
+/// The total bytes received and transmitted by every interface, as reported by sysinfo.
+/// This is used on systems without `/proc/net/dev`.
+fn net_totals_from_sysinfo() -> Vec<(String, (u64, u64))> {
     let mut c = SYSTEM.lock().unwrap();
     c.refresh_networks_list();
-    let interfaces = format!(
-        "{{ {} }}",
-        &c.get_networks()
+    c.refresh_networks();
+    c.get_networks()
+        .iter()
+        .map(|(name, data)| (name.to_string(), (data.get_total_received(), data.get_total_transmitted())))
+        .collect()
+}
+
+/// Parse the content of `/proc/net/dev` into the total bytes received and transmitted by every interface.
+/// After two header lines, it contains one line per interface, like `  eth0: 1234 12 0 0 0 0 0 0 5678 34 0 0 0 0 0 0`
+fn parse_net_dev(content: &str) -> Result<Vec<(String, (u64, u64))>> {
+    content
+        .lines()
+        .skip(2)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, rest)| {
+            let fields = rest.split_whitespace().collect_vec();
+            let field = |i: usize| -> Result<u64> {
+                Ok(fields.get(i).with_context(|| format!("Missing fields for {}", name.trim()))?.parse()?)
+            };
+            Ok((name.trim().to_string(), (field(0)?, field(8)?)))
+        })
+        .collect()
+}
+
+/// The number of bytes read and written per second by every block device, together with the totals since boot.
+fn disk_io_rates() -> HashMap<String, Value> {
+    let devices = read_diskstats();
+    let counters = devices
+        .iter()
+        .flat_map(|(name, (read, written))| vec![(format!("{}/read", name), *read), (format!("{}/written", name), *written)])
+        .collect();
+    let rates = DISK_RATES.lock().unwrap().update(Instant::now(), counters);
+    devices
+        .into_iter()
+        .map(|(name, (read, written))| {
+            let entry = json!({
+                "read": rates[&format!("{}/read", name)],
+                "write": rates[&format!("{}/written", name)],
+                "read_total": read,
+                "write_total": written,
+            });
+            (name, entry)
+        })
+        .collect()
+}
+
+/// The bytes read and written by every block device since boot.
+fn disk_io_totals() -> HashMap<String, Value> {
+    read_diskstats()
+        .into_iter()
+        .map(|(name, (read, written))| (name, json!({ "read_total": read, "write_total": written })))
+        .collect()
+}
+
+fn read_diskstats() -> Vec<(String, (u64, u64))> {
+    match read_optional("/proc/diskstats").and_then(|x| x.map(|x| parse_diskstats(&x)).transpose()) {
+        Ok(devices) => devices.unwrap_or_default(),
+        Err(err) => {
+            log::warn!("Failed to read disk io statistics: {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// Parse the content of `/proc/diskstats` into the total bytes read and written by every device.
+/// Each line looks like `   8       0 sda 1234 0 5678 ...`, where the third and seventh number are the sectors read and written.
+fn parse_diskstats(content: &str) -> Result<Vec<(String, (u64, u64))>> {
+    // The kernel always counts sectors of 512 bytes here, regardless of the actual sector size of the device
+    const SECTOR_SIZE: u64 = 512;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields = line.split_whitespace().collect_vec();
+            let name = fields.get(2).context("Missing device name")?;
+            let field =
+                |i: usize| -> Result<u64> { Ok(fields.get(i).with_context(|| format!("Missing fields for {}", name))?.parse()?) };
+            Ok((name.to_string(), (field(5)? * SECTOR_SIZE, field(9)? * SECTOR_SIZE)))
+        })
+        .collect()
+}
+
+static NET_RATES: Lazy<Mutex<RateTracker>> = Lazy::new(|| Mutex::new(RateTracker::default()));
+static DISK_RATES: Lazy<Mutex<RateTracker>> = Lazy::new(|| Mutex::new(RateTracker::default()));
+
+/// Turns counters that only ever grow, such as the bytes received by an interface, into rates per second.
+/// The rates are based on the time that actually passed since the last update, so they don't depend on how often it is called.
+#[derive(Default)]
+struct RateTracker {
+    last_update: Option<(Instant, HashMap<String, u64>)>,
+}
+
+impl RateTracker {
+    /// Store the current values of the counters, and get how much each of them grew per second since the last update.
+    /// Counters that weren't known before, or that were reset, have a rate of 0.
+    fn update(&mut self, now: Instant, counters: HashMap<String, u64>) -> HashMap<String, f64> {
+        let rates = counters
             .iter()
-            .map(|a| format!(r#""{}": {{ "NET_UP": {}, "NET_DOWN": {} }}"#, a.0, a.1.get_transmitted(), a.1.get_received()))
-            .join(","),
-    );
-    interfaces
+            .map(|(name, value)| {
+                let rate = match &self.last_update {
+                    Some((time, last_counters)) if now > *time => match last_counters.get(name) {
+                        Some(last_value) => value.saturating_sub(*last_value) as f64 / (now - *time).as_secs_f64(),
+                        None => 0.0,
+                    },
+                    _ => 0.0,
+                };
+                (name.clone(), rate)
+            })
+            .collect();
+        self.last_update = Some((now, counters));
+        rates
+    }
+}
+
+/// Read a file that may not exist on every system, such as the files in `/proc` on other operating systems.
+fn read_optional(path: impl AsRef<Path>) -> Result<Option<String>> {
+    match read_to_string(path.as_ref()) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Couldn't read {}", path.as_ref().display())),
+    }
+}
+
+pub fn get_load_avg() -> Result<Value> {
+    read_optional("/proc/loadavg")?.map_or(Ok(json!({})), |x| parse_load_avg(&x))
+}
+
+/// Parse the content of `/proc/loadavg`, which looks like `0.52 0.58 0.59 1/1234 5678`
+fn parse_load_avg(content: &str) -> Result<Value> {
+    let fields = content.split_whitespace().collect_vec();
+    let load = |i: usize| -> Result<f64> { Ok(fields.get(i).context("Missing load average")?.parse()?) };
+    let (running, total) = fields.get(3).and_then(|x| x.split_once('/')).context("Missing the number of processes")?;
+    Ok(json!({
+        "1m": load(0)?,
+        "5m": load(1)?,
+        "15m": load(2)?,
+        "running": running.parse::<u64>()?,
+        "total": total.parse::<u64>()?,
+    }))
+}
+
+pub fn get_uptime() -> Result<Value> {
+    read_optional("/proc/uptime")?.map_or(Ok(json!({})), |x| parse_uptime(&x))
+}
+
+/// Parse the content of `/proc/uptime`, which contains the uptime and the time spent idle in seconds
+fn parse_uptime(content: &str) -> Result<Value> {
+    let seconds = content.split_whitespace().next().context("Missing the uptime")?.parse::<f64>()? as u64;
+    Ok(json!({
+        "seconds": seconds,
+        "days": seconds / 86400,
+        "hours": seconds % 86400 / 3600,
+        "minutes": seconds % 3600 / 60,
+    }))
+}
+
+/// How many processes `EWW_PROCESSES` lists by default, unless configured otherwise with `:count`
+pub const DEFAULT_TOP_PROCESSES_COUNT: usize = 5;
+
+/// The processes that existed when the processes were refreshed the previous time.
+/// The CPU usage of a process is computed from the time between two refreshes, so it is only known for these.
+static SAMPLED_PROCESSES: Lazy<Mutex<HashSet<Pid>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// The `count` processes using the most CPU and memory.
+/// Processes that weren't sampled before are left out of `by_cpu`, and don't report their CPU usage yet.
+pub fn get_top_processes(count: usize) -> Value {
+    let mut c = SYSTEM.lock().unwrap();
+    c.refresh_processes();
+    let mut sampled = SAMPLED_PROCESSES.lock().unwrap();
+    let mut processes = c
+        .get_processes()
+        .values()
+        .map(|p| {
+            let cpu = sampled.contains(&p.pid()).then(|| p.cpu_usage());
+            let mut info = json!({ "pid": p.pid(), "name": p.name(), "mem": p.memory() });
+            if let Some(cpu) = cpu {
+                info["cpu"] = json!(cpu);
+            }
+            (cpu, p.memory(), info)
+        })
+        .collect_vec();
+    *sampled = c.get_processes().keys().copied().collect();
+
+    let mut by_cpu = processes.iter().filter_map(|(cpu, _, info)| cpu.map(|cpu| (cpu, info))).collect_vec();
+    by_cpu.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    let by_cpu = by_cpu.into_iter().take(count).map(|(_, info)| info.clone()).collect_vec();
+    processes.sort_by(|a, b| b.1.cmp(&a.1));
+    let by_mem = processes.into_iter().take(count).map(|(_, _, info)| info).collect_vec();
+    json!({ "by_cpu": by_cpu, "by_mem": by_mem })
+}
+
+pub fn get_backlight() -> Result<Value> {
+    let mut backlights = Map::new();
+    let dir = Path::new("/sys/class/backlight");
+    if !dir.exists() {
+        return Ok(Value::Object(backlights));
+    }
+    for entry in dir.read_dir().context("Couldn't read /sys/class/backlight directory")? {
+        let path = entry?.path();
+        if let (Ok(brightness), Ok(max_brightness)) =
+            (read_to_string(path.join("brightness")), read_to_string(path.join("max_brightness")))
+        {
+            let brightness = brightness.trim().parse::<u64>()?;
+            let max_brightness = max_brightness.trim().parse::<u64>()?;
+            backlights.insert(
+                path.file_name().context("couldn't convert file name to rust string")?.to_string_lossy().to_string(),
+                json!({
+                    "brightness": brightness,
+                    "max_brightness": max_brightness,
+                    "percent": if max_brightness == 0 { 0.0 } else { brightness as f64 / max_brightness as f64 * 100.0 },
+                }),
+            );
+        }
+    }
+    Ok(Value::Object(backlights))
+}
+
+pub fn get_wifi() -> Result<Value> {
+    read_optional("/proc/net/wireless")?.map_or(Ok(json!({})), |x| parse_wireless(&x))
+}
+
+/// Parse the content of `/proc/net/wireless`.
+/// After two header lines, it contains one line per interface, like `wlan0: 0000   54.  -56.  -256        0 ...`
+fn parse_wireless(content: &str) -> Result<Value> {
+    let mut interfaces = Map::new();
+    for line in content.lines().skip(2) {
+        let (name, rest) = match line.split_once(':') {
+            Some(x) => x,
+            None => continue,
+        };
+        let fields = rest.split_whitespace().collect_vec();
+        let field = |i: usize| -> Result<f64> {
+            Ok(fields.get(i).with_context(|| format!("Missing fields for {}", name.trim()))?.trim_end_matches('.').parse()?)
+        };
+        interfaces.insert(name.trim().to_string(), json!({ "link": field(1)?, "level": field(2)?, "noise": field(3)? }));
+    }
+    Ok(Value::Object(interfaces))
+}
+
+pub fn get_pressure() -> Result<Value> {
+    let mut pressure = Map::new();
+    for resource in &["cpu", "memory", "io"] {
+        // pressure stall information is only available on kernels built with CONFIG_PSI
+        if let Some(content) = read_optional(Path::new("/proc/pressure").join(resource))? {
+            pressure.insert(resource.to_string(), parse_pressure(&content)?);
+        }
+    }
+    Ok(Value::Object(pressure))
+}
+
+/// Parse a pressure stall information file, which looks like
+/// `some avg10=0.00 avg60=0.00 avg300=0.00 total=0`, optionally followed by a line starting with `full`
+fn parse_pressure(content: &str) -> Result<Value> {
+    let mut kinds = Map::new();
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = match fields.next() {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let values = fields
+            .map(|field| {
+                let (key, value) = field.split_once('=').with_context(|| format!("Invalid field {}", field))?;
+                Ok((key.to_string(), json!(value.parse::<f64>()?)))
+            })
+            .collect::<Result<Map<_, _>>>()?;
+        kinds.insert(kind.to_string(), Value::Object(values));
+    }
+    Ok(Value::Object(kinds))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_load_avg() {
+        assert_eq!(
+            parse_load_avg("0.52 0.58 0.59 1/1234 5678\n").unwrap(),
+            json!({ "1m": 0.52, "5m": 0.58, "15m": 0.59, "running": 1, "total": 1234 })
+        );
+        assert!(parse_load_avg("0.52 0.58").is_err());
+    }
+
+    #[test]
+    fn test_parse_uptime() {
+        assert_eq!(
+            parse_uptime("93784.57 351234.10\n").unwrap(),
+            json!({ "seconds": 93784, "days": 1, "hours": 2, "minutes": 3 })
+        );
+    }
+
+    #[test]
+    fn test_parse_net_dev() {
+        let content = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 124151581   22250    0    0    0     0          0         0 124151581   22250    0    0    0     0       0          0
+ wlan0:    5000      20    0    0    0     0          0         0     3000      10    0    0    0     0       0          0
+";
+        assert_eq!(
+            parse_net_dev(content).unwrap(),
+            vec![("lo".to_string(), (124151581, 124151581)), ("wlan0".to_string(), (5000, 3000))]
+        );
+    }
+
+    #[test]
+    fn test_parse_diskstats() {
+        let content = "   8       0 sda 100 0 2048 10 50 0 4096 20 0 30 30 0 0 0 0
+ 259       1 nvme0n1p1 1 0 8 0 0 0 0 0 0 0 0 0 0 0 0
+";
+        assert_eq!(
+            parse_diskstats(content).unwrap(),
+            vec![("sda".to_string(), (2048 * 512, 4096 * 512)), ("nvme0n1p1".to_string(), (8 * 512, 0))]
+        );
+    }
+
+    #[test]
+    fn test_rate_tracker() {
+        use maplit::hashmap;
+        use std::time::Duration;
+
+        let mut tracker = RateTracker::default();
+        let start = Instant::now();
+        assert_eq!(tracker.update(start, hashmap! { "a".to_string() => 100 }), hashmap! { "a".to_string() => 0.0 });
+        assert_eq!(
+            tracker.update(start + Duration::from_millis(500), hashmap! { "a".to_string() => 600, "b".to_string() => 10 }),
+            hashmap! { "a".to_string() => 1000.0, "b".to_string() => 0.0 }
+        );
+        // counters that went down were reset, e.g. because the interface was recreated
+        assert_eq!(
+            tracker.update(start + Duration::from_secs(1), hashmap! { "a".to_string() => 0 }),
+            hashmap! { "a".to_string() => 0.0 }
+        );
+    }
+
+    #[test]
+    fn test_parse_wireless() {
+        let content = "Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+wlan0: 0000   54.  -56.  -256        0      0      0      0    241        0
+";
+        assert_eq!(parse_wireless(content).unwrap(), json!({ "wlan0": { "link": 54.0, "level": -56.0, "noise": -256.0 } }));
+    }
+
+    #[test]
+    fn test_parse_pressure() {
+        let content = "some avg10=1.50 avg60=0.25 avg300=0.00 total=12345
+full avg10=0.00 avg60=0.00 avg300=0.00 total=0
+";
+        assert_eq!(
+            parse_pressure(content).unwrap(),
+            json!({
+                "some": { "avg10": 1.5, "avg60": 0.25, "avg300": 0.0, "total": 12345.0 },
+                "full": { "avg10": 0.0, "avg60": 0.0, "avg300": 0.0, "total": 0.0 },
+            })
+        );
+    }
 }
@@ -0,0 +1,186 @@
+use std::{io::SeekFrom, os::unix::fs::MetadataExt, path::PathBuf, time::Duration};
+
+use anyhow::*;
+use futures_util::future::{BoxFuture, FutureExt};
+use simplexpr::dynval::DynVal;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use yuck::config::{
+    magic_var_definition::{KeyFilter, MagicVarDefinition, MagicVarOptions},
+    script_var_definition::{NativeVarSource, PollScriptVar, ScriptEnvironment, VarSource},
+};
+
+use super::script_var;
+
+/// Something a `defpoll` variable reads its value from whenever its interval has passed.
+/// To add a new source that can be selected with `:source`, add a variant to [`NativeVarSource`], and implement this for it.
+pub trait PollSource: Send {
+    /// Read the current value. This is called again after every interval, even if the previous call failed.
+    fn read(&mut self) -> BoxFuture<'_, Result<DynVal>>;
+}
+
+/// Create the source that a poll var reads its values from.
+/// For magic variables, `magic_var` is their configuration from `defmagic`, whose options are passed to their function and
+/// whose filter is applied to the values.
+pub fn create_poll_source(var: &PollScriptVar, magic_var: Option<&MagicVarDefinition>) -> Box<dyn PollSource> {
+    let source: Box<dyn PollSource> = match &var.command {
+        VarSource::Shell(_, command) => {
+            Box::new(ShellSource { command: command.clone(), script_env: var.script_env.clone(), timeout: var.timeout })
+        }
+        VarSource::Function(f) => Box::new(FunctionSource(*f, magic_var.map(|magic_var| magic_var.options).unwrap_or_default())),
+        VarSource::Native(_, NativeVarSource::File { path }) => Box::new(FileSource::new(PathBuf::from(path))),
+        VarSource::Native(_, NativeVarSource::UnixSocket { path, request }) => {
+            Box::new(UnixSocketSource { path: PathBuf::from(path), request: request.clone() })
+        }
+        VarSource::Native(_, NativeVarSource::Time) => Box::new(TimeSource),
+    };
+    match magic_var.and_then(|magic_var| magic_var.filter.clone()) {
+        Some(filter) => Box::new(FilteredSource { source, filter }),
+        None => source,
+    }
+}
+
+struct ShellSource {
+    command: String,
+    script_env: ScriptEnvironment,
+    timeout: Option<Duration>,
+}
+
+impl PollSource for ShellSource {
+    fn read(&mut self) -> BoxFuture<'_, Result<DynVal>> {
+        let (command, script_env, timeout) = (self.command.clone(), self.script_env.clone(), self.timeout);
+        async move { tokio::task::spawn_blocking(move || script_var::run_command(&command, &script_env, timeout)).await? }.boxed()
+    }
+}
+
+/// A builtin source, as used by the magic variables
+struct FunctionSource(fn(MagicVarOptions) -> Result<DynVal, Box<dyn std::error::Error + Sync + Send + 'static>>, MagicVarOptions);
+
+impl PollSource for FunctionSource {
+    fn read(&mut self) -> BoxFuture<'_, Result<DynVal>> {
+        let result = (self.0)(self.1).map_err(|e| anyhow!(e));
+        async move { result }.boxed()
+    }
+}
+
+/// Removes the entries that a `defmagic` excludes from the values of another source
+struct FilteredSource {
+    source: Box<dyn PollSource>,
+    filter: KeyFilter,
+}
+
+impl PollSource for FilteredSource {
+    fn read(&mut self) -> BoxFuture<'_, Result<DynVal>> {
+        async move { Ok(self.filter.apply(self.source.read().await?)) }.boxed()
+    }
+}
+
+/// Reads the last line appended to a file since the previous read, like `tail -n 1`, starting out with the last line it
+/// already contains. If nothing was appended, the previous value is kept. A line that isn't complete yet is only read once it
+/// is. If the file was truncated or replaced, i.e. because it was rotated, it is read from the start again.
+struct FileSource {
+    path: PathBuf,
+    /// The inode of the file that was read last, and the position up to which it has been read
+    position: Option<(u64, u64)>,
+    last_value: DynVal,
+}
+
+impl FileSource {
+    fn new(path: PathBuf) -> Self {
+        FileSource { path, position: None, last_value: DynVal::from_string(String::new()) }
+    }
+}
+
+impl PollSource for FileSource {
+    fn read(&mut self) -> BoxFuture<'_, Result<DynVal>> {
+        async move {
+            let mut file =
+                tokio::fs::File::open(&self.path).await.with_context(|| format!("Failed to open {}", self.path.display()))?;
+            let metadata = file.metadata().await?;
+            let mut offset = match self.position {
+                Some((inode, offset)) if inode == metadata.ino() && offset <= metadata.len() => offset,
+                _ => 0,
+            };
+            file.seek(SeekFrom::Start(offset)).await?;
+            let mut appended = Vec::new();
+            file.read_to_end(&mut appended).await.with_context(|| format!("Failed to read {}", self.path.display()))?;
+            if let Some(end) = appended.iter().rposition(|x| *x == b'\n') {
+                offset += end as u64 + 1;
+                let lines = &appended[..end];
+                let last_line = lines.iter().rposition(|x| *x == b'\n').map_or(lines, |start| &lines[start + 1..]);
+                self.last_value = output_to_value(&String::from_utf8_lossy(last_line));
+            }
+            self.position = Some((metadata.ino(), offset));
+            Ok(self.last_value.clone())
+        }
+        .boxed()
+    }
+}
+
+/// Connects to a unix socket, optionally writes a request followed by a newline to it, and reads everything the other side
+/// sends until it closes the connection.
+struct UnixSocketSource {
+    path: PathBuf,
+    request: Option<String>,
+}
+
+impl PollSource for UnixSocketSource {
+    fn read(&mut self) -> BoxFuture<'_, Result<DynVal>> {
+        async move {
+            let mut stream = tokio::net::UnixStream::connect(&self.path)
+                .await
+                .with_context(|| format!("Failed to connect to {}", self.path.display()))?;
+            // The writing half isn't shut down, as some servers take that as the client going away and don't answer at all
+            if let Some(request) = &self.request {
+                stream.write_all(format!("{}\n", request).as_bytes()).await?;
+            }
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await?;
+            Ok(output_to_value(&response))
+        }
+        .boxed()
+    }
+}
+
+/// The current unix timestamp in seconds
+struct TimeSource;
+
+impl PollSource for TimeSource {
+    fn read(&mut self) -> BoxFuture<'_, Result<DynVal>> {
+        let now = std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).map(|x| x.as_secs() as f64);
+        async move { Ok(DynVal::from(now?)) }.boxed()
+    }
+}
+
+/// Turn the output of a source into a value the same way the output of a script is.
+fn output_to_value(output: &str) -> DynVal {
+    DynVal::from_string_or_json(output.trim_matches('\n').to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_source() {
+        let path = std::env::temp_dir().join(format!("eww-test-file-source-{}", std::process::id()));
+        std::fs::write(&path, "first\nsecond\n").unwrap();
+        let mut source = FileSource::new(path.clone());
+        assert_eq!(source.read().await.unwrap(), DynVal::from("second"));
+        assert_eq!(source.read().await.unwrap(), DynVal::from("second"));
+
+        let append = |content: &str| {
+            use std::io::Write;
+            std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(content.as_bytes()).unwrap();
+        };
+        // incomplete lines are only read once they are finished
+        append("thi");
+        assert_eq!(source.read().await.unwrap(), DynVal::from("second"));
+        append("rd\nfourth\n");
+        assert_eq!(source.read().await.unwrap(), DynVal::from("fourth"));
+
+        // a truncated file is read from the start again
+        std::fs::write(&path, "new\n").unwrap();
+        assert_eq!(source.read().await.unwrap(), DynVal::from("new"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
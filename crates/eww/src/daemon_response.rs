@@ -1,35 +1,207 @@
 use anyhow::*;
+use codespan_reporting::files::Files;
+use eww_shared_util::{Span, VarName};
 use itertools::Itertools;
+use simplexpr::dynval::DynVal;
 
 use crate::error_handling_ctx;
 
 /// Response that the app may send as a response to a event.
-/// This is used in `DaemonCommand`s that contain a response sender.
+/// This is used in `DaemonCommand`s that contain a response sender, and sent to the client as described in `docs/src/ipc.md`.
 #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, derive_more::Display)]
+#[serde(tag = "status", content = "content", rename_all = "kebab-case")]
 pub enum DaemonResponse {
     Success(String),
     Failure(String),
+    /// Sent instead of the plain text responses to clients that asked for json output with `--json`
+    Structured(ResponsePayload),
+    /// Describes what the daemon is doing while it works on a command that takes a while, ahead of the actual response
+    Progress(String),
 }
 
 impl DaemonResponse {
     pub fn is_success(&self) -> bool {
-        matches!(self, DaemonResponse::Success(_))
+        match self {
+            DaemonResponse::Success(_) | DaemonResponse::Progress(_) => true,
+            DaemonResponse::Failure(_) => false,
+            DaemonResponse::Structured(payload) => !matches!(payload, ResponsePayload::Errors { .. }),
+        }
     }
 
     pub fn is_failure(&self) -> bool {
         !self.is_success()
     }
+
+    /// Turn any response into a structured one, such that it can be printed as json.
+    pub fn into_payload(self) -> ResponsePayload {
+        match self {
+            DaemonResponse::Success(output) | DaemonResponse::Progress(output) => ResponsePayload::Output { output },
+            DaemonResponse::Failure(message) => {
+                ResponsePayload::Errors { errors: vec![ErrorInfo { message, spans: Vec::new() }] }
+            }
+            DaemonResponse::Structured(payload) => payload,
+        }
+    }
+}
+
+/// How the daemon should respond to a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResponseFormat {
+    /// Text meant to be read by humans
+    Text,
+    /// [`ResponsePayload`]s, printed as json by the client
+    Json,
+}
+
+impl Default for ResponseFormat {
+    fn default() -> Self {
+        ResponseFormat::Text
+    }
+}
+
+/// The structured content of a response, printed by `--json` just as it is sent by the daemon.
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ResponsePayload {
+    /// The output of a command that has no structured output, which is empty for most of them
+    Output {
+        output: String,
+    },
+    Variable {
+        variable: VariableInfo,
+    },
+    Variables {
+        variables: Vec<VariableInfo>,
+    },
+    Windows {
+        windows: Vec<WindowInfo>,
+    },
+    Listeners {
+        listeners: Vec<ListenerInfo>,
+    },
+    Debug {
+        windows: Vec<WindowInfo>,
+        variables: Vec<VariableInfo>,
+        listeners: Vec<ListenerInfo>,
+    },
+    Errors {
+        errors: Vec<ErrorInfo>,
+    },
+}
+
+impl std::fmt::Display for ResponsePayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).map_err(|_| std::fmt::Error)?)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct VariableInfo {
+    pub name: String,
+    /// The type of the value, as used in the expression language, i.e. `number` or `object`
+    #[serde(rename = "type")]
+    pub value_type: String,
+    pub value: serde_json::Value,
+}
+
+impl VariableInfo {
+    pub fn new(name: &VarName, value: &DynVal) -> Self {
+        VariableInfo { name: name.0.clone(), value_type: value.0.value_type().to_string(), value: value.0.to_json() }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WindowInfo {
+    pub name: String,
+    pub open: bool,
+    /// Whether opening the window failed the last time it was attempted
+    pub failed: bool,
+    /// The index of the monitor the window is opened on, or the one configured in its definition
+    pub monitor: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ListenerInfo {
+    pub name: String,
+    pub state: String,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ErrorInfo {
+    pub message: String,
+    /// The places in the configuration that the error refers to
+    pub spans: Vec<SpanInfo>,
+}
+
+impl ErrorInfo {
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        match error_handling_ctx::anyhow_err_to_diagnostic(err) {
+            Some(diag) => {
+                let files = error_handling_ctx::YUCK_FILES.read().unwrap();
+                let location = |file_id, byte_index| {
+                    let location = files.location(file_id, byte_index).ok()?;
+                    Some(Location { line: location.line_number, column: location.column_number })
+                };
+                let spans = diag
+                    .labels
+                    .iter()
+                    .filter(|label| !Span(label.range.start, label.range.end, label.file_id).is_dummy())
+                    .filter_map(|label| {
+                        Some(SpanInfo {
+                            file: files.name(label.file_id).ok()?.to_string(),
+                            start: location(label.file_id, label.range.start)?,
+                            end: location(label.file_id, label.range.end)?,
+                            label: label.message.clone(),
+                        })
+                    })
+                    .collect();
+                ErrorInfo { message: diag.message, spans }
+            }
+            None => ErrorInfo { message: format!("{:#}", err), spans: Vec::new() },
+        }
+    }
+}
+
+/// A range in one of the configuration files.
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SpanInfo {
+    pub file: String,
+    pub start: Location,
+    pub end: Location,
+    pub label: String,
+}
+
+/// A position in a file, with one-based lines and columns.
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
 }
 
 #[derive(Debug)]
-pub struct DaemonResponseSender(tokio::sync::mpsc::UnboundedSender<DaemonResponse>);
+pub struct DaemonResponseSender(tokio::sync::mpsc::UnboundedSender<DaemonResponse>, ResponseFormat);
 
 pub fn create_pair() -> (DaemonResponseSender, tokio::sync::mpsc::UnboundedReceiver<DaemonResponse>) {
+    create_pair_with_format(ResponseFormat::Text)
+}
+
+pub fn create_pair_with_format(
+    format: ResponseFormat,
+) -> (DaemonResponseSender, tokio::sync::mpsc::UnboundedReceiver<DaemonResponse>) {
     let (sender, recv) = tokio::sync::mpsc::unbounded_channel();
-    (DaemonResponseSender(sender), recv)
+    (DaemonResponseSender(sender, format), recv)
 }
 
 impl DaemonResponseSender {
+    pub fn wants_json(&self) -> bool {
+        self.1 == ResponseFormat::Json
+    }
+
+    pub fn send_structured(&self, payload: ResponsePayload) -> Result<()> {
+        self.0.send(DaemonResponse::Structured(payload)).context("Failed to send structured response from application thread")
+    }
+
     pub fn send_success(&self, s: String) -> Result<()> {
         self.0.send(DaemonResponse::Success(s)).context("Failed to send success response from application thread")
     }
@@ -38,8 +210,22 @@ impl DaemonResponseSender {
         self.0.send(DaemonResponse::Failure(s)).context("Failed to send failure response from application thread")
     }
 
+    /// Tell the client what the daemon is working on. This must be followed by an actual response.
+    /// The client may have stopped waiting already, which is ignored such that the command is still carried out completely.
+    pub fn send_progress(&self, s: String) {
+        let _ = self.0.send(DaemonResponse::Progress(s));
+    }
+
     /// Given a list of errors, respond with an error value if there are any errors, and respond with success otherwise.
     pub fn respond_with_error_list(&self, errors: impl IntoIterator<Item = anyhow::Error>) -> Result<()> {
+        if self.wants_json() {
+            let errors = errors.into_iter().map(|e| ErrorInfo::from_error(&e)).collect_vec();
+            return if errors.is_empty() {
+                self.send_structured(ResponsePayload::Output { output: String::new() })
+            } else {
+                self.send_structured(ResponsePayload::Errors { errors })
+            };
+        }
         let errors = errors.into_iter().map(|e| error_handling_ctx::format_error(&e)).join("\n");
         if errors.is_empty() {
             self.send_success(String::new())
@@ -52,6 +238,9 @@ impl DaemonResponseSender {
     pub fn respond_with_result<T>(&self, result: Result<T>) -> Result<()> {
         match result {
             Ok(_) => self.send_success(String::new()),
+            Err(e) if self.wants_json() => {
+                self.send_structured(ResponsePayload::Errors { errors: vec![ErrorInfo::from_error(&e)] })
+            }
             Err(e) => {
                 let formatted = error_handling_ctx::format_error(&e);
                 self.respond_with_error_msg(formatted)
@@ -0,0 +1,329 @@
+//! The protocol spoken over the IPC socket of the daemon, as documented in `docs/src/ipc.md`.
+//!
+//! Every message is a json document, prefixed by its length in bytes as 4 big-endian bytes.
+//! A connection starts with both sides sending a [`Handshake`], after which the client sends a single [`Request`].
+//! The daemon answers with a [`DaemonResponse`] once the command is done, optionally preceded by
+//! [`DaemonResponse::Progress`] messages, and then closes the connection.
+//! Streaming commands like `subscribe` get a response for every event instead.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use anyhow::*;
+use eww_shared_util::VarName;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use simplexpr::dynval::DynVal;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use yuck::{config::window_geometry::AnchorPoint, value::Coords};
+
+use crate::{
+    app,
+    daemon_response::{self, DaemonResponse, DaemonResponseReceiver, DaemonResponseSender, ResponseFormat},
+};
+
+/// Incremented whenever the messages change in a way that older clients or daemons can't handle.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Larger messages are rejected, as they can only come from something that doesn't speak this protocol.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// The first message sent by both sides of a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    /// The version of eww, which is only used to make error messages more helpful
+    pub eww_version: String,
+}
+
+impl Handshake {
+    pub fn current() -> Self {
+        Handshake { protocol_version: PROTOCOL_VERSION, eww_version: env!("CARGO_PKG_VERSION").to_string() }
+    }
+}
+
+/// Fail with a [`VersionMismatch`] if the client and the daemon speak different versions of the protocol.
+pub fn check_versions(client: &Handshake, daemon: &Handshake) -> Result<()> {
+    if client.protocol_version == daemon.protocol_version {
+        Ok(())
+    } else {
+        Err(VersionMismatch { client: client.clone(), daemon: daemon.clone() }.into())
+    }
+}
+
+#[derive(Debug, derive_more::Display)]
+#[display(
+    fmt = "This client speaks version {} of the IPC protocol (eww {}), but the daemon speaks version {} (eww {}). Restart the \
+           daemon with `eww kill` and `eww daemon`, so that both are the same version of eww.",
+    "client.protocol_version",
+    "client.eww_version",
+    "daemon.protocol_version",
+    "daemon.eww_version"
+)]
+pub struct VersionMismatch {
+    pub client: Handshake,
+    pub daemon: Handshake,
+}
+
+impl std::error::Error for VersionMismatch {}
+
+/// The message a client sends to the daemon after the handshake.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub command: Command,
+    #[serde(default)]
+    pub format: ResponseFormat,
+}
+
+/// The commands the daemon understands. These mostly correspond to the subcommands of the eww cli.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Command {
+    Ping,
+    /// Values that are strings are parsed as json if possible, just like the values given to `eww update`
+    Update {
+        vars: BTreeMap<String, serde_json::Value>,
+        /// Update all of the variables at once, or none of them if any can't be updated
+        #[serde(default)]
+        atomic: bool,
+    },
+    OpenInspector,
+    OpenWindow {
+        name: String,
+        monitor: Option<i32>,
+        #[serde(default, with = "optional_string")]
+        pos: Option<Coords>,
+        #[serde(default, with = "optional_string")]
+        size: Option<Coords>,
+        #[serde(default, with = "optional_string")]
+        anchor: Option<AnchorPoint>,
+        /// If the window is already open, close it instead
+        #[serde(default)]
+        toggle: bool,
+    },
+    OpenMany {
+        windows: Vec<String>,
+        #[serde(default)]
+        toggle: bool,
+    },
+    Close {
+        windows: Vec<String>,
+    },
+    Reload,
+    Kill,
+    CloseAll,
+    State {
+        #[serde(default)]
+        all: bool,
+    },
+    Get {
+        name: String,
+    },
+    Subscribe {
+        vars: Vec<String>,
+        #[serde(default)]
+        windows: bool,
+    },
+    Windows,
+    Listeners,
+    Debug,
+    Graph,
+}
+
+impl Command {
+    /// Whether the daemon answers this command, rather than just closing the connection once it received it.
+    /// If the daemon closes the connection without answering one of these, handling the command failed unexpectedly.
+    pub fn has_response(&self) -> bool {
+        !matches!(self, Command::OpenInspector | Command::Kill)
+    }
+
+    /// Whether the daemon keeps sending responses to this command until the client disconnects.
+    pub fn is_streaming(&self) -> bool {
+        matches!(self, Command::Subscribe { .. })
+    }
+
+    pub fn into_daemon_command(self, format: ResponseFormat) -> (app::DaemonCommand, Option<DaemonResponseReceiver>) {
+        let command = match self {
+            Command::OpenInspector => app::DaemonCommand::OpenInspector,
+            Command::Kill => app::DaemonCommand::KillServer,
+            Command::Update { vars, atomic } => {
+                let mappings = vars
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let value = match value {
+                            serde_json::Value::String(value) => DynVal::from_string_or_json(value),
+                            value => DynVal::from(value),
+                        };
+                        (VarName(name), value)
+                    })
+                    .collect();
+                return with_response_channel(format, |sender| app::DaemonCommand::SetVars { mappings, atomic, sender });
+            }
+            Command::CloseAll => return with_response_channel(format, app::DaemonCommand::CloseAll),
+            Command::Ping => {
+                let (send, recv) = tokio::sync::mpsc::unbounded_channel();
+                let _ = send.send(DaemonResponse::Success("pong".to_owned()));
+                return (app::DaemonCommand::NoOp, Some(recv));
+            }
+            Command::OpenMany { windows, toggle } => {
+                return with_response_channel(format, |sender| app::DaemonCommand::OpenMany {
+                    windows,
+                    should_toggle: toggle,
+                    sender,
+                });
+            }
+            Command::OpenWindow { name, monitor, pos, size, anchor, toggle } => {
+                return with_response_channel(format, |sender| app::DaemonCommand::OpenWindow {
+                    window_name: name,
+                    pos,
+                    size,
+                    anchor,
+                    screen: monitor,
+                    should_toggle: toggle,
+                    sender,
+                })
+            }
+            Command::Close { windows } => {
+                return with_response_channel(format, |sender| app::DaemonCommand::CloseWindows { windows, sender });
+            }
+            Command::Reload => return with_response_channel(format, app::DaemonCommand::ReloadConfigAndCss),
+            Command::Windows => return with_response_channel(format, app::DaemonCommand::PrintWindows),
+            Command::State { all } => {
+                return with_response_channel(format, |sender| app::DaemonCommand::PrintState { all, sender })
+            }
+            Command::Get { name } => return with_response_channel(format, |sender| app::DaemonCommand::GetVar { name, sender }),
+            Command::Subscribe { vars, windows } => {
+                let vars = vars.into_iter().map(VarName).collect();
+                return with_response_channel(format, |sender| app::DaemonCommand::Subscribe { vars, windows, sender });
+            }
+            Command::Listeners => return with_response_channel(format, app::DaemonCommand::PrintListeners),
+            Command::Debug => return with_response_channel(format, app::DaemonCommand::PrintDebug),
+            Command::Graph => return with_response_channel(format, app::DaemonCommand::PrintGraph),
+        };
+        (command, None)
+    }
+}
+
+fn with_response_channel<O, F>(format: ResponseFormat, f: F) -> (O, Option<DaemonResponseReceiver>)
+where
+    F: FnOnce(DaemonResponseSender) -> O,
+{
+    let (sender, recv) = daemon_response::create_pair_with_format(format);
+    (f(sender), Some(recv))
+}
+
+/// (De)serializes values like [`Coords`] in the same form as they are given on the command line, i.e. `"200x50"`.
+mod optional_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::{fmt::Display, str::FromStr};
+
+    pub fn serialize<T: Display, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.serialize_some(&value.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?.map(|x| x.parse().map_err(serde::de::Error::custom)).transpose()
+    }
+}
+
+pub fn write_message(stream: &mut impl Write, message: &impl Serialize) -> Result<()> {
+    let message = serde_json::to_vec(message)?;
+    stream.write_all(&(message.len() as u32).to_be_bytes()).context("Failed to write message size header to IPC stream")?;
+    stream.write_all(&message).context("Failed to write message to IPC stream")?;
+    Ok(())
+}
+
+/// Read a single message, or return `None` if the other side closed the connection instead of sending one.
+pub fn read_message<T: DeserializeOwned>(stream: &mut impl Read) -> Result<Option<T>> {
+    let mut message_byte_length = [0u8; 4];
+    match stream.read_exact(&mut message_byte_length) {
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result.context("Failed to read message size header from IPC stream")?,
+    }
+    let mut message = vec![0u8; checked_message_size(message_byte_length)?];
+    stream.read_exact(&mut message).context("Failed to read message from IPC stream")?;
+    Ok(Some(serde_json::from_slice(&message).context("Failed to parse IPC message")?))
+}
+
+pub async fn write_message_async(stream: &mut (impl AsyncWriteExt + Unpin), message: &impl Serialize) -> Result<()> {
+    let message = serde_json::to_vec(message)?;
+    stream.write_all(&(message.len() as u32).to_be_bytes()).await.context("Failed to write message size header to IPC stream")?;
+    stream.write_all(&message).await.context("Failed to write message to IPC stream")?;
+    Ok(())
+}
+
+/// Read a single message, or return `None` if the other side closed the connection instead of sending one.
+pub async fn read_message_async<T: DeserializeOwned>(stream: &mut (impl AsyncReadExt + Unpin)) -> Result<Option<T>> {
+    let mut message_byte_length = [0u8; 4];
+    match stream.read_exact(&mut message_byte_length).await {
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result.context("Failed to read message size header from IPC stream")?,
+    };
+    let mut message = vec![0u8; checked_message_size(message_byte_length)?];
+    stream.read_exact(&mut message).await.context("Failed to read message from IPC stream")?;
+    Ok(Some(serde_json::from_slice(&message).context("Failed to parse IPC message")?))
+}
+
+fn checked_message_size(message_byte_length: [u8; 4]) -> Result<usize> {
+    let size = u32::from_be_bytes(message_byte_length) as usize;
+    if size > MAX_MESSAGE_SIZE {
+        bail!("IPC message of {} bytes is larger than the maximum of {} bytes", size, MAX_MESSAGE_SIZE);
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request: Request =
+            serde_json::from_str(r#"{"command": {"type": "open-window", "name": "bar", "pos": "10x20"}}"#).unwrap();
+        assert_eq!(
+            request,
+            Request {
+                command: Command::OpenWindow {
+                    name: "bar".to_string(),
+                    monitor: None,
+                    pos: Some(Coords::from_pixels((10, 20))),
+                    size: None,
+                    anchor: None,
+                    toggle: false,
+                },
+                format: ResponseFormat::Text,
+            }
+        );
+
+        let request: Request = serde_json::from_str(r#"{"command": {"type": "state", "all": true}, "format": "json"}"#).unwrap();
+        assert_eq!(request, Request { command: Command::State { all: true }, format: ResponseFormat::Json });
+    }
+
+    #[test]
+    fn test_message_roundtrip() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &Handshake::current()).unwrap();
+        write_message(&mut buf, &DaemonResponse::Success("pong".to_string())).unwrap();
+        let mut stream = std::io::Cursor::new(buf);
+        let handshake: Option<Handshake> = read_message(&mut stream).unwrap();
+        assert_eq!(handshake, Some(Handshake::current()));
+        let response: Option<DaemonResponse> = read_message(&mut stream).unwrap();
+        assert_eq!(response, Some(DaemonResponse::Success("pong".to_string())));
+        let response: Option<DaemonResponse> = read_message(&mut stream).unwrap();
+        assert_eq!(response, None);
+
+        let response = serde_json::to_string(&DaemonResponse::Success("pong".to_string())).unwrap();
+        assert_eq!(response, r#"{"status":"success","content":"pong"}"#);
+        let progress = serde_json::to_string(&DaemonResponse::Progress("Opening bar".to_string())).unwrap();
+        assert_eq!(progress, r#"{"status":"progress","content":"Opening bar"}"#);
+    }
+}
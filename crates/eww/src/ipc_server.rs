@@ -1,6 +1,9 @@
-use crate::{app, opts};
+use crate::{
+    app,
+    daemon_response::DaemonResponse,
+    ipc_protocol::{self, Handshake, Request},
+};
 use anyhow::*;
-use std::time::Duration;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     sync::mpsc::*,
@@ -29,36 +32,62 @@ pub async fn run_server<P: AsRef<std::path::Path>>(evt_send: UnboundedSender<app
 async fn handle_connection(mut stream: tokio::net::UnixStream, evt_send: UnboundedSender<app::DaemonCommand>) -> Result<()> {
     let (mut stream_read, mut stream_write) = stream.split();
 
-    let action: opts::ActionWithServer = read_action_from_stream(&mut stream_read).await?;
+    let client_handshake: Handshake = match ipc_protocol::read_message_async(&mut stream_read).await? {
+        Some(handshake) => handshake,
+        // clients like `check_server_running` may connect without sending anything
+        None => return Ok(()),
+    };
+    let daemon_handshake = Handshake::current();
+    ipc_protocol::write_message_async(&mut stream_write, &daemon_handshake).await?;
+    // the client reports the mismatch to the user, as it has the same handshakes to compare
+    if let Err(err) = ipc_protocol::check_versions(&client_handshake, &daemon_handshake) {
+        log::warn!("{}", err);
+        stream_write.shutdown().await?;
+        return Ok(());
+    }
+
+    let Request { command, format } = match ipc_protocol::read_message_async(&mut stream_read).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err(err) => {
+            let response = DaemonResponse::Failure(format!("Invalid request: {:#}", err));
+            ipc_protocol::write_message_async(&mut stream_write, &response).await?;
+            stream_write.shutdown().await?;
+            return Err(err);
+        }
+    };
 
-    log::debug!("received command from IPC: {:?}", &action);
+    log::debug!("received command from IPC: {:?}", &command);
 
-    let (command, maybe_response_recv) = action.into_daemon_command();
+    let is_streaming = command.is_streaming();
+    let (command, maybe_response_recv) = command.into_daemon_command(format);
 
     evt_send.send(command)?;
 
     if let Some(mut response_recv) = maybe_response_recv {
         log::debug!("Waiting for response for IPC client");
-        if let Ok(Some(response)) = tokio::time::timeout(Duration::from_millis(100), response_recv.recv()).await {
-            let response = bincode::serialize(&response)?;
-            let result = &stream_write.write_all(&response).await;
-            crate::print_result_err!("sending text response to ipc client", &result);
+        // The app drops the sender once it is done with the command, no matter how long that takes.
+        // Streaming commands keep it until they fail to send a response, which happens once the receiver is dropped here.
+        // Clients don't send anything after their request, so reading from the stream only finishes once they disconnected.
+        let mut buf = [0; 1];
+        loop {
+            tokio::select! {
+                response = response_recv.recv() => match response {
+                    Some(response) => {
+                        if ipc_protocol::write_message_async(&mut stream_write, &response).await.is_err() {
+                            log::debug!("IPC client disconnected");
+                            break;
+                        }
+                    }
+                    None => break,
+                },
+                _ = stream_read.read(&mut buf), if is_streaming => {
+                    log::debug!("IPC client disconnected");
+                    break;
+                }
+            }
         }
     }
     stream_write.shutdown().await?;
     Ok(())
 }
-
-/// Read a single message from a unix stream, and parses it into a `ActionWithServer`
-/// The format here requires the first 4 bytes to be the size of the rest of the message (in big-endian), followed by the rest of the message.
-async fn read_action_from_stream(stream_read: &'_ mut tokio::net::unix::ReadHalf<'_>) -> Result<opts::ActionWithServer> {
-    let mut message_byte_length = [0u8; 4];
-    stream_read.read_exact(&mut message_byte_length).await.context("Failed to read message size header in IPC message")?;
-    let message_byte_length = u32::from_be_bytes(message_byte_length);
-    let mut raw_message = Vec::<u8>::with_capacity(message_byte_length as usize);
-    while raw_message.len() < message_byte_length as usize {
-        stream_read.read_buf(&mut raw_message).await.context("Failed to read actual IPC message")?;
-    }
-
-    bincode::deserialize(&raw_message).context("Failed to parse client message")
-}
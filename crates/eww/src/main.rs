@@ -14,10 +14,11 @@ extern crate gtk;
 extern crate gtk_layer_shell as gtk_layer_shell;
 
 use anyhow::*;
-use daemon_response::{DaemonResponse, DaemonResponseReceiver};
+use daemon_response::{DaemonResponse, ResponseFormat};
+use ipc_protocol::{Command, VersionMismatch};
 use opts::ActionWithServer;
 use std::{
-    os::unix::net,
+    os::unix::{io::AsRawFd, net},
     path::{Path, PathBuf},
     time::Duration,
 };
@@ -33,6 +34,7 @@ pub mod display_backend;
 pub mod error;
 mod error_handling_ctx;
 pub mod geometry;
+pub mod ipc_protocol;
 pub mod ipc_server;
 pub mod opts;
 pub mod script_var_handler;
@@ -44,6 +46,7 @@ pub mod widgets;
 fn main() {
     let eww_binary_name = std::env::args().next().unwrap();
     let opts: opts::Opt = opts::Opt::from_env();
+    let format = if opts.json { ResponseFormat::Json } else { ResponseFormat::Text };
 
     let log_level_filter = if opts.log_debug { log::LevelFilter::Debug } else { log::LevelFilter::Info };
     if std::env::var("RUST_LOG").is_ok() {
@@ -65,9 +68,9 @@ fn main() {
             opts::Action::ClientOnly(_) => false,
         };
         if should_restart {
-            let response = handle_server_command(&paths, &ActionWithServer::KillServer, 1);
+            let response = handle_server_command(&paths, &Command::Kill, 1, format, opts.timeout);
             if let Ok(Some(response)) = response {
-                handle_daemon_response(response);
+                handle_daemon_response(response, format);
             }
             std::thread::sleep(std::time::Duration::from_millis(200));
         }
@@ -90,28 +93,44 @@ fn main() {
                 if !opts.show_logs {
                     println!("Run `{} logs` to see any errors while editing your configuration.", eww_binary_name);
                 }
-                let fork_result = server::initialize_server(paths.clone(), None, !opts.no_daemonize)?;
+                let fork_result = server::initialize_server(paths.clone(), !opts.no_daemonize)?;
                 opts.no_daemonize || fork_result == ForkResult::Parent
             }
 
             opts::Action::WithServer(ActionWithServer::KillServer) => {
-                if let Some(response) = handle_server_command(&paths, &ActionWithServer::KillServer, 1)? {
-                    handle_daemon_response(response);
+                if let Some(response) = handle_server_command(&paths, &Command::Kill, 1, format, opts.timeout)? {
+                    handle_daemon_response(response, format);
                 }
                 false
             }
 
+            opts::Action::WithServer(action) if action.is_streaming() => {
+                let mut stream = attempt_connect(&paths.get_ipc_socket_file(), 5).context("Failed to connect to daemon")?;
+                client::do_streaming_server_call(&mut stream, &action.into_command()?, format, |response| {
+                    match response {
+                        // the events are json already, so they are printed as they are regardless of the format
+                        DaemonResponse::Success(event) => println!("{}", event),
+                        response => handle_daemon_response(response, format),
+                    }
+                    Ok(())
+                })?;
+                false
+            }
+
             // a running daemon is necessary for this command
             opts::Action::WithServer(action) => {
+                let can_start_daemon = action.can_start_daemon();
+                let command = action.into_command()?;
                 // attempt to just send the command to a running daemon
-                match handle_server_command(&paths, &action, 5) {
+                match handle_server_command(&paths, &command, 5, format, opts.timeout) {
                     Ok(Some(response)) => {
-                        handle_daemon_response(response);
+                        handle_daemon_response(response, format);
                         true
                     }
                     Ok(None) => true,
 
-                    Err(err) if action.can_start_daemon() && !opts.no_daemonize => {
+                    // a daemon of a different version is still running, so starting another one would fail
+                    Err(err) if can_start_daemon && !opts.no_daemonize && !err.is::<VersionMismatch>() => {
                         // connecting to the daemon failed. Thus, start the daemon here!
                         log::warn!("Failed to connect to daemon: {}", err);
                         log::info!("Initializing eww server. ({})", paths.get_ipc_socket_file().display());
@@ -120,12 +139,15 @@ fn main() {
                             println!("Run `{} logs` to see any errors while editing your configuration.", eww_binary_name);
                         }
 
-                        let (command, response_recv) = action.into_daemon_command();
-                        // start the daemon and give it the command
-                        let fork_result = server::initialize_server(paths.clone(), Some(command), true)?;
+                        let fork_result = server::initialize_server(paths.clone(), true)?;
                         let is_parent = fork_result == ForkResult::Parent;
-                        if let (Some(recv), true) = (response_recv, is_parent) {
-                            listen_for_daemon_response(recv);
+                        // send the command once the daemon is up, such that its response can be awaited like any other
+                        if is_parent {
+                            let response =
+                                handle_server_command(&paths, &command, DAEMON_STARTUP_CONNECT_ATTEMPTS, format, opts.timeout)?;
+                            if let Some(response) = response {
+                                handle_daemon_response(response, format);
+                            }
                         }
                         is_parent
                     }
@@ -144,51 +166,75 @@ fn main() {
     }
 }
 
-fn listen_for_daemon_response(mut recv: DaemonResponseReceiver) {
-    let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().expect("Failed to initialize tokio runtime");
-    rt.block_on(async {
-        if let Ok(Some(response)) = tokio::time::timeout(Duration::from_millis(100), recv.recv()).await {
-            println!("{}", response);
-        }
-    })
-}
+/// How long a daemon may take to answer a ping, which it does without waiting for anything else.
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often to try connecting to a daemon that was just started, 200ms apart.
+const DAEMON_STARTUP_CONNECT_ATTEMPTS: usize = 25;
 
 /// attempt to send a command to the daemon and send it the given action repeatedly.
-fn handle_server_command(paths: &EwwPaths, action: &ActionWithServer, connect_attempts: usize) -> Result<Option<DaemonResponse>> {
+fn handle_server_command(
+    paths: &EwwPaths,
+    command: &Command,
+    connect_attempts: usize,
+    format: ResponseFormat,
+    timeout: Option<Duration>,
+) -> Result<Option<DaemonResponse>> {
     log::debug!("Trying to find server process at socket {}", paths.get_ipc_socket_file().display());
     let mut stream = attempt_connect(&paths.get_ipc_socket_file(), connect_attempts).context("Failed to connect to daemon")?;
     log::debug!("Connected to Eww server ({}).", &paths.get_ipc_socket_file().display());
-    client::do_server_call(&mut stream, action).context("Error while forwarding command to server")
+    client::do_server_call(&mut stream, command, format, timeout, print_progress)
+        .context("Error while forwarding command to server")
 }
 
-fn handle_daemon_response(res: DaemonResponse) {
-    match res {
-        DaemonResponse::Success(x) => println!("{}", x),
-        DaemonResponse::Failure(x) => {
-            eprintln!("{}", x);
-            std::process::exit(1);
-        }
+/// Show what the daemon is working on to users waiting for it in a terminal, without cluttering the output of scripts.
+fn print_progress(progress: String) {
+    if nix::unistd::isatty(std::io::stderr().as_raw_fd()).unwrap_or(false) {
+        eprintln!("{}...", progress);
+    } else {
+        log::debug!("Daemon progress: {}", progress);
     }
 }
 
-fn attempt_connect(socket_path: impl AsRef<Path>, attempts: usize) -> Option<net::UnixStream> {
+/// Print the response of the daemon, and exit unsuccessfully if it reports a failure.
+fn handle_daemon_response(res: DaemonResponse, format: ResponseFormat) {
+    let is_failure = res.is_failure();
+    match (res, format) {
+        (DaemonResponse::Success(x), ResponseFormat::Text) => println!("{}", x),
+        (DaemonResponse::Failure(x), ResponseFormat::Text) => eprintln!("{}", x),
+        (res, _) => println!("{}", res.into_payload()),
+    }
+    if is_failure {
+        std::process::exit(1);
+    }
+}
+
+/// Connect to the daemon once it responds to a ping. If it never does, the error of the last attempt is returned.
+fn attempt_connect(socket_path: impl AsRef<Path>, attempts: usize) -> Result<net::UnixStream> {
+    let mut last_error = anyhow!("Failed to connect to daemon");
     for _ in 0..attempts {
-        if let Ok(mut con) = net::UnixStream::connect(&socket_path) {
-            if client::do_server_call(&mut con, &opts::ActionWithServer::Ping).is_ok() {
-                return net::UnixStream::connect(&socket_path).ok();
-            }
+        match net::UnixStream::connect(&socket_path) {
+            Ok(mut con) => match client::do_server_call(&mut con, &Command::Ping, ResponseFormat::Text, Some(PING_TIMEOUT), drop)
+            {
+                Ok(_) => return Ok(net::UnixStream::connect(&socket_path)?),
+                // retrying won't help against a daemon of a different version
+                Err(err) if err.is::<VersionMismatch>() => return Err(err),
+                Err(err) => last_error = err,
+            },
+            Err(err) => last_error = err.into(),
         }
         std::thread::sleep(Duration::from_millis(200));
     }
-    None
+    Err(last_error)
 }
 
 /// Check if a eww server is currently running by trying to send a ping message to it.
 fn check_server_running(socket_path: impl AsRef<Path>) -> bool {
-    let response = net::UnixStream::connect(socket_path)
-        .ok()
-        .and_then(|mut stream| client::do_server_call(&mut stream, &opts::ActionWithServer::Ping).ok());
-    response.is_some()
+    let response = net::UnixStream::connect(socket_path).map_err(anyhow::Error::from).and_then(|mut stream| {
+        client::do_server_call(&mut stream, &Command::Ping, ResponseFormat::Text, Some(PING_TIMEOUT), drop)
+    });
+    // a daemon of a different version is running as well, even though it can't be talked to
+    response.is_ok() || matches!(response, Err(err) if err.is::<VersionMismatch>())
 }
 
 #[derive(Debug, Clone)]
@@ -2,13 +2,11 @@ use anyhow::*;
 use eww_shared_util::VarName;
 use serde::{Deserialize, Serialize};
 use simplexpr::dynval::DynVal;
+use std::{collections::BTreeMap, io::Read, time::Duration};
 use structopt::StructOpt;
 use yuck::{config::window_geometry::AnchorPoint, value::Coords};
 
-use crate::{
-    app,
-    daemon_response::{self, DaemonResponse, DaemonResponseSender},
-};
+use crate::ipc_protocol;
 
 /// Struct that gets generated from `RawOpt`.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -19,6 +17,9 @@ pub struct Opt {
     pub config_path: Option<std::path::PathBuf>,
     pub action: Action,
     pub no_daemonize: bool,
+    pub json: bool,
+    /// How long to wait for the daemon to finish a command, or `None` to wait indefinitely
+    pub timeout: Option<Duration>,
 }
 
 #[derive(StructOpt, Debug, Serialize, Deserialize, PartialEq)]
@@ -43,6 +44,14 @@ struct RawOpt {
     #[structopt(long = "restart", global = true)]
     restart: bool,
 
+    /// Print the response of the daemon as json
+    #[structopt(long = "json", global = true)]
+    json: bool,
+
+    /// How long to wait for the daemon to finish the command, like "30s" or "500ms". "0s" waits indefinitely
+    #[structopt(long = "timeout", global = true, default_value = "30s", parse(try_from_str = parse_duration))]
+    timeout: Duration,
+
     #[structopt(subcommand)]
     action: Action,
 }
@@ -79,6 +88,11 @@ pub enum ActionWithServer {
         /// variable_name="new_value"-pairs that will be updated
         #[structopt(parse(try_from_str = parse_var_update_arg))]
         mappings: Vec<(VarName, DynVal)>,
+
+        /// Read the updates from stdin instead, as variable_name=new_value lines or a json object, and apply all of them at
+        /// once. If any of them can't be applied, none are.
+        #[structopt(long, conflicts_with = "mappings")]
+        batch: bool,
     },
 
     /// Open the GTK debugger
@@ -151,10 +165,26 @@ pub enum ActionWithServer {
     #[structopt(name = "get")]
     GetVar { name: String },
 
+    /// Print a line whenever one of the given variables changes, starting with their current values.
+    /// Every line is a json object describing the change.
+    #[structopt(name = "subscribe")]
+    Subscribe {
+        /// The variables to watch
+        vars: Vec<String>,
+
+        /// Also print a line whenever a window is opened or closed
+        #[structopt(long)]
+        windows: bool,
+    },
+
     /// Print the names of all configured windows. Windows with a * in front of them are currently opened.
     #[structopt(name = "windows")]
     ShowWindows,
 
+    /// Print the state of the scripts of all running deflisten variables, i.e. whether they are waiting to be restarted.
+    #[structopt(name = "listeners")]
+    ShowListeners,
+
     /// Print out the widget structure as seen by eww.
     ///
     /// This may be useful if you are facing issues with how eww is interpreting your configuration,
@@ -176,8 +206,9 @@ impl Opt {
 
 impl From<RawOpt> for Opt {
     fn from(other: RawOpt) -> Self {
-        let RawOpt { log_debug, config, show_logs, no_daemonize, restart, action } = other;
-        Opt { log_debug, show_logs, restart, config_path: config, action, no_daemonize }
+        let RawOpt { log_debug, config, show_logs, no_daemonize, restart, json, timeout, action } = other;
+        let timeout = if timeout.is_zero() { None } else { Some(timeout) };
+        Opt { log_debug, show_logs, restart, config_path: config, action, no_daemonize, json, timeout }
     }
 }
 
@@ -188,59 +219,88 @@ fn parse_var_update_arg(s: &str) -> Result<(VarName, DynVal)> {
     Ok((name.into(), DynVal::from_string(value.to_owned())))
 }
 
+/// Parse the updates given to `eww update --batch`, which are either a json object or `variable_name=new_value` lines.
+fn parse_batch_update(input: &str) -> Result<BTreeMap<String, serde_json::Value>> {
+    if input.trim_start().starts_with('{') {
+        return serde_json::from_str(input).context("Failed to parse the updates as a json object");
+    }
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (name, value) = parse_var_update_arg(line)?;
+            Ok((name.0, serde_json::Value::String(value.into_inner())))
+        })
+        .collect()
+}
+
+fn parse_duration(s: &str) -> Result<Duration> {
+    Ok(DynVal::from_string(s.to_owned()).as_duration()?)
+}
+
 impl ActionWithServer {
     pub fn can_start_daemon(&self) -> bool {
         matches!(self, ActionWithServer::OpenWindow { .. } | ActionWithServer::OpenMany { .. })
     }
 
-    pub fn into_daemon_command(self) -> (app::DaemonCommand, Option<daemon_response::DaemonResponseReceiver>) {
-        let command = match self {
-            ActionWithServer::Update { mappings } => app::DaemonCommand::UpdateVars(mappings),
-            ActionWithServer::OpenInspector => app::DaemonCommand::OpenInspector,
-
-            ActionWithServer::KillServer => app::DaemonCommand::KillServer,
-            ActionWithServer::CloseAll => app::DaemonCommand::CloseAll,
-            ActionWithServer::Ping => {
-                let (send, recv) = tokio::sync::mpsc::unbounded_channel();
-                let _ = send.send(DaemonResponse::Success("pong".to_owned()));
-                return (app::DaemonCommand::NoOp, Some(recv));
-            }
-            ActionWithServer::OpenMany { windows, should_toggle } => {
-                return with_response_channel(|sender| app::DaemonCommand::OpenMany { windows, should_toggle, sender });
-            }
-            ActionWithServer::OpenWindow { window_name, pos, size, screen, anchor, should_toggle } => {
-                return with_response_channel(|sender| app::DaemonCommand::OpenWindow {
-                    window_name,
-                    pos,
-                    size,
-                    anchor,
-                    screen,
-                    should_toggle,
-                    sender,
-                })
-            }
-            ActionWithServer::CloseWindows { windows } => {
-                return with_response_channel(|sender| app::DaemonCommand::CloseWindows { windows, sender });
+    /// Whether the daemon keeps sending responses to this action until the client disconnects, rather than just one.
+    pub fn is_streaming(&self) -> bool {
+        matches!(self, ActionWithServer::Subscribe { .. })
+    }
+
+    /// Turn the action into the command sent to the daemon. For `eww update --batch`, this reads the updates from stdin.
+    pub fn into_command(self) -> Result<ipc_protocol::Command> {
+        Ok(match self {
+            ActionWithServer::Ping => ipc_protocol::Command::Ping,
+            ActionWithServer::Update { mappings, batch: false } => ipc_protocol::Command::Update {
+                vars: mappings.into_iter().map(|(name, value)| (name.0, serde_json::Value::String(value.into_inner()))).collect(),
+                atomic: false,
+            },
+            ActionWithServer::Update { batch: true, .. } => {
+                let mut input = String::new();
+                std::io::stdin().read_to_string(&mut input).context("Failed to read the updates from stdin")?;
+                ipc_protocol::Command::Update { vars: parse_batch_update(&input)?, atomic: true }
             }
-            ActionWithServer::Reload => return with_response_channel(app::DaemonCommand::ReloadConfigAndCss),
-            ActionWithServer::ShowWindows => return with_response_channel(app::DaemonCommand::PrintWindows),
-            ActionWithServer::ShowState { all } => {
-                return with_response_channel(|sender| app::DaemonCommand::PrintState { all, sender })
+            ActionWithServer::OpenInspector => ipc_protocol::Command::OpenInspector,
+            ActionWithServer::OpenWindow { window_name, screen, pos, size, anchor, should_toggle } => {
+                ipc_protocol::Command::OpenWindow { name: window_name, monitor: screen, pos, size, anchor, toggle: should_toggle }
             }
-            ActionWithServer::GetVar { name } => {
-                return with_response_channel(|sender| app::DaemonCommand::GetVar { name, sender })
+            ActionWithServer::OpenMany { windows, should_toggle } => {
+                ipc_protocol::Command::OpenMany { windows, toggle: should_toggle }
             }
-            ActionWithServer::ShowDebug => return with_response_channel(app::DaemonCommand::PrintDebug),
-            ActionWithServer::ShowGraph => return with_response_channel(app::DaemonCommand::PrintGraph),
-        };
-        (command, None)
+            ActionWithServer::CloseWindows { windows } => ipc_protocol::Command::Close { windows },
+            ActionWithServer::Reload => ipc_protocol::Command::Reload,
+            ActionWithServer::KillServer => ipc_protocol::Command::Kill,
+            ActionWithServer::CloseAll => ipc_protocol::Command::CloseAll,
+            ActionWithServer::ShowState { all } => ipc_protocol::Command::State { all },
+            ActionWithServer::GetVar { name } => ipc_protocol::Command::Get { name },
+            ActionWithServer::Subscribe { vars, windows } => ipc_protocol::Command::Subscribe { vars, windows },
+            ActionWithServer::ShowWindows => ipc_protocol::Command::Windows,
+            ActionWithServer::ShowListeners => ipc_protocol::Command::Listeners,
+            ActionWithServer::ShowDebug => ipc_protocol::Command::Debug,
+            ActionWithServer::ShowGraph => ipc_protocol::Command::Graph,
+        })
     }
 }
 
-fn with_response_channel<O, F>(f: F) -> (O, Option<tokio::sync::mpsc::UnboundedReceiver<DaemonResponse>>)
-where
-    F: FnOnce(DaemonResponseSender) -> O,
-{
-    let (sender, recv) = daemon_response::create_pair();
-    (f(sender), Some(recv))
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch_update() {
+        let expected: BTreeMap<String, serde_json::Value> = vec![
+            ("volume".to_string(), serde_json::Value::String("42".to_string())),
+            ("song".to_string(), serde_json::Value::String("a = b".to_string())),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(parse_batch_update("volume=42\n\nsong=a = b\n").unwrap(), expected);
+        assert!(parse_batch_update("volume").is_err());
+
+        let json = parse_batch_update(r#" {"volume": 42, "muted": false}"#).unwrap();
+        assert_eq!(json["volume"], serde_json::json!(42));
+        assert_eq!(json["muted"], serde_json::json!(false));
+        assert!(parse_batch_update(r#"{"volume": "#).is_err());
+    }
 }
@@ -2,7 +2,12 @@ use std::collections::HashMap;
 
 use crate::{
     app,
-    config::{create_script_var_failed_warn, script_var},
+    config::{
+        create_script_var_failed_warn,
+        record_splitter::RecordSplitter,
+        script_var::{self, ScriptVarMeta},
+        var_source::{create_poll_source, PollSource},
+    },
 };
 use anyhow::*;
 use app::DaemonCommand;
@@ -12,17 +17,26 @@ use nix::{
     sys::signal,
     unistd::{setpgid, Pid},
 };
-use simplexpr::dynval::DynVal;
+use simplexpr::{ast::meta_var_name, dynval::DynVal};
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
     sync::mpsc::UnboundedSender,
 };
 use tokio_util::sync::CancellationToken;
-use yuck::config::script_var_definition::{ListenScriptVar, PollScriptVar, ScriptVarDefinition, VarSource};
+use yuck::config::{
+    magic_var_definition::MagicVarDefinition,
+    script_var_definition::{
+        FileScriptVar, ListenScriptVar, ListenTransform, PollScriptVar, RestartMode, ScriptVarDefinition, SocketScriptVar,
+        VarSource,
+    },
+};
 
 /// Initialize the script var handler, and return a handle to that handler, which can be used to control
-/// the script var execution.
-pub fn init(evt_send: UnboundedSender<DaemonCommand>) -> ScriptVarHandlerHandle {
+/// the script var execution. `magic_vars` configure the magic variables it runs, as given with `defmagic`.
+pub fn init(
+    evt_send: UnboundedSender<DaemonCommand>,
+    magic_vars: HashMap<VarName, MagicVarDefinition>,
+) -> ScriptVarHandlerHandle {
     let (msg_send, mut msg_recv) = tokio::sync::mpsc::unbounded_channel();
     let handle = ScriptVarHandlerHandle { msg_send };
     std::thread::spawn(move || {
@@ -31,7 +45,9 @@ pub fn init(evt_send: UnboundedSender<DaemonCommand>) -> ScriptVarHandlerHandle
             let _: Result<_> = try {
                 let mut handler = ScriptVarHandler {
                     listen_handler: ListenVarHandler::new(evt_send.clone())?,
-                    poll_handler: PollVarHandler::new(evt_send)?,
+                    poll_handler: PollVarHandler::new(evt_send.clone(), magic_vars)?,
+                    file_handler: FileVarHandler::new(evt_send.clone())?,
+                    socket_handler: SocketVarHandler::new(evt_send)?,
                 };
                 crate::loop_select_exiting! {
                     Some(msg) = msg_recv.recv() => match msg {
@@ -86,7 +102,7 @@ impl ScriptVarHandlerHandle {
 }
 
 /// Message enum used by the ScriptVarHandlerHandle to communicate to the ScriptVarHandler
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 enum ScriptVarHandlerMsg {
     AddVar(ScriptVarDefinition),
     Stop(VarName),
@@ -97,6 +113,8 @@ enum ScriptVarHandlerMsg {
 struct ScriptVarHandler {
     listen_handler: ListenVarHandler,
     poll_handler: PollVarHandler,
+    file_handler: FileVarHandler,
+    socket_handler: SocketVarHandler,
 }
 
 impl ScriptVarHandler {
@@ -104,6 +122,8 @@ impl ScriptVarHandler {
         match script_var {
             ScriptVarDefinition::Poll(var) => self.poll_handler.start(var).await,
             ScriptVarDefinition::Listen(var) => self.listen_handler.start(var).await,
+            ScriptVarDefinition::File(var) => self.file_handler.start(var),
+            ScriptVarDefinition::Socket(var) => self.socket_handler.start(var),
         };
     }
 
@@ -112,6 +132,8 @@ impl ScriptVarHandler {
         log::debug!("Stopping script var process for variable {}", name);
         self.listen_handler.stop_for_variable(name);
         self.poll_handler.stop_for_variable(name);
+        self.file_handler.stop_for_variable(name);
+        self.socket_handler.stop_for_variable(name);
         Ok(())
     }
 
@@ -120,17 +142,20 @@ impl ScriptVarHandler {
         log::debug!("Stopping script-var-handlers");
         self.listen_handler.stop_all();
         self.poll_handler.stop_all();
+        self.file_handler.stop_all();
+        self.socket_handler.stop_all();
     }
 }
 
 struct PollVarHandler {
     evt_send: UnboundedSender<DaemonCommand>,
+    magic_vars: HashMap<VarName, MagicVarDefinition>,
     poll_handles: HashMap<VarName, CancellationToken>,
 }
 
 impl PollVarHandler {
-    fn new(evt_send: UnboundedSender<DaemonCommand>) -> Result<Self> {
-        let handler = PollVarHandler { evt_send, poll_handles: HashMap::new() };
+    fn new(evt_send: UnboundedSender<DaemonCommand>, magic_vars: HashMap<VarName, MagicVarDefinition>) -> Result<Self> {
+        let handler = PollVarHandler { evt_send, magic_vars, poll_handles: HashMap::new() };
         Ok(handler)
     }
 
@@ -143,22 +168,17 @@ impl PollVarHandler {
         let cancellation_token = CancellationToken::new();
         self.poll_handles.insert(var.name.clone(), cancellation_token.clone());
         let evt_send = self.evt_send.clone();
+        let mut source = create_poll_source(&var, self.magic_vars.get(&var.name));
         tokio::spawn(async move {
-            let result: Result<_> = try {
-                evt_send.send(app::DaemonCommand::UpdateVars(vec![(var.name.clone(), run_poll_once(&var)?)]))?;
-            };
-            if let Err(err) = result {
+            let mut meta = ScriptVarMeta::default();
+            if let Err(err) = update_poll_var(&evt_send, &var, source.as_mut(), &mut meta).await {
                 crate::error_handling_ctx::print_error(err);
             }
 
             crate::loop_select_exiting! {
                 _ = cancellation_token.cancelled() => break,
                 _ = tokio::time::sleep(var.interval) => {
-                    let result: Result<_> = try {
-                        evt_send.send(app::DaemonCommand::UpdateVars(vec![(var.name.clone(), run_poll_once(&var)?)]))?;
-                    };
-
-                    if let Err(err) = result {
+                    if let Err(err) = update_poll_var(&evt_send, &var, source.as_mut(), &mut meta).await {
                         crate::error_handling_ctx::print_error(err);
                     }
                 }
@@ -178,13 +198,45 @@ impl PollVarHandler {
     }
 }
 
-fn run_poll_once(var: &PollScriptVar) -> Result<DynVal> {
-    match &var.command {
-        VarSource::Shell(span, command) => {
-            script_var::run_command(command).map_err(|e| anyhow!(create_script_var_failed_warn(*span, &var.name, &e.to_string())))
+/// Read the source of a poll var once, sending the new value and metadata of the variable to the app.
+async fn update_poll_var(
+    evt_send: &UnboundedSender<DaemonCommand>,
+    var: &PollScriptVar,
+    source: &mut dyn PollSource,
+    meta: &mut ScriptVarMeta,
+) -> Result<()> {
+    let value = match (&var.command, var.timeout) {
+        // Shell scripts are killed by the source itself once they time out
+        (VarSource::Native(..), Some(timeout)) => {
+            tokio::time::timeout(timeout, source.read()).await.unwrap_or_else(|_| Err(anyhow!("Timed out after {:?}", timeout)))
         }
-        VarSource::Function(x) => x().map_err(|e| anyhow!(e)),
-    }
+        _ => source.read().await,
+    };
+    let value = match value {
+        Ok(value) => {
+            meta.update_succeeded();
+            meta.exit_code = if let VarSource::Shell(..) = var.command { Some(0) } else { None };
+            Ok(value)
+        }
+        Err(err) => {
+            meta.poll_failed(&err);
+            Err(err)
+        }
+    };
+    let mut updates = vec![(meta_var_name(&var.name), meta.to_dynval())];
+    let result = match value {
+        Ok(value) => {
+            updates.push((var.name.clone(), value));
+            Ok(())
+        }
+        Err(err) => Err(match &var.command {
+            VarSource::Shell(span, _) => anyhow!(create_script_var_failed_warn(*span, &var.name, &err.to_string())),
+            VarSource::Native(_, source) => err.context(format!("Failed to read the `{}` source of {}", source.kind(), var.name)),
+            VarSource::Function(_) => err,
+        }),
+    };
+    evt_send.send(DaemonCommand::UpdateVars(updates))?;
+    result
 }
 
 impl Drop for PollVarHandler {
@@ -205,40 +257,76 @@ impl ListenVarHandler {
     }
 
     async fn start(&mut self, var: ListenScriptVar) {
+        // The variable is still running when another window that uses it is opened, or its run-while condition is re-checked
+        if self.listen_process_handles.get(&var.name).map_or(false, |token| !token.is_cancelled()) {
+            return;
+        }
         log::debug!("starting listen-var {}", &var.name);
         let cancellation_token = CancellationToken::new();
         self.listen_process_handles.insert(var.name.clone(), cancellation_token.clone());
 
         let evt_send = self.evt_send.clone();
         tokio::spawn(async move {
-            crate::try_logging_errors!(format!("Executing listen var-command {}", &var.command) =>  {
-                let mut handle = unsafe {
-                    tokio::process::Command::new("sh")
-                    .args(&["-c", &var.command])
-                    .stdout(std::process::Stdio::piped())
-                    .stderr(std::process::Stdio::piped())
-                    .stdin(std::process::Stdio::null())
-                    .pre_exec(|| {
-                        let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
-                        Ok(())
-                    }).spawn()?
+            let policy = &var.restart;
+            let mut meta = ScriptVarMeta::default();
+            let mut restarts = 0;
+            let mut delay = policy.delay;
+            loop {
+                send_listen_process_state(&evt_send, &var, ListenProcessState::Running { restarts });
+                let started_at = std::time::Instant::now();
+                let exit_status = match run_listen_process(&var, &mut meta, &evt_send, &cancellation_token).await {
+                    Ok(Some(exit_status)) => exit_status,
+                    // The variable was stopped or eww is exiting
+                    Ok(None) => break,
+                    Err(err) => {
+                        log::error!("Error while executing listen var-command {}: {:?}", &var.command, err);
+                        meta.update_failed(&err);
+                        meta.stale = true;
+                        send_listen_var_meta(&evt_send, &var.name, &meta);
+                        send_listen_process_state(&evt_send, &var, ListenProcessState::Dead { exit_code: None, restarts });
+                        break;
+                    }
                 };
-                let mut stdout_lines = BufReader::new(handle.stdout.take().unwrap()).lines();
-                let mut stderr_lines = BufReader::new(handle.stderr.take().unwrap()).lines();
-                crate::loop_select_exiting! {
-                    _ = handle.wait() => break,
+                meta.exit_code = exit_status.code();
+                meta.stale = true;
+                if !exit_status.success() {
+                    meta.error = Some(match exit_status.code() {
+                        Some(code) => format!("The script exited with code {}", code),
+                        None => "The script was terminated".to_string(),
+                    });
+                }
+                send_listen_var_meta(&evt_send, &var.name, &meta);
+
+                // A script that kept running for a while worked fine, so a later failure starts a new series of restarts.
+                if started_at.elapsed() >= policy.max_delay {
+                    restarts = 0;
+                    delay = policy.delay;
+                }
+                let should_restart = match policy.mode {
+                    RestartMode::Never => false,
+                    RestartMode::OnFailure => !exit_status.success(),
+                    RestartMode::Always => true,
+                };
+                let exit_code = exit_status.code();
+                if !should_restart || policy.max_restarts.map_or(false, |max_restarts| restarts >= max_restarts) {
+                    log::info!("listen-var {} exited ({}), not restarting it", &var.name, exit_status);
+                    send_listen_process_state(&evt_send, &var, ListenProcessState::Dead { exit_code, restarts });
+                    break;
+                }
+
+                log::info!("listen-var {} exited ({}), restarting it in {:?}", &var.name, exit_status, delay);
+                let restart_at = std::time::Instant::now() + delay;
+                send_listen_process_state(&evt_send, &var, ListenProcessState::BackingOff { exit_code, restarts, restart_at });
+                tokio::select! {
                     _ = cancellation_token.cancelled() => break,
-                    Ok(Some(line)) = stdout_lines.next_line() => {
-                        let new_value = DynVal::from_string(line.to_owned());
-                        evt_send.send(DaemonCommand::UpdateVars(vec![(var.name.to_owned(), new_value)]))?;
-                    }
-                    Ok(Some(line)) = stderr_lines.next_line() => {
-                        log::warn!("stderr of `{}`: {}", var.name, line);
-                    }
-                    else => break,
+                    Ok(()) = crate::application_lifecycle::recv_exit() => break,
+                    _ = tokio::time::sleep(delay) => {}
                 }
-                terminate_handle(handle).await;
-            });
+                restarts += 1;
+                delay = std::cmp::min(delay * 2, policy.max_delay);
+            }
+            // mark the variable as no longer running, such that it can be started again
+            cancellation_token.cancel();
         });
     }
 
@@ -254,12 +342,385 @@ impl ListenVarHandler {
     }
 }
 
+/// Run the script of a listen var, sending its output to the app until it exits or the variable is stopped.
+/// Returns the exit status of the script, or `None` if it was stopped.
+async fn run_listen_process(
+    var: &ListenScriptVar,
+    meta: &mut ScriptVarMeta,
+    evt_send: &UnboundedSender<DaemonCommand>,
+    cancellation_token: &CancellationToken,
+) -> Result<Option<std::process::ExitStatus>> {
+    let mut handle = unsafe {
+        tokio::process::Command::from(script_var::script_command(&var.command, &var.script_env))
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .stdin(std::process::Stdio::null())
+            .pre_exec(|| {
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                Ok(())
+            })
+            .spawn()?
+    };
+    let mut stdout = handle.stdout.take().unwrap();
+    let mut stderr_lines = BufReader::new(handle.stderr.take().unwrap()).lines();
+    let mut records = RecordSplitter::new(&var.framing)?;
+    let mut read_buffer = [0; 4096];
+    let mut stdout_closed = false;
+    let mut exit_status = None;
+    crate::loop_select_exiting! {
+        status = handle.wait() => {
+            exit_status = Some(status?);
+            break;
+        },
+        _ = cancellation_token.cancelled() => break,
+        Ok(read) = stdout.read(&mut read_buffer), if !stdout_closed => {
+            stdout_closed = read == 0;
+            for new_value in records.push(&read_buffer[..read]) {
+                send_listen_var_value(evt_send, &var.name, var.transform.as_ref(), meta, new_value)?;
+            }
+        }
+        Ok(Some(line)) = stderr_lines.next_line() => {
+            log::warn!("stderr of `{}`: {}", var.name, line);
+        }
+        else => break,
+    }
+    // the script may exit before all of its output was read, which is still read until the end, unless the variable is stopped
+    while exit_status.is_some() && !stdout_closed {
+        tokio::select! {
+            Ok(()) = crate::application_lifecycle::recv_exit() => break,
+            _ = cancellation_token.cancelled() => break,
+            read = stdout.read(&mut read_buffer) => match read {
+                Ok(read) if read > 0 => {
+                    for new_value in records.push(&read_buffer[..read]) {
+                        send_listen_var_value(evt_send, &var.name, var.transform.as_ref(), meta, new_value)?;
+                    }
+                }
+                _ => stdout_closed = true,
+            },
+        }
+    }
+    for new_value in records.finish() {
+        send_listen_var_value(evt_send, &var.name, var.transform.as_ref(), meta, new_value)?;
+    }
+    terminate_handle(handle).await;
+    Ok(exit_status)
+}
+
+fn send_listen_process_state(evt_send: &UnboundedSender<DaemonCommand>, var: &ListenScriptVar, state: ListenProcessState) {
+    crate::print_result_err!(
+        "while sending the state of a listen-var process",
+        evt_send.send(DaemonCommand::UpdateListenProcessState(var.name.clone(), state))
+    );
+}
+
+/// The state of the script of a `deflisten` variable, as shown by `eww listeners`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenProcessState {
+    Running {
+        restarts: u32,
+    },
+    /// The script exited, and will be started again at `restart_at`.
+    BackingOff {
+        exit_code: Option<i32>,
+        restarts: u32,
+        restart_at: std::time::Instant,
+    },
+    /// The script exited, and won't be restarted.
+    Dead {
+        exit_code: Option<i32>,
+        restarts: u32,
+    },
+}
+
+impl std::fmt::Display for ListenProcessState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe_exit = |exit_code: &Option<i32>| match exit_code {
+            Some(code) => format!("exited with code {}", code),
+            None => "was terminated".to_string(),
+        };
+        let restarts = match self {
+            ListenProcessState::Running { restarts }
+            | ListenProcessState::BackingOff { restarts, .. }
+            | ListenProcessState::Dead { restarts, .. } => *restarts,
+        };
+        match self {
+            ListenProcessState::Running { .. } => write!(f, "running")?,
+            ListenProcessState::BackingOff { exit_code, restart_at, .. } => {
+                let remaining = restart_at.saturating_duration_since(std::time::Instant::now());
+                write!(f, "{}, restarting in {:.1}s", describe_exit(exit_code), remaining.as_secs_f64())?
+            }
+            ListenProcessState::Dead { exit_code, .. } => write!(f, "{}", describe_exit(exit_code))?,
+        }
+        if restarts > 0 {
+            write!(f, " (restarted {} times)", restarts)?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply the transform of a listen or socket var to a value it read, and send the result to the app.
+/// Values that can't be parsed or transformed are reported, but don't stop the variable.
+fn send_listen_var_value(
+    evt_send: &UnboundedSender<DaemonCommand>,
+    name: &VarName,
+    transform: Option<&ListenTransform>,
+    meta: &mut ScriptVarMeta,
+    value: Result<DynVal>,
+) -> Result<()> {
+    let value = value.with_context(|| format!("Failed to read the output of {}", name)).and_then(|value| match transform {
+        Some(transform) => transform.apply(value).map_err(|err| anyhow!(err)),
+        None => Ok(value),
+    });
+    match value {
+        Ok(value) => {
+            meta.update_succeeded();
+            evt_send.send(DaemonCommand::UpdateVars(vec![(name.clone(), value), (meta_var_name(name), meta.to_dynval())]))?
+        }
+        Err(err) => {
+            meta.update_failed(&err);
+            send_listen_var_meta(evt_send, name, meta);
+            crate::error_handling_ctx::print_error(err)
+        }
+    }
+    Ok(())
+}
+
+fn send_listen_var_meta(evt_send: &UnboundedSender<DaemonCommand>, name: &VarName, meta: &ScriptVarMeta) {
+    crate::print_result_err!(
+        "while sending the metadata of a listen-var",
+        evt_send.send(DaemonCommand::UpdateVars(vec![(meta_var_name(name), meta.to_dynval())]))
+    );
+}
+
 impl Drop for ListenVarHandler {
     fn drop(&mut self) {
         self.stop_all();
     }
 }
 
+struct FileVarHandler {
+    evt_send: UnboundedSender<DaemonCommand>,
+    watch_handles: HashMap<VarName, CancellationToken>,
+}
+
+impl FileVarHandler {
+    fn new(evt_send: UnboundedSender<DaemonCommand>) -> Result<Self> {
+        let handler = FileVarHandler { evt_send, watch_handles: HashMap::new() };
+        Ok(handler)
+    }
+
+    fn start(&mut self, var: FileScriptVar) {
+        if self.watch_handles.contains_key(&var.name) {
+            return;
+        }
+
+        log::debug!("starting file var {}", &var.name);
+        let cancellation_token = CancellationToken::new();
+        self.watch_handles.insert(var.name.clone(), cancellation_token.clone());
+        let evt_send = self.evt_send.clone();
+        tokio::spawn(async move {
+            if let Err(err) = watch_file_var(&var, &evt_send, &cancellation_token).await {
+                crate::error_handling_ctx::print_error(err.context(format!("Failed to watch {} for {}", var.path, var.name)));
+            }
+        });
+    }
+
+    fn stop_for_variable(&mut self, name: &VarName) {
+        if let Some(token) = self.watch_handles.remove(name) {
+            log::debug!("stopped file var {}", name);
+            token.cancel();
+        }
+    }
+
+    fn stop_all(&mut self) {
+        self.watch_handles.drain().for_each(|(_, token)| token.cancel());
+    }
+}
+
+impl Drop for FileVarHandler {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
+/// Read the file of a file var whenever it changes, until the variable is stopped.
+async fn watch_file_var(
+    var: &FileScriptVar,
+    evt_send: &UnboundedSender<DaemonCommand>,
+    cancellation_token: &CancellationToken,
+) -> Result<()> {
+    use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+    let path = std::path::Path::new(&var.path);
+    let file_name = path.file_name().context("The path does not point to a file")?.to_owned();
+    // Watching the directory rather than the file itself also notices the file being replaced or created
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| std::path::Path::new("."));
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher: RecommendedWatcher = Watcher::new(move |res: notify::Result<notify::Event>| match res {
+        Ok(event) if event.paths.iter().any(|path| path.file_name() == Some(&file_name)) => {
+            let _ = tx.send(());
+        }
+        Ok(_) => {}
+        Err(e) => log::error!("Encountered error while watching files: {}", e),
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    let mut meta = ScriptVarMeta::default();
+    let mut last_content = None;
+    update_file_var(evt_send, var, &mut meta, &mut last_content).await?;
+    crate::loop_select_exiting! {
+        _ = cancellation_token.cancelled() => break,
+        Some(()) = rx.recv() => update_file_var(evt_send, var, &mut meta, &mut last_content).await?,
+    }
+    Ok(())
+}
+
+/// Read the file of a file var, sending its value to the app if the content changed since it was last read.
+async fn update_file_var(
+    evt_send: &UnboundedSender<DaemonCommand>,
+    var: &FileScriptVar,
+    meta: &mut ScriptVarMeta,
+    last_content: &mut Option<String>,
+) -> Result<()> {
+    let content = tokio::fs::read_to_string(&var.path).await.with_context(|| format!("Failed to read {}", var.path));
+    match &content {
+        Ok(content) if last_content.as_ref() == Some(content) => return Ok(()),
+        Ok(content) => *last_content = Some(content.clone()),
+        // forget the content, such that the error is cleared once the file can be read again, even if it didn't change
+        Err(_) => *last_content = None,
+    }
+    let value = content.and_then(|content| {
+        let content = if var.trim { content.trim() } else { content.strip_suffix('\n').unwrap_or(&content) };
+        if var.json {
+            let json: serde_json::Value =
+                serde_json::from_str(content).with_context(|| format!("Failed to parse {} as json", var.path))?;
+            Ok(DynVal::from(json))
+        } else {
+            Ok(DynVal::from_string(content.to_string()))
+        }
+    });
+    match value {
+        Ok(value) => {
+            meta.update_succeeded();
+            evt_send
+                .send(DaemonCommand::UpdateVars(vec![(var.name.clone(), value), (meta_var_name(&var.name), meta.to_dynval())]))?
+        }
+        Err(err) => {
+            meta.update_failed(&err);
+            evt_send.send(DaemonCommand::UpdateVars(vec![(meta_var_name(&var.name), meta.to_dynval())]))?;
+            crate::error_handling_ctx::print_error(err);
+        }
+    }
+    Ok(())
+}
+
+struct SocketVarHandler {
+    evt_send: UnboundedSender<DaemonCommand>,
+    socket_handles: HashMap<VarName, CancellationToken>,
+}
+
+impl SocketVarHandler {
+    fn new(evt_send: UnboundedSender<DaemonCommand>) -> Result<Self> {
+        let handler = SocketVarHandler { evt_send, socket_handles: HashMap::new() };
+        Ok(handler)
+    }
+
+    fn start(&mut self, var: SocketScriptVar) {
+        if self.socket_handles.contains_key(&var.name) {
+            return;
+        }
+
+        log::debug!("starting socket var {}", &var.name);
+        let cancellation_token = CancellationToken::new();
+        self.socket_handles.insert(var.name.clone(), cancellation_token.clone());
+        let evt_send = self.evt_send.clone();
+        tokio::spawn(async move {
+            let mut meta = ScriptVarMeta::default();
+            let mut delay = var.reconnect_delay;
+            loop {
+                let connected_at = std::time::Instant::now();
+                match run_socket_connection(&var, &mut meta, &evt_send, &cancellation_token).await {
+                    // The variable was stopped or eww is exiting
+                    Ok(false) => break,
+                    Ok(true) => meta.error = Some("The connection was closed".to_string()),
+                    Err(err) => {
+                        log::warn!("Error in the connection of socket var {}: {:?}", &var.name, err);
+                        meta.update_failed(&err);
+                    }
+                }
+                meta.stale = true;
+                send_listen_var_meta(&evt_send, &var.name, &meta);
+
+                // A connection that stayed open for a while worked fine, so the next attempt can happen quickly again.
+                if connected_at.elapsed() >= var.max_reconnect_delay {
+                    delay = var.reconnect_delay;
+                }
+                log::info!("reconnecting socket var {} in {:?}", &var.name, delay);
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+                    Ok(()) = crate::application_lifecycle::recv_exit() => break,
+                    _ = tokio::time::sleep(delay) => {}
+                }
+                delay = std::cmp::min(delay * 2, var.max_reconnect_delay);
+            }
+        });
+    }
+
+    fn stop_for_variable(&mut self, name: &VarName) {
+        if let Some(token) = self.socket_handles.remove(name) {
+            log::debug!("stopped socket var {}", name);
+            token.cancel();
+        }
+    }
+
+    fn stop_all(&mut self) {
+        self.socket_handles.drain().for_each(|(_, token)| token.cancel());
+    }
+}
+
+impl Drop for SocketVarHandler {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
+/// Connect to the socket of a socket var, sending every message it receives to the app until the connection is closed
+/// or the variable is stopped. Returns whether the connection was closed by the other side.
+async fn run_socket_connection(
+    var: &SocketScriptVar,
+    meta: &mut ScriptVarMeta,
+    evt_send: &UnboundedSender<DaemonCommand>,
+    cancellation_token: &CancellationToken,
+) -> Result<bool> {
+    let mut stream =
+        tokio::net::UnixStream::connect(&var.path).await.with_context(|| format!("Failed to connect to {}", var.path))?;
+    if let Some(request) = &var.request {
+        stream.write_all(format!("{}\n", request).as_bytes()).await?;
+    }
+    meta.stale = false;
+    meta.error = None;
+    send_listen_var_meta(evt_send, &var.name, meta);
+    let mut records = RecordSplitter::new(&var.framing)?;
+    let mut read_buffer = [0; 4096];
+    crate::loop_select_exiting! {
+        _ = cancellation_token.cancelled() => return Ok(false),
+        read = stream.read(&mut read_buffer) => {
+            let read = read?;
+            if read == 0 {
+                break;
+            }
+            for new_value in records.push(&read_buffer[..read]) {
+                send_listen_var_value(evt_send, &var.name, var.transform.as_ref(), meta, new_value)?;
+            }
+        }
+    }
+    for new_value in records.finish() {
+        send_listen_var_value(evt_send, &var.name, var.transform.as_ref(), meta, new_value)?;
+    }
+    Ok(true)
+}
+
 async fn terminate_handle(mut child: tokio::process::Child) {
     if let Some(id) = child.id() {
         let _ = signal::killpg(Pid::from_raw(id as i32), signal::SIGTERM);
@@ -1,22 +1,24 @@
 use crate::{
-    app::{self, DaemonCommand},
-    config, daemon_response, error_handling_ctx, ipc_server, script_var_handler,
-    state::scope_graph::ScopeGraph,
-    util, EwwPaths,
+    app, config, daemon_response, error_handling_ctx, ipc_server, script_var_handler, state::scope_graph::ScopeGraph, util,
+    EwwPaths,
 };
 use anyhow::*;
 
 use std::{cell::RefCell, collections::{HashMap, HashSet}, os::unix::io::AsRawFd, path::Path, rc::Rc, sync::{atomic::Ordering, Arc}};
 use tokio::sync::mpsc::*;
 
-pub fn initialize_server(paths: EwwPaths, action: Option<DaemonCommand>, should_daemonize: bool) -> Result<ForkResult> {
+pub fn initialize_server(paths: EwwPaths, should_daemonize: bool) -> Result<ForkResult> {
     let (ui_send, mut ui_recv) = tokio::sync::mpsc::unbounded_channel();
 
     std::env::set_current_dir(&paths.get_config_dir())
         .with_context(|| format!("Failed to change working directory to {}", paths.get_config_dir().display()))?;
+    // inherited by every script eww runs
+    std::env::set_var("EWW_CONFIG_DIR", paths.get_config_dir());
 
     log::info!("Loading paths: {}", &paths);
 
+    config::inbuilt::register_inbuilt_functions();
+
     let read_config = config::read_from_file(&paths.get_yuck_path());
 
     let eww_config = match read_config {
@@ -54,20 +56,26 @@ pub fn initialize_server(paths: EwwPaths, action: Option<DaemonCommand>, should_
     gtk::init()?;
 
     log::debug!("Initializing script var handler");
-    let script_var_handler = script_var_handler::init(ui_send.clone());
+    let script_var_handler = script_var_handler::init(ui_send.clone(), eww_config.get_magic_vars().clone());
 
     let (scope_graph_evt_send, mut scope_graph_evt_recv) = tokio::sync::mpsc::unbounded_channel();
 
+    let mut scope_graph = ScopeGraph::from_global_vars(eww_config.generate_initial_state()?, scope_graph_evt_send);
+    if let Err(err) = scope_graph.register_derived_globals(eww_config.get_derived_globals().clone()) {
+        error_handling_ctx::print_error(err);
+    }
+
+    let initialized_inbuilt_vars = eww_config.inbuilt_vars_used_by_globals();
     let mut app = app::App {
-        scope_graph: Rc::new(RefCell::new(ScopeGraph::from_global_vars(
-            eww_config.generate_initial_state()?,
-            scope_graph_evt_send,
-        ))),
+        scope_graph: Rc::new(RefCell::new(scope_graph)),
         eww_config,
         open_windows: HashMap::new(),
         failed_windows: HashSet::new(),
         css_provider: gtk::CssProvider::new(),
         script_var_handler,
+        listen_process_states: HashMap::new(),
+        initialized_inbuilt_vars,
+        subscriptions: Vec::new(),
         app_evt_send: ui_send.clone(),
         paths,
     };
@@ -84,11 +92,6 @@ pub fn initialize_server(paths: EwwPaths, action: Option<DaemonCommand>, should_
     init_async_part(app.paths.clone(), ui_send);
 
     glib::MainContext::default().spawn_local(async move {
-        // if an action was given to the daemon initially, execute it first.
-        if let Some(action) = action {
-            app.handle_command(action);
-        }
-
         loop {
             tokio::select! {
                 Some(scope_graph_evt) = scope_graph_evt_recv.recv() => {
@@ -185,10 +188,15 @@ async fn run_filewatch<P: AsRef<Path>>(config_dir: P, evt_send: UnboundedSender<
                 tokio::time::sleep(std::time::Duration::from_millis(50)).await;
                 evt_send.send(app::DaemonCommand::ReloadConfigAndCss(daemon_resp_sender))?;
                 tokio::spawn(async move {
-                    match daemon_resp_response.recv().await {
-                        Some(daemon_response::DaemonResponse::Success(_)) => log::info!("Reloaded config successfully"),
-                        Some(daemon_response::DaemonResponse::Failure(e)) => eprintln!("{}", e),
-                        None => log::error!("No response to reload configuration-reload request"),
+                    loop {
+                        match daemon_resp_response.recv().await {
+                            // progress messages are sent ahead of the actual response
+                            Some(daemon_response::DaemonResponse::Progress(_)) => continue,
+                            Some(daemon_response::DaemonResponse::Failure(e)) => eprintln!("{}", e),
+                            Some(_) => log::info!("Reloaded config successfully"),
+                            None => log::error!("No response to reload configuration-reload request"),
+                        }
+                        break;
                     }
                 });
             }
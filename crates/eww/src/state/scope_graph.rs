@@ -5,7 +5,8 @@ use std::{
 
 use anyhow::*;
 use eww_shared_util::{AttrName, VarName};
-use simplexpr::{dynval::DynVal, SimplExpr};
+use itertools::Itertools;
+use simplexpr::{ast::var_name_of_meta_var, dynval::DynVal, SimplExpr};
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::error_handling_ctx;
@@ -58,6 +59,12 @@ pub enum ScopeGraphEvent {
 pub struct ScopeGraph {
     pub(self) graph: internal::ScopeGraphInternal,
     pub root_index: ScopeIndex,
+    /// Global variables whose values are computed from other globals (`defexpr`s).
+    /// Their values are stored in the root scope, and recalculated whenever a variable they reference changes.
+    /// They are kept in the order they were registered in, such that each one comes after the ones it references.
+    derived_globals: Vec<(VarName, SimplExpr)>,
+    /// The global variables whose value changed since [`Self::take_changed_globals`] was last called.
+    changed_globals: Vec<VarName>,
     // TODO this should be factored out, it doesn't really belong into this module / struct.
     pub event_sender: UnboundedSender<ScopeGraphEvent>,
 }
@@ -75,13 +82,73 @@ impl ScopeGraph {
         if let Some(scope) = graph.scope_at_mut(root_index) {
             scope.node_index = root_index;
         }
-        Self { graph, root_index, event_sender }
+        Self { graph, root_index, derived_globals: Vec::new(), changed_globals: Vec::new(), event_sender }
+    }
+
+    /// Add global variables that are computed from other globals.
+    /// Every expression may only reference derived globals that are given before it.
+    /// Expressions that can't be evaluated yet start out as an empty string, and are still recalculated once their inputs change.
+    pub fn register_derived_globals(&mut self, derived_globals: Vec<(VarName, SimplExpr)>) -> Result<()> {
+        for (name, expr) in derived_globals {
+            let value = self.evaluate_simplexpr_in_scope(self.root_index, &expr).unwrap_or_else(|err| {
+                error_handling_ctx::print_error(err.context(format!("Failed to compute the initial value of {}", name)));
+                DynVal::from("")
+            });
+            self.graph.scope_at_mut(self.root_index).context("No root scope in graph")?.data.insert(name.clone(), value);
+            self.derived_globals.push((name, expr));
+        }
+        Ok(())
     }
 
     pub fn update_global_value(&mut self, var_name: &VarName, value: DynVal) -> Result<()> {
+        if self.is_derived_global(var_name) {
+            bail!("The value of {} is computed from an expression, and can't be changed directly", var_name);
+        }
         self.update_value(self.root_index, var_name, value)
     }
 
+    /// Update several global variables at once.
+    /// Everything that depends on more than one of them is only updated once, and already sees all of the new values.
+    /// All of the variables are checked before any of them is changed: if one of them doesn't exist or is computed from an
+    /// expression, none of them are. Errors while updating the widgets and derived globals afterwards are not rolled back.
+    pub fn update_global_values(&mut self, values: Vec<(VarName, DynVal)>) -> Result<()> {
+        let root_scope = self.graph.scope_at(self.root_index).context("No root scope in graph")?;
+        for (var_name, _) in &values {
+            if self.is_derived_global(var_name) {
+                bail!("The value of {} is computed from an expression, and can't be changed directly", var_name);
+            }
+            if !root_scope.data.contains_key(var_name) {
+                bail!("Variable {} not in scope", var_name);
+            }
+        }
+
+        let root_scope = self.graph.scope_at_mut(self.root_index).context("No root scope in graph")?;
+        let mut changed_vars = Vec::new();
+        for (var_name, new_value) in values {
+            if let Some(entry) = root_scope.data.get_mut(&var_name) {
+                if *entry != new_value {
+                    *entry = new_value;
+                    changed_vars.push(var_name);
+                }
+            }
+        }
+        let changed_vars = changed_vars.into_iter().unique().collect_vec();
+        self.changed_globals.extend(changed_vars.iter().cloned());
+
+        self.notify_values_changed(self.root_index, &changed_vars)?;
+
+        #[cfg(debug_assertions)]
+        self.graph.validate()?;
+
+        Ok(())
+    }
+
+    /// Get the global variables whose value changed since this was last called, including the `defexpr`s that were recalculated.
+    /// A variable that changed several times is only listed once.
+    pub fn take_changed_globals(&mut self) -> Vec<VarName> {
+        std::mem::take(&mut self.changed_globals).into_iter().unique().collect()
+    }
+
     pub fn handle_scope_graph_event(&mut self, evt: ScopeGraphEvent) {
         match evt {
             ScopeGraphEvent::RemoveScope(scope_index) => {
@@ -93,6 +160,8 @@ impl ScopeGraph {
     /// Fully reinitialize the scope graph. Completely removes all state, and resets the ScopeIndex uniqueness.
     pub fn clear(&mut self, vars: HashMap<VarName, DynVal>) {
         self.graph.clear();
+        self.derived_globals.clear();
+        self.changed_globals.clear();
         let root_index = self.graph.add_scope(Scope {
             name: "global".to_string(),
             ancestor: None,
@@ -119,7 +188,25 @@ impl ScopeGraph {
     }
 
     pub fn currently_used_globals(&self) -> HashSet<VarName> {
-        self.variables_used_in_self_or_subscopes_of(self.root_index)
+        self.with_inputs_of_derived_globals(self.variables_used_in_self_or_subscopes_of(self.root_index))
+    }
+
+    /// Extend the given set of global variables by all the variables the derived globals within it are computed from,
+    /// as well as the script vars whose metadata is used.
+    pub fn with_inputs_of_derived_globals(&self, mut variables: HashSet<VarName>) -> HashSet<VarName> {
+        let mut pending = variables.iter().cloned().collect::<Vec<_>>();
+        while let Some(var_name) = pending.pop() {
+            if let Some(script_var_name) = var_name_of_meta_var(&var_name) {
+                variables.insert(script_var_name);
+            } else if let Some((_, expr)) = self.derived_globals.iter().find(|(name, _)| *name == var_name) {
+                for input in expr.collect_var_refs() {
+                    if variables.insert(input.clone()) {
+                        pending.push(input);
+                    }
+                }
+            }
+        }
+        variables
     }
 
     pub fn currently_unused_globals(&self) -> HashSet<VarName> {
@@ -248,6 +335,9 @@ impl ScopeGraph {
             .with_context(|| format!("Variable {} not scope", updated_var))?;
 
         if let Some(entry) = self.graph.scope_at_mut(scope_index).and_then(|scope| scope.data.get_mut(updated_var)) {
+            if scope_index == self.root_index && *entry != new_value {
+                self.changed_globals.push(updated_var.clone());
+            }
             *entry = new_value;
         }
 
@@ -261,9 +351,25 @@ impl ScopeGraph {
 
     /// Notify a scope that a value has been changed. This triggers the listeners and notifies further subscopes scopes recursively.
     pub fn notify_value_changed(&mut self, scope_index: ScopeIndex, updated_var: &VarName) -> Result<()> {
-        // Update scopes that reference the changed variable in their attribute expressions.
-        let edges: Vec<(ScopeIndex, internal::ProvidedAttr)> =
-            self.graph.scopes_getting_attr_using(scope_index, updated_var).into_iter().map(|(a, b)| (a, b.clone())).collect();
+        self.notify_values_changed(scope_index, std::slice::from_ref(updated_var))
+    }
+
+    /// Notify a scope that several values have been changed at once, like [`Self::notify_value_changed`].
+    /// Attributes, listeners and derived globals that use more than one of the values are only updated once.
+    pub fn notify_values_changed(&mut self, scope_index: ScopeIndex, updated_vars: &[VarName]) -> Result<()> {
+        // Recalculate the derived globals first, such that everything below already sees their new values,
+        // and is notified about them together with the variables they are computed from.
+        let updated_vars =
+            if scope_index == self.root_index { self.recalculate_derived_globals(updated_vars) } else { updated_vars.to_vec() };
+        let updated_vars = updated_vars.as_slice();
+
+        // Update scopes that reference the changed variables in their attribute expressions.
+        let edges: Vec<(ScopeIndex, internal::ProvidedAttr)> = updated_vars
+            .iter()
+            .flat_map(|updated_var| self.graph.scopes_getting_attr_using(scope_index, updated_var))
+            .map(|(a, b)| (a, b.clone()))
+            .unique_by(|(referencing_scope, edge)| (*referencing_scope, edge.attr_name.clone()))
+            .collect();
         for (referencing_scope, edge) in edges {
             if let Err(err) = self.evaluate_simplexpr_in_scope(scope_index, &edge.expression).and_then(|updated_attr_value| {
                 self.update_value(referencing_scope, edge.attr_name.to_var_name_ref(), updated_attr_value)
@@ -273,25 +379,73 @@ impl ScopeGraph {
         }
 
         // Trigger the listeners from this scope
-        self.call_listeners_in_scope(scope_index, updated_var)?;
-
-        // Now find subscopes that reference this variable
-        let affected_subscopes = self.graph.subscopes_referencing(scope_index, updated_var);
-        for affected_subscope in affected_subscopes {
-            self.notify_value_changed(affected_subscope, updated_var)?;
+        self.call_listeners_in_scope(scope_index, updated_vars)?;
+
+        // Now find subscopes that reference these variables
+        let affected_subscopes = updated_vars
+            .iter()
+            .flat_map(|updated_var| {
+                self.graph
+                    .subscopes_referencing(scope_index, updated_var)
+                    .into_iter()
+                    .map(move |subscope| (subscope, updated_var))
+            })
+            .into_group_map();
+        for (affected_subscope, vars) in affected_subscopes {
+            let vars = vars.into_iter().cloned().collect_vec();
+            self.notify_values_changed(affected_subscope, &vars)?;
         }
         Ok(())
     }
 
-    /// Call all of the listeners in a given `scope_index` that are affected by a change to the `updated_var`.
-    fn call_listeners_in_scope(&mut self, scope_index: ScopeIndex, updated_var: &VarName) -> Result<()> {
-        let scope = self.graph.scope_at(scope_index).context("Scope not in graph")?;
-        if let Some(triggered_listeners) = scope.listeners.get(updated_var) {
-            for listener in triggered_listeners.clone() {
-                let required_variables = self.lookup_variables_in_scope(scope_index, &listener.needed_variables)?;
-                if let Err(err) = (*listener.f)(self, required_variables).context("Error while updating UI after state change") {
-                    error_handling_ctx::print_error(err);
+    fn is_derived_global(&self, var_name: &VarName) -> bool {
+        self.derived_globals.iter().any(|(name, _)| name == var_name)
+    }
+
+    /// Recalculate the derived globals that use any of the `updated_vars`, directly or through other derived globals,
+    /// without notifying anything about their new values yet.
+    /// Returns the `updated_vars` together with all of the derived globals whose value changed.
+    fn recalculate_derived_globals(&mut self, updated_vars: &[VarName]) -> Vec<VarName> {
+        let mut changed_vars = updated_vars.to_vec();
+        // derived globals only reference the ones before them, so a single pass sees every change they depend on.
+        for index in 0..self.derived_globals.len() {
+            let (name, expr) = &self.derived_globals[index];
+            if !changed_vars.iter().any(|changed_var| expr.references_var(changed_var)) {
+                continue;
+            }
+            let name = name.clone();
+            match self.evaluate_simplexpr_in_scope(self.root_index, expr) {
+                Ok(new_value) => {
+                    let entry = self.graph.scope_at_mut(self.root_index).and_then(|scope| scope.data.get_mut(&name));
+                    if let Some(entry) = entry {
+                        if *entry != new_value {
+                            *entry = new_value;
+                            self.changed_globals.push(name.clone());
+                            changed_vars.push(name);
+                        }
+                    }
                 }
+                Err(err) => error_handling_ctx::print_error(err),
+            }
+        }
+        changed_vars
+    }
+
+    /// Call all of the listeners in a given `scope_index` that are affected by a change to any of the `updated_vars`,
+    /// each of them only once.
+    fn call_listeners_in_scope(&mut self, scope_index: ScopeIndex, updated_vars: &[VarName]) -> Result<()> {
+        let scope = self.graph.scope_at(scope_index).context("Scope not in graph")?;
+        let triggered_listeners = updated_vars
+            .iter()
+            .filter_map(|updated_var| scope.listeners.get(updated_var))
+            .flatten()
+            .unique_by(|listener| Rc::as_ptr(listener))
+            .cloned()
+            .collect_vec();
+        for listener in triggered_listeners {
+            let required_variables = self.lookup_variables_in_scope(scope_index, &listener.needed_variables)?;
+            if let Err(err) = (*listener.f)(self, required_variables).context("Error while updating UI after state change") {
+                error_handling_ctx::print_error(err);
             }
         }
         Ok(())
@@ -366,7 +520,7 @@ mod internal {
     use super::{super::one_to_n_elements_map::OneToNElementsMap, *};
 
     /// a --provides attribute [`Self::attr_name`] calculated via [`Self::expression`] to--> b
-    #[derive(Debug, Eq, PartialEq, Clone)]
+    #[derive(Debug, PartialEq, Clone)]
     pub struct ProvidedAttr {
         pub attr_name: AttrName,
         pub expression: SimplExpr,
@@ -647,6 +801,151 @@ mod test {
         assert_eq!(scope_graph.lookup_variable_in_scope(widget_no_parent_scope, &"global".into()), None);
     }
 
+    #[test]
+    fn test_derived_variables_update() {
+        let globals = hashmap! {
+            "count".into() => "1".into(),
+        };
+
+        let (send, _recv) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut scope_graph = ScopeGraph::from_global_vars(globals, send);
+        let root_scope = scope_graph.root_index;
+
+        let let_scope = scope_graph
+            .register_new_scope(
+                "let".to_string(),
+                Some(root_scope),
+                root_scope,
+                hashmap! { "doubled".into() => simplexpr::parse_string(0, 0, "count * 2").unwrap() },
+            )
+            .unwrap();
+        scope_graph.register_scope_referencing_variable(let_scope, "count".into()).unwrap();
+        assert_eq!(scope_graph.lookup_variable_in_scope(let_scope, &"doubled".into()).unwrap(), &DynVal::from(2));
+
+        scope_graph.update_global_value(&"count".into(), DynVal::from(21)).unwrap();
+        assert_eq!(scope_graph.lookup_variable_in_scope(let_scope, &"doubled".into()).unwrap(), &DynVal::from(42));
+        assert_eq!(scope_graph.lookup_variable_in_scope(let_scope, &"count".into()).unwrap(), &DynVal::from(21));
+    }
+
+    #[test]
+    fn test_derived_globals() {
+        let globals = hashmap! {
+            "count".into() => DynVal::from(1),
+        };
+
+        let (send, _recv) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut scope_graph = ScopeGraph::from_global_vars(globals, send);
+        scope_graph
+            .register_derived_globals(vec![
+                ("doubled".into(), simplexpr::parse_string(0, 0, "count * 2").unwrap()),
+                ("quadrupled".into(), simplexpr::parse_string(0, 0, "doubled * 2").unwrap()),
+            ])
+            .unwrap();
+        let root_scope = scope_graph.root_index;
+        assert_eq!(scope_graph.lookup_variable_in_scope(root_scope, &"quadrupled".into()).unwrap(), &DynVal::from(4));
+
+        scope_graph.update_global_value(&"count".into(), DynVal::from(2)).unwrap();
+        assert_eq!(scope_graph.lookup_variable_in_scope(root_scope, &"doubled".into()).unwrap(), &DynVal::from(4));
+        assert_eq!(scope_graph.lookup_variable_in_scope(root_scope, &"quadrupled".into()).unwrap(), &DynVal::from(8));
+        assert!(scope_graph.update_global_value(&"doubled".into(), DynVal::from(0)).is_err());
+
+        let widget_scope =
+            scope_graph.register_new_scope("widget".to_string(), Some(root_scope), root_scope, hashmap! {}).unwrap();
+        scope_graph.register_scope_referencing_variable(widget_scope, "quadrupled".into()).unwrap();
+        assert_eq!(scope_graph.currently_used_globals(), hashset!["quadrupled".into(), "doubled".into(), "count".into()]);
+    }
+
+    #[test]
+    fn test_failing_derived_globals_are_still_registered() {
+        let (send, _recv) = tokio::sync::mpsc::unbounded_channel();
+        let mut scope_graph = ScopeGraph::from_global_vars(hashmap! { "count".into() => DynVal::from(1) }, send);
+        scope_graph
+            .register_derived_globals(vec![
+                ("broken".into(), simplexpr::parse_string(0, 0, "missing * 2").unwrap()),
+                ("doubled".into(), simplexpr::parse_string(0, 0, "count * 2").unwrap()),
+            ])
+            .unwrap();
+        let root_scope = scope_graph.root_index;
+        assert_eq!(scope_graph.lookup_variable_in_scope(root_scope, &"broken".into()).unwrap(), &DynVal::from(""));
+
+        scope_graph.update_global_value(&"count".into(), DynVal::from(2)).unwrap();
+        assert_eq!(scope_graph.lookup_variable_in_scope(root_scope, &"doubled".into()).unwrap(), &DynVal::from(4));
+        assert!(scope_graph.update_global_value(&"broken".into(), DynVal::from(0)).is_err());
+    }
+
+    #[test]
+    fn test_changed_globals() {
+        let (send, _recv) = tokio::sync::mpsc::unbounded_channel();
+        let globals = hashmap! { "count".into() => DynVal::from(1), "other".into() => DynVal::from(0) };
+        let mut scope_graph = ScopeGraph::from_global_vars(globals, send);
+        scope_graph
+            .register_derived_globals(vec![("doubled".into(), simplexpr::parse_string(0, 0, "count * 2").unwrap())])
+            .unwrap();
+        scope_graph.take_changed_globals();
+
+        scope_graph.update_global_value(&"count".into(), DynVal::from(2)).unwrap();
+        scope_graph.update_global_value(&"count".into(), DynVal::from(3)).unwrap();
+        scope_graph.update_global_value(&"other".into(), DynVal::from(0)).unwrap();
+        assert_eq!(scope_graph.take_changed_globals(), vec![VarName::from("count"), VarName::from("doubled")]);
+        assert_eq!(scope_graph.take_changed_globals(), Vec::<VarName>::new());
+    }
+
+    #[test]
+    fn test_update_global_values() {
+        let (send, _recv) = tokio::sync::mpsc::unbounded_channel();
+        let globals = hashmap! { "a".into() => DynVal::from(1), "b".into() => DynVal::from(2) };
+        let mut scope_graph = ScopeGraph::from_global_vars(globals, send);
+        scope_graph.register_derived_globals(vec![("sum".into(), simplexpr::parse_string(0, 0, "a + b").unwrap())]).unwrap();
+        let root_scope = scope_graph.root_index;
+        let widget_scope =
+            scope_graph.register_new_scope("widget".to_string(), Some(root_scope), root_scope, hashmap! {}).unwrap();
+
+        let seen_values = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let seen_by_listener = seen_values.clone();
+        let listener = Listener {
+            needed_variables: vec!["a".into(), "b".into(), "sum".into()],
+            f: Box::new(move |_, values| {
+                seen_by_listener.borrow_mut().push((values["a"].clone(), values["b"].clone(), values["sum"].clone()));
+                Ok(())
+            }),
+        };
+        scope_graph.register_listener(widget_scope, listener).unwrap();
+
+        // the listener is called once, and already sees the recalculated sum
+        scope_graph.update_global_values(vec![("a".into(), DynVal::from(10)), ("b".into(), DynVal::from(20))]).unwrap();
+        assert_eq!(
+            *seen_values.borrow(),
+            vec![(DynVal::from(1), DynVal::from(2), DynVal::from(3)), (DynVal::from(10), DynVal::from(20), DynVal::from(30))]
+        );
+        assert_eq!(scope_graph.take_changed_globals(), vec![VarName::from("a"), VarName::from("b"), VarName::from("sum")]);
+
+        // nothing is changed if any of the variables can't be updated
+        assert!(scope_graph
+            .update_global_values(vec![("a".into(), DynVal::from(0)), ("missing".into(), DynVal::from(0))])
+            .is_err());
+        assert!(scope_graph.update_global_values(vec![("a".into(), DynVal::from(0)), ("sum".into(), DynVal::from(0))]).is_err());
+        assert_eq!(scope_graph.lookup_variable_in_scope(root_scope, &"a".into()).unwrap(), &DynVal::from(10));
+        assert_eq!(seen_values.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_meta_vars_use_their_script_var() {
+        let (send, _recv) = tokio::sync::mpsc::unbounded_channel();
+        let mut scope_graph = ScopeGraph::from_global_vars(hashmap! { "battery.meta".into() => DynVal::from("{}") }, send);
+        scope_graph
+            .register_derived_globals(vec![(
+                "battery-stale".into(),
+                simplexpr::parse_string(0, 0, "meta(battery).stale").unwrap(),
+            )])
+            .unwrap();
+        assert_eq!(
+            scope_graph.with_inputs_of_derived_globals(hashset!["battery-stale".into()]),
+            hashset!["battery-stale".into(), "battery.meta".into(), "battery".into()]
+        );
+    }
+
     /// tests the following graph structure:
     /// ```
     ///              ┌───────────────────────────────────────────────────┐
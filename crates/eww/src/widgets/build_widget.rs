@@ -21,7 +21,7 @@ use crate::{
         scope::Listener,
         scope_graph::{ScopeGraph, ScopeGraphEvent, ScopeIndex},
     },
-    widgets::widget_definitions,
+    widgets::{widget_definitions, CommandContext},
 };
 
 use super::widget_definitions::{resolve_orientable_attrs, resolve_range_attrs, resolve_widget_attrs};
@@ -32,6 +32,7 @@ pub struct BuilderArgs<'a> {
     pub scope_graph: &'a mut ScopeGraph,
     pub unhandled_attrs: Vec<AttrName>,
     pub widget_defs: Rc<HashMap<String, WidgetDefinition>>,
+    pub command_context: Rc<CommandContext>,
     pub custom_widget_invocation: Option<Rc<CustomWidgetInvocation>>,
 }
 
@@ -44,10 +45,21 @@ pub struct BuilderArgs<'a> {
 pub fn build_gtk_widget(
     graph: &mut ScopeGraph,
     widget_defs: Rc<HashMap<String, WidgetDefinition>>,
+    command_context: Rc<CommandContext>,
     calling_scope: ScopeIndex,
     mut widget_use: WidgetUse,
     custom_widget_invocation: Option<Rc<CustomWidgetInvocation>>,
 ) -> Result<gtk::Widget> {
+    if widget_use.name == "let" {
+        return build_let_special_widget(
+            graph,
+            widget_defs,
+            command_context,
+            calling_scope,
+            widget_use,
+            custom_widget_invocation,
+        );
+    }
     if let Some(custom_widget) = widget_defs.clone().get(&widget_use.name) {
         let widget_use_attributes = custom_widget
             .expected_args
@@ -72,6 +84,7 @@ pub fn build_gtk_widget(
         let gtk_widget = build_gtk_widget(
             graph,
             widget_defs,
+            command_context,
             new_scope_index,
             custom_widget.widget.clone(),
             Some(Rc::new(CustomWidgetInvocation { scope: calling_scope, children: widget_use.children })),
@@ -84,7 +97,7 @@ pub fn build_gtk_widget(
         });
         Ok(gtk_widget)
     } else {
-        build_builtin_gtk_widget(graph, widget_defs, calling_scope, widget_use, custom_widget_invocation)
+        build_builtin_gtk_widget(graph, widget_defs, command_context, calling_scope, widget_use, custom_widget_invocation)
     }
 }
 
@@ -99,6 +112,7 @@ pub fn build_gtk_widget(
 fn build_builtin_gtk_widget(
     graph: &mut ScopeGraph,
     widget_defs: Rc<HashMap<String, WidgetDefinition>>,
+    command_context: Rc<CommandContext>,
     calling_scope: ScopeIndex,
     widget_use: WidgetUse,
     custom_widget_invocation: Option<Rc<CustomWidgetInvocation>>,
@@ -109,6 +123,7 @@ fn build_builtin_gtk_widget(
         calling_scope,
         widget_use,
         widget_defs,
+        command_context,
         custom_widget_invocation,
     };
     let gtk_widget = widget_definitions::widget_use_to_gtk_widget(&mut bargs)?;
@@ -122,6 +137,7 @@ fn build_builtin_gtk_widget(
             populate_widget_children(
                 bargs.scope_graph,
                 bargs.widget_defs.clone(),
+                bargs.command_context.clone(),
                 calling_scope,
                 gtk_container,
                 bargs.widget_use.children.clone(),
@@ -154,6 +170,7 @@ fn build_builtin_gtk_widget(
 fn populate_widget_children(
     tree: &mut ScopeGraph,
     widget_defs: Rc<HashMap<String, WidgetDefinition>>,
+    command_context: Rc<CommandContext>,
     calling_scope: ScopeIndex,
     gtk_container: &gtk::Container,
     widget_use_children: Vec<WidgetUse>,
@@ -165,14 +182,21 @@ fn populate_widget_children(
             build_children_special_widget(
                 tree,
                 widget_defs.clone(),
+                command_context.clone(),
                 calling_scope,
                 child,
                 gtk_container,
                 custom_widget_invocation,
             )?;
         } else {
-            let child_widget =
-                build_gtk_widget(tree, widget_defs.clone(), calling_scope, child, custom_widget_invocation.clone())?;
+            let child_widget = build_gtk_widget(
+                tree,
+                widget_defs.clone(),
+                command_context.clone(),
+                calling_scope,
+                child,
+                custom_widget_invocation.clone(),
+            )?;
             gtk_container.add(&child_widget);
         }
     }
@@ -186,6 +210,7 @@ fn populate_widget_children(
 fn build_children_special_widget(
     tree: &mut ScopeGraph,
     widget_defs: Rc<HashMap<String, WidgetDefinition>>,
+    command_context: Rc<CommandContext>,
     calling_scope: ScopeIndex,
     mut widget_use: WidgetUse,
     gtk_container: &gtk::Container,
@@ -213,6 +238,7 @@ fn build_children_special_widget(
                         let new_child_widget = build_gtk_widget(
                             tree,
                             widget_defs.clone(),
+                            command_context.clone(),
                             custom_widget_invocation.scope,
                             nth_child_widget_use.clone(),
                             None,
@@ -231,13 +257,51 @@ fn build_children_special_widget(
         )?;
     } else {
         for child in &custom_widget_invocation.children {
-            let child_widget = build_gtk_widget(tree, widget_defs.clone(), custom_widget_invocation.scope, child.clone(), None)?;
+            let child_widget = build_gtk_widget(
+                tree,
+                widget_defs.clone(),
+                command_context.clone(),
+                custom_widget_invocation.scope,
+                child.clone(),
+                None,
+            )?;
             gtk_container.add(&child_widget);
         }
     }
     Ok(())
 }
 
+/// Handle a use of the special `let` [`WidgetUse`].
+/// The bindings are registered as a new scope which inherits from the calling scope, and gets the values of the bindings
+/// provided by it. That way, the bindings get updated like the attributes of a custom widget whenever a variable they use changes.
+fn build_let_special_widget(
+    tree: &mut ScopeGraph,
+    widget_defs: Rc<HashMap<String, WidgetDefinition>>,
+    command_context: Rc<CommandContext>,
+    calling_scope: ScopeIndex,
+    mut widget_use: WidgetUse,
+    custom_widget_invocation: Option<Rc<CustomWidgetInvocation>>,
+) -> Result<gtk::Widget> {
+    assert_eq!(&widget_use.name, "let");
+
+    let bindings = widget_use
+        .attrs
+        .attrs
+        .iter()
+        .map(|(name, entry)| Ok((name.clone(), entry.value.as_simplexpr()?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+    let child = widget_use.children.pop().context("let expression has no child")?;
+
+    let let_scope_index = tree.register_new_scope("let".to_string(), Some(calling_scope), calling_scope, bindings)?;
+    let gtk_widget = build_gtk_widget(tree, widget_defs, command_context, let_scope_index, child, custom_widget_invocation)?;
+
+    let scope_graph_sender = tree.event_sender.clone();
+    gtk_widget.connect_destroy(move |_| {
+        let _ = scope_graph_sender.send(ScopeGraphEvent::RemoveScope(let_scope_index));
+    });
+    Ok(gtk_widget)
+}
+
 /// When a custom widget gets used, some context about that invocation needs to be
 /// remembered whilst building it's content. If the body of the custom widget uses a `children`
 /// widget, the children originally passed to the widget need to be set.
@@ -1,22 +1,64 @@
-use std::process::Command;
+use yuck::config::{script_var_definition::ScriptEnvironment, settings::PlaceholderMode};
+
+use crate::config::script_var;
 
 pub mod build_widget;
 pub mod circular_progressbar;
 pub mod def_widget_macro;
 pub mod graph;
+pub mod prop_types;
 pub mod widget_definitions;
 
-const CMD_STRING_PLACEHODLER: &str = "{}";
+/// Information about the window a widget was built in, which is passed on to the commands the widget runs.
+#[derive(Debug, Clone)]
+pub struct CommandContext {
+    pub window_name: String,
+    /// The index of the monitor the window was opened on, if one was selected
+    pub monitor: Option<i32>,
+    /// The shell, environment and working directory configured in `defsettings`
+    pub script_env: ScriptEnvironment,
+    pub placeholder_mode: PlaceholderMode,
+}
 
-/// Run a command that was provided as an attribute. This command may use a
-/// placeholder ('{}') which will be replaced by the value provided as `arg`
-pub(self) fn run_command<T: 'static + std::fmt::Display + Send + Sync>(timeout: std::time::Duration, cmd: &str, arg: T) {
+/// Run a command that was provided as an attribute.
+/// The command may use named placeholders like `{value}` or `{window}`, which are replaced by the shell-quoted values,
+/// or passed as environment variables like `EWW_VALUE` if configured with `:command-placeholders "env"`.
+/// `{value}` is the value provided as `arg`, and any further placeholders are given in `placeholders`.
+/// The unnamed placeholder `{}` is replaced by `arg` as is, without any quoting.
+pub(self) fn run_command<T: 'static + std::fmt::Display + Send + Sync>(
+    context: &CommandContext,
+    timeout: std::time::Duration,
+    cmd: &str,
+    arg: T,
+    placeholders: &[(&'static str, String)],
+) {
     use wait_timeout::ChildExt;
     let cmd = cmd.to_string();
+    let context = context.clone();
+    let mut placeholders = placeholders.to_vec();
     std::thread::spawn(move || {
-        let cmd = cmd.replace(CMD_STRING_PLACEHODLER, &format!("{}", arg));
+        let arg = format!("{}", arg);
+        placeholders.push(("value", arg.clone()));
+        placeholders.push(("window", context.window_name.clone()));
+        if let Some(monitor) = context.monitor {
+            placeholders.push(("monitor", monitor.to_string()));
+        }
+        let cmd = match context.placeholder_mode {
+            PlaceholderMode::Replace => fill_placeholders(&cmd, &arg, &placeholders),
+            PlaceholderMode::Env => cmd,
+        };
         log::debug!("Running command from widget: {}", cmd);
-        let child = Command::new("/bin/sh").arg("-c").arg(&cmd).spawn();
+        let mut command = script_var::script_command(&cmd, &context.script_env);
+        command.env("EWW_WINDOW", &context.window_name);
+        if let Some(monitor) = context.monitor {
+            command.env("EWW_MONITOR", monitor.to_string());
+        }
+        if context.placeholder_mode == PlaceholderMode::Env {
+            for (name, value) in &placeholders {
+                command.env(format!("EWW_{}", name.to_uppercase()), value);
+            }
+        }
+        let child = command.spawn();
         match child {
             Ok(mut child) => match child.wait_timeout(timeout) {
                 // child timed out
@@ -32,3 +74,46 @@ pub(self) fn run_command<T: 'static + std::fmt::Display + Send + Sync>(timeout:
         }
     });
 }
+
+/// Replace the named placeholders in a command by the shell-quoted values, and `{}` by `unnamed_value` as is.
+/// Anything in braces that isn't a known placeholder is left untouched, and so is shell syntax like `${HOME}` or `${value}`.
+fn fill_placeholders(cmd: &str, unnamed_value: &str, placeholders: &[(&str, String)]) -> String {
+    regex!(r"(\$?)\{(\w*)\}")
+        .replace_all(cmd, |captures: &regex::Captures| {
+            if !captures[1].is_empty() {
+                return captures[0].to_string();
+            }
+            let name = &captures[2];
+            if name.is_empty() {
+                return unnamed_value.to_string();
+            }
+            match placeholders.iter().find(|(placeholder, _)| *placeholder == name) {
+                Some((_, value)) => shell_quote(value),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Quote a value such that the shell reads it as a single word, without expanding anything within it.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fill_placeholders() {
+        let placeholders = [("value", "it's 5".to_string()), ("button", "1".to_string())];
+        assert_eq!(
+            fill_placeholders("echo {value} {button} {} {unknown} ${HOME}", "raw", &placeholders),
+            r"echo 'it'\''s 5' '1' raw {unknown} ${HOME}"
+        );
+        assert_eq!(fill_placeholders("echo {value}", "", &[("value", "$(rm -rf ~)".to_string())]), "echo '$(rm -rf ~)'");
+        assert_eq!(fill_placeholders("echo ${value} ${} {value}", "raw", &placeholders), r"echo ${value} ${} 'it'\''s 5'");
+        // the quotes are added even within double quotes, where the shell keeps them as part of the value
+        assert_eq!(fill_placeholders(r#"echo "button {button}""#, "", &placeholders), r#"echo "button '1'""#);
+    }
+}
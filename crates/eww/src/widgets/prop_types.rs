@@ -0,0 +1,203 @@
+//! The types of the properties of all builtin widgets, used to type check widget uses when loading the config.
+//!
+//! This table has to be kept in sync with the `def_widget!` invocations in [`super::widget_definitions`].
+//! The tests at the bottom of this file fail whenever a widget or property is missing from it.
+//! Durations and lists are read from strings, and are therefore typed as such.
+
+use std::collections::HashMap;
+
+use eww_shared_util::AttrName;
+use once_cell::sync::Lazy;
+use simplexpr::dynval::ValueType;
+
+type Props = &'static [(&'static str, ValueType)];
+
+/// The properties that apply to all widgets.
+const WIDGET_PROPS: Props = &[
+    ("class", ValueType::String),
+    ("valign", ValueType::String),
+    ("halign", ValueType::String),
+    ("vexpand", ValueType::Bool),
+    ("hexpand", ValueType::Bool),
+    ("width", ValueType::Number),
+    ("height", ValueType::Number),
+    ("active", ValueType::Bool),
+    ("tooltip", ValueType::String),
+    ("visible", ValueType::Bool),
+    ("style", ValueType::String),
+];
+
+const RANGE_PROPS: Props = &[
+    ("value", ValueType::Number),
+    ("min", ValueType::Number),
+    ("max", ValueType::Number),
+    ("timeout", ValueType::String),
+    ("onchange", ValueType::String),
+];
+
+const ORIENTABLE_PROPS: Props = &[("orientation", ValueType::String)];
+
+/// All builtin widgets, together with the properties they support in addition to [`WIDGET_PROPS`].
+const BUILTIN_WIDGETS: &[(&str, &[Props])] = &[
+    ("box", &[&[("spacing", ValueType::Number), ("orientation", ValueType::String), ("space-evenly", ValueType::Bool)]]),
+    ("centerbox", &[ORIENTABLE_PROPS]),
+    (
+        "eventbox",
+        &[&[
+            ("timeout", ValueType::String),
+            ("onscroll", ValueType::String),
+            ("onhover", ValueType::String),
+            ("onhoverlost", ValueType::String),
+            ("cursor", ValueType::String),
+        ]],
+    ),
+    (
+        "circular-progress",
+        &[&[
+            ("value", ValueType::Number),
+            ("start-at", ValueType::Number),
+            ("thickness", ValueType::Number),
+            ("clockwise", ValueType::Bool),
+        ]],
+    ),
+    (
+        "graph",
+        &[&[
+            ("value", ValueType::Number),
+            ("thickness", ValueType::Number),
+            ("time-range", ValueType::String),
+            ("min", ValueType::Number),
+            ("max", ValueType::Number),
+            ("dynamic", ValueType::Bool),
+            ("line-style", ValueType::String),
+        ]],
+    ),
+    (
+        "scale",
+        &[
+            RANGE_PROPS,
+            ORIENTABLE_PROPS,
+            &[("flipped", ValueType::Bool), ("marks", ValueType::String), ("draw-value", ValueType::Bool)],
+        ],
+    ),
+    ("progress", &[&[("flipped", ValueType::Bool), ("value", ValueType::Number), ("orientation", ValueType::String)]]),
+    ("image", &[&[("path", ValueType::String), ("image-width", ValueType::Number), ("image-height", ValueType::Number)]]),
+    (
+        "button",
+        &[&[
+            ("timeout", ValueType::String),
+            ("onclick", ValueType::String),
+            ("onmiddleclick", ValueType::String),
+            ("onrightclick", ValueType::String),
+        ]],
+    ),
+    (
+        "label",
+        &[&[
+            ("text", ValueType::String),
+            ("limit-width", ValueType::Number),
+            ("show-truncated", ValueType::Bool),
+            ("markup", ValueType::String),
+            ("wrap", ValueType::Bool),
+            ("angle", ValueType::Number),
+        ]],
+    ),
+    ("literal", &[&[("content", ValueType::String)]]),
+    ("input", &[&[("value", ValueType::String), ("timeout", ValueType::String), ("onchange", ValueType::String)]]),
+    (
+        "calendar",
+        &[&[
+            ("day", ValueType::Number),
+            ("month", ValueType::Number),
+            ("year", ValueType::Number),
+            ("show-details", ValueType::Bool),
+            ("show-heading", ValueType::Bool),
+            ("show-day-names", ValueType::Bool),
+            ("show-week-numbers", ValueType::Bool),
+            ("timeout", ValueType::String),
+            ("onclick", ValueType::String),
+        ]],
+    ),
+    ("color-button", &[&[("use-alpha", ValueType::Bool), ("timeout", ValueType::String), ("onchange", ValueType::String)]]),
+    ("expander", &[&[("name", ValueType::String), ("expanded", ValueType::Bool)]]),
+    ("color-chooser", &[&[("use-alpha", ValueType::Bool), ("timeout", ValueType::String), ("onchange", ValueType::String)]]),
+    ("combo-box-text", &[&[("items", ValueType::String), ("timeout", ValueType::String), ("onchange", ValueType::String)]]),
+    ("checkbox", &[&[("timeout", ValueType::String), ("onchecked", ValueType::String), ("onunchecked", ValueType::String)]]),
+    ("revealer", &[&[("transition", ValueType::String), ("reveal", ValueType::Bool), ("duration", ValueType::String)]]),
+];
+
+pub static WIDGET_PROP_TYPES: Lazy<HashMap<String, HashMap<AttrName, ValueType>>> = Lazy::new(|| {
+    BUILTIN_WIDGETS
+        .iter()
+        .map(|(name, prop_groups)| {
+            let props = std::iter::once(WIDGET_PROPS)
+                .chain(prop_groups.iter().copied())
+                .flatten()
+                .map(|(prop, prop_type)| (AttrName(prop.to_string()), *prop_type))
+                .collect();
+            (name.to_string(), props)
+        })
+        .collect()
+});
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    static WIDGET_DEFINITIONS_SOURCE: &str = include_str!("widget_definitions.rs");
+
+    /// The type a property is expected to have, given the name of the `DynVal` method it is read with.
+    fn prop_type_for_getter(getter: &str) -> ValueType {
+        match getter {
+            "f64" | "i32" => ValueType::Number,
+            "bool" => ValueType::Bool,
+            _ => ValueType::String,
+        }
+    }
+
+    #[test]
+    fn test_all_widgets_are_listed() {
+        let widget_regex = regex!(r#""([\w-]+)" => \w+\(bargs\)"#);
+        let defined_widgets =
+            widget_regex.captures_iter(WIDGET_DEFINITIONS_SOURCE).map(|cap| cap[1].to_string()).collect::<HashSet<_>>();
+        let listed_widgets = WIDGET_PROP_TYPES.keys().cloned().collect::<HashSet<_>>();
+        assert_eq!(defined_widgets, listed_widgets);
+        assert_eq!(BUILTIN_WIDGETS.len(), listed_widgets.len(), "A widget is listed more than once");
+    }
+
+    #[test]
+    fn test_all_props_are_listed() {
+        let prop_regex = regex!(r"prop\(([^{]*?)\)\s*\{");
+        let prop_arg_regex = regex!(r"(\w+)\s*:\s*as_(\w+)");
+        let defined_props = prop_regex
+            .captures_iter(WIDGET_DEFINITIONS_SOURCE)
+            .flat_map(|cap| {
+                prop_arg_regex
+                    .captures_iter(&cap[1])
+                    .map(|arg| (arg[1].replace('_', "-"), prop_type_for_getter(&arg[2])))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<HashSet<_>>();
+        let listed_props = WIDGET_PROP_TYPES
+            .values()
+            .flat_map(|props| props.iter().map(|(prop, prop_type)| (prop.0.clone(), *prop_type)))
+            .collect::<HashSet<_>>();
+        assert_eq!(defined_props, listed_props);
+    }
+
+    #[test]
+    fn test_widget_prop_types() {
+        let prop_type = |widget: &str, prop: &str| WIDGET_PROP_TYPES.get(widget).and_then(|props| props.get(prop)).copied();
+        assert_eq!(prop_type("box", "spacing"), Some(ValueType::Number));
+        assert_eq!(prop_type("box", "space-evenly"), Some(ValueType::Bool));
+        assert_eq!(prop_type("box", "visible"), Some(ValueType::Bool));
+        assert_eq!(prop_type("label", "text"), Some(ValueType::String));
+        assert_eq!(prop_type("button", "onmiddleclick"), Some(ValueType::String));
+        assert_eq!(prop_type("scale", "max"), Some(ValueType::Number));
+        assert_eq!(prop_type("scale", "orientation"), Some(ValueType::String));
+        assert_eq!(prop_type("combo-box-text", "items"), Some(ValueType::String));
+        assert_eq!(prop_type("input", "value"), Some(ValueType::String));
+        assert_eq!(prop_type("box", "nope"), None);
+    }
+}
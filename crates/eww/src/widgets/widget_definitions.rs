@@ -178,6 +178,7 @@ pub(super) fn resolve_range_attrs(bargs: &mut BuilderArgs, gtk_widget: &gtk::Ran
         gtk::Inhibit(false)
     }));
 
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
         // @prop value - the value
         prop(value: as_f64) {
@@ -190,12 +191,13 @@ pub(super) fn resolve_range_attrs(bargs: &mut BuilderArgs, gtk_widget: &gtk::Ran
         // @prop max - the maximum value
         prop(max: as_f64) { gtk_widget.adjustment().set_upper(max)},
         // @prop timeout - timeout of the command
-        // @prop onchange - command executed once the value is changes. The placeholder `{}`, used in the command will be replaced by the new value.
+        // @prop onchange - command executed once the value is changes. The placeholder `{value}` used in the command will be replaced by the new value.
         prop(timeout: as_duration = Duration::from_millis(200), onchange: as_string) {
+            let command_context = command_context.clone();
             gtk_widget.set_sensitive(true);
             gtk_widget.add_events(gdk::EventMask::PROPERTY_CHANGE_MASK);
             connect_single_handler!(gtk_widget, gtk_widget.connect_value_changed(move |gtk_widget| {
-                run_command(timeout, &onchange, gtk_widget.value());
+                run_command(&command_context, timeout, &onchange, gtk_widget.value(), &[]);
             }));
         }
     });
@@ -217,6 +219,7 @@ pub(super) fn resolve_orientable_attrs(bargs: &mut BuilderArgs, gtk_widget: &gtk
 /// @desc A combo box allowing the user to choose between several items.
 fn build_gtk_combo_box_text(bargs: &mut BuilderArgs) -> Result<gtk::ComboBoxText> {
     let gtk_widget = gtk::ComboBoxText::new();
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
         // @prop items - Items that should be displayed in the combo box
         prop(items: as_vec) {
@@ -226,10 +229,11 @@ fn build_gtk_combo_box_text(bargs: &mut BuilderArgs) -> Result<gtk::ComboBoxText
             }
         },
         // @prop timeout - timeout of the command
-        // @prop onchange - runs the code when a item was selected, replacing {} with the item as a string
+        // @prop onchange - runs the code when a item was selected, replacing `{value}` with the item as a string
         prop(timeout: as_duration = Duration::from_millis(200), onchange: as_string) {
+            let command_context = command_context.clone();
             connect_single_handler!(gtk_widget, gtk_widget.connect_changed(move |gtk_widget| {
-                run_command(timeout, &onchange, gtk_widget.active_text().unwrap_or_else(|| "".into()));
+                run_command(&command_context, timeout, &onchange, gtk_widget.active_text().unwrap_or_else(|| "".into()), &[]);
             }));
         },
     });
@@ -267,13 +271,15 @@ fn build_gtk_revealer(bargs: &mut BuilderArgs) -> Result<gtk::Revealer> {
 /// @desc A checkbox that can trigger events on checked / unchecked.
 fn build_gtk_checkbox(bargs: &mut BuilderArgs) -> Result<gtk::CheckButton> {
     let gtk_widget = gtk::CheckButton::new();
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
         // @prop timeout - timeout of the command
         // @prop onchecked - action (command) to be executed when checked by the user
         // @prop onunchecked - similar to onchecked but when the widget is unchecked
         prop(timeout: as_duration = Duration::from_millis(200), onchecked: as_string = "", onunchecked: as_string = "") {
+            let command_context = command_context.clone();
             connect_single_handler!(gtk_widget, gtk_widget.connect_toggled(move |gtk_widget| {
-                run_command(timeout, if gtk_widget.is_active() { &onchecked } else { &onunchecked }, "");
+                run_command(&command_context, timeout, if gtk_widget.is_active() { &onchecked } else { &onunchecked }, "", &[]);
             }));
        }
     });
@@ -285,6 +291,7 @@ fn build_gtk_checkbox(bargs: &mut BuilderArgs) -> Result<gtk::CheckButton> {
 /// @desc A button opening a color chooser window
 fn build_gtk_color_button(bargs: &mut BuilderArgs) -> Result<gtk::ColorButton> {
     let gtk_widget = gtk::ColorButtonBuilder::new().build();
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
         // @prop use-alpha - bool to whether or not use alpha
         prop(use_alpha: as_bool) {gtk_widget.set_use_alpha(use_alpha);},
@@ -292,8 +299,9 @@ fn build_gtk_color_button(bargs: &mut BuilderArgs) -> Result<gtk::ColorButton> {
         // @prop onchange - runs the code when the color was selected
         // @prop timeout - timeout of the command
         prop(timeout: as_duration = Duration::from_millis(200), onchange: as_string) {
+            let command_context = command_context.clone();
             connect_single_handler!(gtk_widget, gtk_widget.connect_color_set(move |gtk_widget| {
-                run_command(timeout, &onchange, gtk_widget.rgba());
+                run_command(&command_context, timeout, &onchange, gtk_widget.rgba(), &[]);
             }));
         }
     });
@@ -305,6 +313,7 @@ fn build_gtk_color_button(bargs: &mut BuilderArgs) -> Result<gtk::ColorButton> {
 /// @desc A color chooser widget
 fn build_gtk_color_chooser(bargs: &mut BuilderArgs) -> Result<gtk::ColorChooserWidget> {
     let gtk_widget = gtk::ColorChooserWidget::new();
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
         // @prop use-alpha - bool to wether or not use alpha
         prop(use_alpha: as_bool) {gtk_widget.set_use_alpha(use_alpha);},
@@ -312,8 +321,9 @@ fn build_gtk_color_chooser(bargs: &mut BuilderArgs) -> Result<gtk::ColorChooserW
         // @prop onchange - runs the code when the color was selected
         // @prop timeout - timeout of the command
         prop(timeout: as_duration = Duration::from_millis(200), onchange: as_string) {
+            let command_context = command_context.clone();
             connect_single_handler!(gtk_widget, gtk_widget.connect_color_activated(move |_a, color| {
-                run_command(timeout, &onchange, *color);
+                run_command(&command_context, timeout, &onchange, *color, &[]);
             }));
         }
     });
@@ -365,17 +375,19 @@ fn build_gtk_progress(bargs: &mut BuilderArgs) -> Result<gtk::ProgressBar> {
 /// @desc An input field. For this to be useful, set `focusable="true"` on the window.
 fn build_gtk_input(bargs: &mut BuilderArgs) -> Result<gtk::Entry> {
     let gtk_widget = gtk::Entry::new();
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
         // @prop value - the content of the text field
         prop(value: as_string) {
             gtk_widget.set_text(&value);
         },
 
-        // @prop onchange - Command to run when the text changes. The placeholder `{}` will be replaced by the value
+        // @prop onchange - Command to run when the text changes. The placeholder `{value}` will be replaced by the value
         // @prop timeout - timeout of the command
         prop(timeout: as_duration = Duration::from_millis(200), onchange: as_string) {
+            let command_context = command_context.clone();
             connect_single_handler!(gtk_widget, gtk_widget.connect_changed(move |gtk_widget| {
-                run_command(timeout, &onchange, gtk_widget.text().to_string());
+                run_command(&command_context, timeout, &onchange, gtk_widget.text().to_string(), &[]);
             }));
         }
     });
@@ -387,8 +399,9 @@ fn build_gtk_input(bargs: &mut BuilderArgs) -> Result<gtk::Entry> {
 fn build_gtk_button(bargs: &mut BuilderArgs) -> Result<gtk::Button> {
     let gtk_widget = gtk::Button::new();
 
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
-        // @prop onclick - a command that get's run when the button is clicked
+        // @prop onclick - a command that get's run when the button is clicked. The placeholders `{button}`, `{x}` and `{y}` will be replaced by the clicked button and the position of the click
         // @prop onmiddleclick - a command that get's run when the button is middleclicked
         // @prop onrightclick - a command that get's run when the button is rightclicked
         // @prop timeout - timeout of the command
@@ -398,12 +411,18 @@ fn build_gtk_button(bargs: &mut BuilderArgs) -> Result<gtk::Button> {
             onmiddleclick: as_string = "",
             onrightclick: as_string = ""
         ) {
+            let command_context = command_context.clone();
             gtk_widget.add_events(gdk::EventMask::BUTTON_PRESS_MASK);
             connect_single_handler!(gtk_widget, gtk_widget.connect_button_press_event(move |_, evt| {
+                let placeholders = [
+                    ("button", evt.button().to_string()),
+                    ("x", evt.position().0.to_string()),
+                    ("y", evt.position().1.to_string()),
+                ];
                 match evt.button() {
-                    1 => run_command(timeout, &onclick, ""),
-                    2 => run_command(timeout, &onmiddleclick, ""),
-                    3 => run_command(timeout, &onrightclick, ""),
+                    1 => run_command(&command_context, timeout, &onclick, "", &placeholders),
+                    2 => run_command(&command_context, timeout, &onmiddleclick, "", &placeholders),
+                    3 => run_command(&command_context, timeout, &onrightclick, "", &placeholders),
                     _ => {},
                 }
                 gtk::Inhibit(false)
@@ -479,6 +498,7 @@ fn build_center_box(bargs: &mut BuilderArgs) -> Result<gtk::Box> {
                 build_gtk_widget(
                     bargs.scope_graph,
                     bargs.widget_defs.clone(),
+                    bargs.command_context.clone(),
                     bargs.calling_scope,
                     child.clone(),
                     bargs.custom_widget_invocation.clone(),
@@ -518,27 +538,33 @@ fn build_gtk_event_box(bargs: &mut BuilderArgs) -> Result<gtk::EventBox> {
         gtk::Inhibit(false)
     });
 
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
         // @prop timeout - timeout of the command
-        // @prop onscroll - event to execute when the user scrolls with the mouse over the widget. The placeholder `{}` used in the command will be replaced with either `up` or `down`.
+        // @prop onscroll - event to execute when the user scrolls with the mouse over the widget. The placeholder `{value}` used in the command will be replaced with either `up` or `down`, and `{x}` and `{y}` by the position of the pointer.
         prop(timeout: as_duration = Duration::from_millis(200), onscroll: as_string) {
+            let command_context = command_context.clone();
             gtk_widget.add_events(gdk::EventMask::SCROLL_MASK);
             gtk_widget.add_events(gdk::EventMask::SMOOTH_SCROLL_MASK);
             connect_single_handler!(gtk_widget, gtk_widget.connect_scroll_event(move |_, evt| {
                 let delta = evt.delta().1;
                 if delta != 0f64 { // Ignore the first event https://bugzilla.gnome.org/show_bug.cgi?id=675959
-                    run_command(timeout, &onscroll, if delta < 0f64 { "up" } else { "down" });
+                    let placeholders = [("x", evt.position().0.to_string()), ("y", evt.position().1.to_string())];
+                    run_command(&command_context, timeout, &onscroll, if delta < 0f64 { "up" } else { "down" }, &placeholders);
                 }
                 gtk::Inhibit(false)
             }));
         },
         // @prop timeout - timeout of the command
-        // @prop onhover - event to execute when the user hovers over the widget
+        // @prop onhover - event to execute when the user hovers over the widget. The placeholders `{x}` and `{y}` will be replaced by the position of the pointer
         prop(timeout: as_duration = Duration::from_millis(200), onhover: as_string) {
+            let command_context = command_context.clone();
             gtk_widget.add_events(gdk::EventMask::ENTER_NOTIFY_MASK);
             connect_single_handler!(gtk_widget, gtk_widget.connect_enter_notify_event(move |_, evt| {
                 if evt.detail() != NotifyType::Inferior {
-                    run_command(timeout, &onhover, format!("{} {}", evt.position().0, evt.position().1));
+                    let placeholders = [("x", evt.position().0.to_string()), ("y", evt.position().1.to_string())];
+                    let position = format!("{} {}", evt.position().0, evt.position().1);
+                    run_command(&command_context, timeout, &onhover, position, &placeholders);
                 }
                 gtk::Inhibit(false)
             }));
@@ -546,10 +572,13 @@ fn build_gtk_event_box(bargs: &mut BuilderArgs) -> Result<gtk::EventBox> {
         // @prop timeout - timeout of the command
         // @prop onhoverlost - event to execute when the user losts hovers over the widget
         prop(timeout: as_duration = Duration::from_millis(200), onhoverlost: as_string) {
+            let command_context = command_context.clone();
             gtk_widget.add_events(gdk::EventMask::LEAVE_NOTIFY_MASK);
             connect_single_handler!(gtk_widget, gtk_widget.connect_leave_notify_event(move |_, evt| {
                 if evt.detail() != NotifyType::Inferior {
-                    run_command(timeout, &onhoverlost, format!("{} {}", evt.position().0, evt.position().1));
+                    let placeholders = [("x", evt.position().0.to_string()), ("y", evt.position().1.to_string())];
+                    let position = format!("{} {}", evt.position().0, evt.position().1);
+                    run_command(&command_context, timeout, &onhoverlost, position, &placeholders);
                 }
                 gtk::Inhibit(false)
             }));
@@ -627,6 +656,7 @@ fn build_gtk_literal(bargs: &mut BuilderArgs) -> Result<gtk::Box> {
     let literal_file_id: Rc<RefCell<Option<usize>>> = Rc::new(RefCell::new(None));
 
     let widget_defs = bargs.widget_defs.clone();
+    let command_context = bargs.command_context.clone();
     let calling_scope = bargs.calling_scope;
 
     def_widget!(bargs, scope_graph, gtk_widget, {
@@ -649,12 +679,19 @@ fn build_gtk_literal(bargs: &mut BuilderArgs) -> Result<gtk::Box> {
                 let content_widget_use = content_widget_use?;
 
                 // TODO a literal should create a new scope, that I'm not even sure should inherit from root
-                let child_widget = build_gtk_widget(scope_graph, widget_defs.clone(), calling_scope, content_widget_use, None)
-                    .map_err(|e| AstError::ErrorContext {
-                        label_span: literal_use_span,
-                        context: "Error in the literal used here".to_string(),
-                        main_err: Box::new(error_handling_ctx::anyhow_err_to_diagnostic(&e).unwrap_or_else(|| gen_diagnostic!(e)))
-                    })?;
+                let child_widget = build_gtk_widget(
+                    scope_graph,
+                    widget_defs.clone(),
+                    command_context.clone(),
+                    calling_scope,
+                    content_widget_use,
+                    None,
+                )
+                .map_err(|e| AstError::ErrorContext {
+                    label_span: literal_use_span,
+                    context: "Error in the literal used here".to_string(),
+                    main_err: Box::new(error_handling_ctx::anyhow_err_to_diagnostic(&e).unwrap_or_else(|| gen_diagnostic!(e)))
+                })?;
                 gtk_widget.add(&child_widget);
                 child_widget.show();
             }
@@ -667,6 +704,7 @@ fn build_gtk_literal(bargs: &mut BuilderArgs) -> Result<gtk::Box> {
 /// @desc A widget that displays a calendar
 fn build_gtk_calendar(bargs: &mut BuilderArgs) -> Result<gtk::Calendar> {
     let gtk_widget = gtk::Calendar::new();
+    let command_context = bargs.command_context.clone();
     def_widget!(bargs, _g, gtk_widget, {
         // @prop day - the selected day
         prop(day: as_f64) { gtk_widget.set_day(day as i32) },
@@ -682,14 +720,17 @@ fn build_gtk_calendar(bargs: &mut BuilderArgs) -> Result<gtk::Calendar> {
         prop(show_day_names: as_bool) { gtk_widget.set_show_day_names(show_day_names) },
         // @prop show-week-numbers - show week numbers
         prop(show_week_numbers: as_bool) { gtk_widget.set_show_week_numbers(show_week_numbers) },
-        // @prop onclick - command to run when the user selects a date. The `{}` placeholder will be replaced by the selected date.
+        // @prop onclick - command to run when the user selects a date. The `{value}` placeholder will be replaced by the selected date.
         // @prop timeout - timeout of the command
         prop(timeout: as_duration = Duration::from_millis(200), onclick: as_string) {
+            let command_context = command_context.clone();
             connect_single_handler!(gtk_widget, gtk_widget.connect_day_selected(move |w| {
                 run_command(
+                    &command_context,
                     timeout,
                     &onclick,
-                    format!("{}.{}.{}", w.day(), w.month(), w.year())
+                    format!("{}.{}.{}", w.day(), w.month(), w.year()),
+                    &[],
                 )
             }));
         }
@@ -1,10 +1,25 @@
-use crate::dynval::DynVal;
+use crate::dynval::{DynVal, Value};
 use eww_shared_util::{Span, Spanned};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
 use eww_shared_util::VarName;
 
+/// The function that gives access to the metadata of a `defpoll` or `deflisten` variable, i.e. `meta(battery).stale`.
+/// Rather than a regular function, this reads the variable returned by [`meta_var_name`].
+pub const META_FUNCTION_NAME: &str = "meta";
+
+/// The name of the variable that holds the metadata of the given script var.
+/// Variables defined in the configuration can't contain a `.`, so this can't clash with them.
+pub fn meta_var_name(var_name: &VarName) -> VarName {
+    VarName(format!("{}.meta", var_name))
+}
+
+/// The script var whose metadata is held by the given variable, if it is one of the variables returned by [`meta_var_name`].
+pub fn var_name_of_meta_var(name: &VarName) -> Option<VarName> {
+    name.0.strip_suffix(".meta").map(VarName::from)
+}
+
 #[rustfmt::skip]
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug, strum::EnumString, strum::Display)]
 pub enum BinOp {
@@ -29,7 +44,7 @@ pub enum UnaryOp {
     Not,
 }
 
-#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum SimplExpr {
     Literal(DynVal),
     JsonArray(Span, Vec<SimplExpr>),
@@ -41,12 +56,14 @@ pub enum SimplExpr {
     IfElse(Span, Box<SimplExpr>, Box<SimplExpr>, Box<SimplExpr>),
     JsonAccess(Span, Box<SimplExpr>, Box<SimplExpr>),
     FunctionCall(Span, String, Vec<SimplExpr>),
+    /// A single-parameter function (`x => x * 2`), which may only be passed as an argument to a function call.
+    Lambda(Span, VarName, Box<SimplExpr>),
 }
 
 impl std::fmt::Display for SimplExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            SimplExpr::Literal(x) => write!(f, "\"{}\"", x),
+            SimplExpr::Literal(x) => write!(f, "{:?}", x),
             SimplExpr::Concat(_, elems) => {
                 let text = elems
                     .iter()
@@ -65,6 +82,7 @@ impl std::fmt::Display for SimplExpr {
             SimplExpr::FunctionCall(_, function_name, args) => {
                 write!(f, "{}({})", function_name, args.iter().join(", "))
             }
+            SimplExpr::Lambda(_, param, body) => write!(f, "{} => {}", param, body),
             SimplExpr::JsonArray(_, values) => write!(f, "[{}]", values.iter().join(", ")),
             SimplExpr::JsonObject(_, entries) => {
                 write!(f, "{{{}}}", entries.iter().map(|(k, v)| format!("{}: {}", k, v)).join(", "))
@@ -74,12 +92,20 @@ impl std::fmt::Display for SimplExpr {
 }
 impl SimplExpr {
     pub fn literal(span: Span, s: String) -> Self {
-        Self::Literal(DynVal(s, span))
+        Self::Literal(DynVal::from(Value::String(s)).at(span))
+    }
+
+    /// Construct a literal from the source text of a number, falling back to a string if it is not a valid number.
+    pub fn number_literal(span: Span, s: String) -> Self {
+        match s.parse() {
+            Ok(n) => Self::Literal(DynVal::from(Value::Number(n)).at(span)),
+            Err(_) => Self::literal(span, s),
+        }
     }
 
     /// Construct a synthetic simplexpr from a literal string, without adding any relevant span information (uses [`Span::DUMMY`])
     pub fn synth_string(s: impl Into<String>) -> Self {
-        Self::Literal(DynVal(s.into(), Span::DUMMY))
+        Self::Literal(DynVal::from_string(s.into()))
     }
 
     /// Construct a synthetic simplexpr from a literal dynval, without adding any relevant span information (uses [`Span::DUMMY`])
@@ -91,8 +117,22 @@ impl SimplExpr {
         Self::VarRef(span, n.into())
     }
 
+    /// If this is a call to `meta`, returns the script var whose metadata is accessed.
+    pub fn as_meta_call(&self) -> Option<&VarName> {
+        match self {
+            SimplExpr::FunctionCall(_, name, args) if name == META_FUNCTION_NAME => match args.as_slice() {
+                [SimplExpr::VarRef(_, var_name)] => Some(var_name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     pub fn references_var(&self, var: &VarName) -> bool {
         use SimplExpr::*;
+        if let Some(meta_of) = self.as_meta_call() {
+            return &meta_var_name(meta_of) == var;
+        }
         match self {
             Literal(_) => false,
             Concat(_, x) | FunctionCall(_, _, x) | JsonArray(_, x) => x.iter().any(|x| x.references_var(var)),
@@ -101,11 +141,16 @@ impl SimplExpr {
             UnaryOp(_, _, x) => x.references_var(var),
             IfElse(_, a, b, c) => a.references_var(var) || b.references_var(var) || c.references_var(var),
             VarRef(_, x) => x == var,
+            Lambda(_, param, body) => param != var && body.references_var(var),
         }
     }
 
     pub fn collect_var_refs_into(&self, dest: &mut Vec<VarName>) {
         use SimplExpr::*;
+        if let Some(meta_of) = self.as_meta_call() {
+            dest.push(meta_var_name(meta_of));
+            return;
+        }
         match self {
             VarRef(_, x) => dest.push(x.clone()),
             UnaryOp(_, _, x) => x.as_ref().collect_var_refs_into(dest),
@@ -123,6 +168,9 @@ impl SimplExpr {
                 k.collect_var_refs_into(dest);
                 v.collect_var_refs_into(dest);
             }),
+            Lambda(_, param, body) => {
+                dest.extend(body.collect_var_refs().into_iter().filter(|x| x != param));
+            }
             Literal(_) => {}
         };
     }
@@ -147,6 +195,7 @@ impl Spanned for SimplExpr {
             SimplExpr::IfElse(span, ..) => *span,
             SimplExpr::JsonAccess(span, ..) => *span,
             SimplExpr::FunctionCall(span, ..) => *span,
+            SimplExpr::Lambda(span, ..) => *span,
         }
     }
 }
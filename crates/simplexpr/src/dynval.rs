@@ -1,7 +1,7 @@
 use eww_shared_util::{Span, Spanned};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::{convert::TryFrom, fmt, iter::FromIterator, str::FromStr};
+use std::{borrow::Cow, collections::BTreeMap, fmt, iter::FromIterator, str::FromStr, sync::Arc};
 
 pub type Result<T> = std::result::Result<T, ConversionError>;
 
@@ -24,40 +24,177 @@ impl Spanned for ConversionError {
     }
 }
 
-#[derive(Clone, Deserialize, Serialize, Eq)]
-pub struct DynVal(pub String, pub Span);
+/// The type of a [`Value`], as used in function signatures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ValueType {
+    Any,
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+/// The actual data held by a [`DynVal`].
+///
+/// Arrays and objects are reference counted, as values get cloned every time an expression referencing them is evaluated.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Arc<Vec<Value>>),
+    Object(Arc<BTreeMap<String, Value>>),
+}
+
+impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::Null => ValueType::Null,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Number(_) => ValueType::Number,
+            Value::String(_) => ValueType::String,
+            Value::Array(_) => ValueType::Array,
+            Value::Object(_) => ValueType::Object,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(b) => serde_json::Value::Bool(*b),
+            Value::Number(n) => number_to_json(*n),
+            Value::String(s) => serde_json::Value::String(s.clone()),
+            Value::Array(items) => serde_json::Value::Array(items.iter().map(Value::to_json).collect()),
+            Value::Object(entries) => serde_json::Value::Object(entries.iter().map(|(k, v)| (k.clone(), v.to_json())).collect()),
+        }
+    }
+}
+
+/// The largest integer up to which all integers can be represented exactly as a float.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Integral numbers are written without a fractional part, so `[1, 2]` doesn't turn into `[1.0,2.0]`.
+fn number_to_json(n: f64) -> serde_json::Value {
+    if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER {
+        serde_json::Value::from(n as i64)
+    } else {
+        serde_json::Number::from_f64(n).map(serde_json::Value::Number).unwrap_or(serde_json::Value::Null)
+    }
+}
+
+/// Integers that don't fit into a float exactly are kept as strings, such that they aren't silently changed.
+fn number_from_json(n: serde_json::Number) -> Value {
+    match n.as_f64() {
+        Some(f) if !(n.is_i64() || n.is_u64()) || f.abs() <= MAX_SAFE_INTEGER => Value::Number(f),
+        _ => Value::String(n.to_string()),
+    }
+}
+
+impl From<serde_json::Value> for Value {
+    fn from(v: serde_json::Value) -> Self {
+        match v {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Number(n) => number_from_json(n),
+            serde_json::Value::String(s) => Value::String(s),
+            serde_json::Value::Array(items) => Value::Array(Arc::new(items.into_iter().map(Value::from).collect())),
+            serde_json::Value::Object(entries) => {
+                Value::Object(Arc::new(entries.into_iter().map(|(k, v)| (k, Value::from(v))).collect()))
+            }
+        }
+    }
+}
+
+/// Strings are shown as they are, arrays and objects are written as compact json.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Array(_) | Value::Object(_) => write!(f, "{}", self.to_json()),
+        }
+    }
+}
+
+/// A value together with the span of the expression it came from.
+///
+/// Values parsed from json text, like the output of a script, also keep that text. Parsing it is lossy, as object keys are
+/// reordered, whitespace is dropped and large integers lose precision, so the string form of such values is the original text.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "SerializedDynVal", into = "SerializedDynVal")]
+pub struct DynVal(pub Value, pub Span, Option<Arc<str>>);
+
+/// Values are always serialized in their string form, such that they can be exchanged with anything that only knows strings.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "DynVal")]
+struct SerializedDynVal(String, Span);
+
+impl From<SerializedDynVal> for DynVal {
+    fn from(SerializedDynVal(s, span): SerializedDynVal) -> Self {
+        DynVal(Value::String(s), span, None)
+    }
+}
+
+impl From<DynVal> for SerializedDynVal {
+    fn from(x: DynVal) -> Self {
+        let span = x.1;
+        SerializedDynVal(x.into_inner(), span)
+    }
+}
 
 impl From<String> for DynVal {
     fn from(s: String) -> Self {
-        DynVal(s, Span::DUMMY)
+        DynVal(Value::String(s), Span::DUMMY, None)
+    }
+}
+
+impl From<Value> for DynVal {
+    fn from(v: Value) -> Self {
+        DynVal(v, Span::DUMMY, None)
     }
 }
 
 impl fmt::Display for DynVal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        match &self.2 {
+            Some(source) => write!(f, "{}", source),
+            None => write!(f, "{}", self.0),
+        }
     }
 }
 impl fmt::Debug for DynVal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "\"{}\"", self.0)
+        match &self.0 {
+            Value::String(s) => write!(f, "\"{}\"", s),
+            other => write!(f, "{}", other),
+        }
     }
 }
 
 /// Manually implement equality, to allow for values in different formats (i.e. "1" and "1.0") to still be considered as equal.
 impl std::cmp::PartialEq<Self> for DynVal {
     fn eq(&self, other: &Self) -> bool {
-        if let (Ok(a), Ok(b)) = (self.as_f64(), other.as_f64()) {
+        if let (Some(a), Some(b)) = (self.to_f64(), other.to_f64()) {
             a == b
         } else {
-            self.0 == other.0
+            match (&self.0, &other.0) {
+                (Value::String(a), Value::String(b)) => a == b,
+                (a @ Value::Array(_), b @ Value::Array(_)) | (a @ Value::Object(_), b @ Value::Object(_)) => a == b,
+                _ => self.as_str() == other.as_str(),
+            }
         }
     }
 }
 
 impl FromIterator<DynVal> for DynVal {
     fn from_iter<T: IntoIterator<Item = DynVal>>(iter: T) -> Self {
-        DynVal(iter.into_iter().join(""), Span::DUMMY)
+        DynVal(Value::String(iter.into_iter().join("")), Span::DUMMY, None)
     }
 }
 
@@ -80,43 +217,44 @@ impl<E, T: FromStr<Err = E>> FromDynVal for T {
     type Err = E;
 
     fn from_dynval(x: &DynVal) -> std::result::Result<Self, Self::Err> {
-        x.0.parse()
+        x.as_str().parse()
     }
 }
 
 macro_rules! impl_dynval_from {
-    ($($t:ty),*) => {
+    ($variant:ident: $($t:ty),*) => {
         $(impl From<$t> for DynVal {
-            fn from(x: $t) -> Self { DynVal(x.to_string(), Span::DUMMY) }
+            fn from(x: $t) -> Self { DynVal(Value::$variant(x.into()), Span::DUMMY, None) }
         })*
     };
 }
 
-impl_dynval_from!(bool, i32, u32, f32, u8, f64, &str);
+impl_dynval_from!(Bool: bool);
+impl_dynval_from!(Number: i32, u32, u8, f64);
+impl_dynval_from!(String: &str);
 
-impl TryFrom<serde_json::Value> for DynVal {
-    type Error = serde_json::Error;
+impl From<f32> for DynVal {
+    /// Goes through the string representation, as widening the float directly would show its imprecision (`0.1` -> `0.10000000149011612`)
+    fn from(x: f32) -> Self {
+        DynVal(Value::Number(x.to_string().parse().unwrap_or_else(|_| f64::from(x))), Span::DUMMY, None)
+    }
+}
 
-    fn try_from(value: serde_json::Value) -> std::result::Result<Self, Self::Error> {
-        Ok(DynVal(serde_json::to_string(&value)?, Span::DUMMY))
+impl From<serde_json::Value> for DynVal {
+    fn from(value: serde_json::Value) -> Self {
+        DynVal(Value::from(value), Span::DUMMY, None)
     }
 }
 
 impl From<std::time::Duration> for DynVal {
     fn from(d: std::time::Duration) -> Self {
-        DynVal(format!("{}ms", d.as_millis()), Span::DUMMY)
+        DynVal(Value::String(format!("{}ms", d.as_millis())), Span::DUMMY, None)
     }
 }
 
 impl From<&serde_json::Value> for DynVal {
     fn from(v: &serde_json::Value) -> Self {
-        DynVal(
-            v.as_str()
-                .map(|x| x.to_string())
-                .or_else(|| serde_json::to_string(v).ok())
-                .unwrap_or_else(|| "<invalid json value>".to_string()),
-            Span::DUMMY,
-        )
+        DynVal(Value::from(v.clone()), Span::DUMMY, None)
     }
 }
 
@@ -133,7 +271,20 @@ impl DynVal {
     }
 
     pub fn from_string(s: String) -> Self {
-        DynVal(s, Span::DUMMY)
+        DynVal(Value::String(s), Span::DUMMY, None)
+    }
+
+    /// Read a value produced by the outside world, i.e. the output of a script.
+    /// If the string contains a json array or object, it is parsed once here, rather than every time it gets indexed into,
+    /// while its string form stays exactly the given text. Anything else is kept as a string, unchanged.
+    pub fn from_string_or_json(s: String) -> Self {
+        let trimmed = s.trim();
+        let looks_like_json =
+            (trimmed.starts_with('{') && trimmed.ends_with('}')) || (trimmed.starts_with('[') && trimmed.ends_with(']'));
+        match serde_json::from_str::<serde_json::Value>(trimmed) {
+            Ok(json) if looks_like_json => DynVal(Value::from(json), Span::DUMMY, Some(s.into())),
+            _ => DynVal::from_string(s),
+        }
     }
 
     pub fn read_as<E, T: FromDynVal<Err = E>>(&self) -> std::result::Result<T, E> {
@@ -141,29 +292,63 @@ impl DynVal {
     }
 
     pub fn into_inner(self) -> String {
-        self.0
+        match (self.0, self.2) {
+            (_, Some(source)) => source.to_string(),
+            (Value::String(s), None) => s,
+            (other, None) => other.to_string(),
+        }
+    }
+
+    /// The string representation of this value, without copying it if it already is a string.
+    pub fn as_str(&self) -> Cow<str> {
+        match (&self.0, &self.2) {
+            (_, Some(source)) => Cow::Borrowed(source),
+            (Value::String(s), None) => Cow::Borrowed(s),
+            (other, None) => Cow::Owned(other.to_string()),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self.0, Value::Null)
+    }
+
+    pub(crate) fn to_f64(&self) -> Option<f64> {
+        match &self.0 {
+            Value::Number(n) => Some(*n),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
     }
 
     /// This will never fail
     pub fn as_string(&self) -> Result<String> {
-        Ok(self.0.to_owned())
+        Ok(self.as_str().into_owned())
     }
 
     pub fn as_f64(&self) -> Result<f64> {
-        self.0.parse().map_err(|e| ConversionError::new(self.clone(), "f64", e))
+        match &self.0 {
+            Value::Number(n) => Ok(*n),
+            _ => self.as_str().parse().map_err(|e| ConversionError::new(self.clone(), "f64", e)),
+        }
     }
 
     pub fn as_i32(&self) -> Result<i32> {
-        self.0.parse().map_err(|e| ConversionError::new(self.clone(), "i32", e))
+        match &self.0 {
+            Value::Number(n) if n.fract() == 0.0 && *n >= i32::MIN as f64 && *n <= i32::MAX as f64 => Ok(*n as i32),
+            _ => self.as_str().parse().map_err(|e| ConversionError::new(self.clone(), "i32", e)),
+        }
     }
 
     pub fn as_bool(&self) -> Result<bool> {
-        self.0.parse().map_err(|e| ConversionError::new(self.clone(), "bool", e))
+        match &self.0 {
+            Value::Bool(b) => Ok(*b),
+            _ => self.as_str().parse().map_err(|e| ConversionError::new(self.clone(), "bool", e)),
+        }
     }
 
     pub fn as_duration(&self) -> Result<std::time::Duration> {
         use std::time::Duration;
-        let s = &self.0;
+        let s = &self.as_str();
         if s.ends_with("ms") {
             Ok(Duration::from_millis(
                 s.trim_end_matches("ms").parse().map_err(|e| ConversionError::new(self.clone(), "integer", e))?,
@@ -186,10 +371,14 @@ impl DynVal {
     }
 
     pub fn as_vec(&self) -> Result<Vec<String>> {
-        if self.0.is_empty() {
+        let s = match &self.0 {
+            Value::Array(items) => return Ok(items.iter().map(|x| x.to_string()).collect()),
+            _ => self.as_str(),
+        };
+        if s.is_empty() {
             Ok(Vec::new())
         } else {
-            match self.0.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            match s.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
                 Some(content) => {
                     let mut items: Vec<String> = content.split(',').map(|x: &str| x.to_string()).collect();
                     let mut removed = 0;
@@ -210,9 +399,55 @@ impl DynVal {
         }
     }
 
+    /// Get the elements of an array, parsing the value as json if it is a plain string.
+    pub fn as_array(&self) -> Result<Arc<Vec<Value>>> {
+        match &self.0 {
+            Value::Array(items) => Ok(items.clone()),
+            Value::String(_) => match Value::from(self.as_json_value()?) {
+                Value::Array(items) => Ok(items),
+                _ => Err(ConversionError { value: self.clone(), target_type: "array", source: None }),
+            },
+            _ => Err(ConversionError { value: self.clone(), target_type: "array", source: None }),
+        }
+    }
+
+    /// Get the entries of an object, parsing the value as json if it is a plain string.
+    pub fn as_object(&self) -> Result<Arc<BTreeMap<String, Value>>> {
+        match &self.0 {
+            Value::Object(entries) => Ok(entries.clone()),
+            Value::String(_) => match Value::from(self.as_json_value()?) {
+                Value::Object(entries) => Ok(entries),
+                _ => Err(ConversionError { value: self.clone(), target_type: "object", source: None }),
+            },
+            _ => Err(ConversionError { value: self.clone(), target_type: "object", source: None }),
+        }
+    }
+
+    /// Convert this value into the given type, such that the matching variant of [`Value`] can be used directly.
+    pub fn coerce_to(self, ty: ValueType) -> Result<DynVal> {
+        if self.0.value_type() == ty {
+            return Ok(self);
+        }
+        let span = self.1;
+        let value = match ty {
+            ValueType::Any => return Ok(self),
+            ValueType::Null if self.is_null() => Value::Null,
+            ValueType::Null => return Err(ConversionError { value: self, target_type: "null", source: None }),
+            ValueType::Bool => Value::Bool(self.as_bool()?),
+            ValueType::Number => Value::Number(self.as_f64()?),
+            ValueType::String => Value::String(self.into_inner()),
+            ValueType::Array => Value::Array(self.as_array()?),
+            ValueType::Object => Value::Object(self.as_object()?),
+        };
+        Ok(DynVal(value, span, None))
+    }
+
     pub fn as_json_value(&self) -> Result<serde_json::Value> {
-        serde_json::from_str::<serde_json::Value>(&self.0)
-            .map_err(|e| ConversionError::new(self.clone(), "json-value", Box::new(e)))
+        match &self.0 {
+            Value::String(s) => serde_json::from_str::<serde_json::Value>(s)
+                .map_err(|e| ConversionError::new(self.clone(), "json-value", Box::new(e))),
+            other => Ok(other.to_json()),
+        }
     }
 }
 
@@ -230,4 +465,26 @@ mod test {
         insta::assert_debug_snapshot!(DynVal::from_string("[a,b".to_string()).as_vec());
         insta::assert_debug_snapshot!(DynVal::from_string("a]".to_string()).as_vec());
     }
+
+    #[test]
+    fn test_typed_values() {
+        assert_eq!(DynVal::from(1.5).as_string().unwrap(), "1.5");
+        assert_eq!(DynVal::from(2).as_i32().unwrap(), 2);
+        assert_eq!(DynVal::from(1), DynVal::from_string("1.0".to_string()));
+        assert_eq!(DynVal::from(true), DynVal::from_string("true".to_string()));
+
+        // json keeps its original text, while its content can be used directly
+        let text = r#"{"b": [1, "x", null], "a": 1.5, "big": 12345678901234567891}"#;
+        let json = DynVal::from_string_or_json(text.to_string());
+        assert!(matches!(json.0, Value::Object(_)));
+        assert_eq!(json.as_string().unwrap(), text);
+        assert_eq!(json.to_string(), text);
+        assert_eq!(json.as_object().unwrap()["big"], Value::String("12345678901234567891".to_string()));
+        assert_eq!(json, DynVal::from(serde_json::json!({"a": 1.5, "b": [1, "x", null], "big": "12345678901234567891"})));
+
+        // anything that isn't a json array or object is kept exactly as it was
+        for s in ["01", " 1.50", "true", "\"quoted\"", "[a,b]", ""] {
+            assert_eq!(DynVal::from_string_or_json(s.to_string()).as_string().unwrap(), s);
+        }
+    }
 }
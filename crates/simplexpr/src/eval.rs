@@ -1,11 +1,12 @@
 use itertools::Itertools;
 
 use crate::{
-    ast::{BinOp, SimplExpr, UnaryOp},
-    dynval::{ConversionError, DynVal},
+    ast::{meta_var_name, BinOp, SimplExpr, UnaryOp, META_FUNCTION_NAME},
+    dynval::{ConversionError, DynVal, Value},
+    functions::{self, FunctionArg, Lambda},
 };
 use eww_shared_util::{Span, Spanned, VarName};
-use std::{collections::HashMap, convert::TryFrom};
+use std::{collections::HashMap, sync::Arc};
 
 #[derive(Debug, thiserror::Error)]
 pub enum EvalError {
@@ -18,6 +19,12 @@ pub enum EvalError {
     #[error("Unknown variable {0}")]
     UnknownVariable(VarName, Vec<VarName>),
 
+    #[error("`{0}` has no metadata, as only `defpoll` and `deflisten` variables do")]
+    NoMetadata(VarName),
+
+    #[error("`meta` expects the name of a `defpoll` or `deflisten` variable, i.e. `meta(battery)`")]
+    InvalidMetaCall,
+
     #[error(transparent)]
     ConversionError(#[from] ConversionError),
 
@@ -30,6 +37,15 @@ pub enum EvalError {
     #[error("Unable to index into value {0}")]
     CannotIndex(String),
 
+    #[error("Expected a lambda (i.e. `x => x * 2`) as argument")]
+    ExpectedLambda,
+
+    #[error("Lambdas can only be used as arguments to functions that expect them")]
+    UnexpectedLambda,
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
     #[error("Json operation failed: {0}")]
     SerdeError(#[from] serde_json::error::Error),
 
@@ -60,33 +76,76 @@ impl Spanned for EvalError {
     }
 }
 
+/// The variables an expression is evaluated with.
+/// A lambda adds its parameter on top of the scope it was written in, which it only borrows.
+pub(crate) enum Scope<'a> {
+    Variables(&'a HashMap<VarName, DynVal>),
+    Lambda { param: &'a VarName, arg: &'a DynVal, outer: &'a Scope<'a> },
+}
+
+impl<'a> Scope<'a> {
+    fn get(&self, name: &VarName) -> Option<&'a DynVal> {
+        match *self {
+            Scope::Variables(values) => values.get(name),
+            Scope::Lambda { param, arg, .. } if param == name => Some(arg),
+            Scope::Lambda { outer, .. } => outer.get(name),
+        }
+    }
+
+    fn names(&self) -> Vec<&'a VarName> {
+        match *self {
+            Scope::Variables(values) => values.keys().collect(),
+            Scope::Lambda { param, outer, .. } => {
+                let mut names = outer.names();
+                names.push(param);
+                names
+            }
+        }
+    }
+}
+
 impl SimplExpr {
     /// map over all of the variable references, replacing them with whatever expression the provided function returns.
+    /// References to lambda parameters are left untouched.
     /// Returns [Err] when the provided function fails with an [Err]
     pub fn try_map_var_refs<E, F: Fn(Span, VarName) -> Result<SimplExpr, E> + Copy>(self, f: F) -> Result<Self, E> {
+        self.try_map_free_var_refs(&f, &[])
+    }
+
+    fn try_map_free_var_refs<E>(self, f: &dyn Fn(Span, VarName) -> Result<SimplExpr, E>, bound: &[VarName]) -> Result<Self, E> {
         use SimplExpr::*;
+        let map_all = |xs: Vec<SimplExpr>| xs.into_iter().map(|x| x.try_map_free_var_refs(f, bound)).collect::<Result<_, _>>();
         Ok(match self {
-            BinOp(span, box a, op, box b) => BinOp(span, box a.try_map_var_refs(f)?, op, box b.try_map_var_refs(f)?),
-            Concat(span, elems) => Concat(span, elems.into_iter().map(|x| x.try_map_var_refs(f)).collect::<Result<_, _>>()?),
-            UnaryOp(span, op, box a) => UnaryOp(span, op, box a.try_map_var_refs(f)?),
-            IfElse(span, box a, box b, box c) => {
-                IfElse(span, box a.try_map_var_refs(f)?, box b.try_map_var_refs(f)?, box c.try_map_var_refs(f)?)
+            BinOp(span, box a, op, box b) => {
+                BinOp(span, box a.try_map_free_var_refs(f, bound)?, op, box b.try_map_free_var_refs(f, bound)?)
             }
-            JsonAccess(span, box a, box b) => JsonAccess(span, box a.try_map_var_refs(f)?, box b.try_map_var_refs(f)?),
-            FunctionCall(span, name, args) => {
-                FunctionCall(span, name, args.into_iter().map(|x| x.try_map_var_refs(f)).collect::<Result<_, _>>()?)
+            Concat(span, elems) => Concat(span, map_all(elems)?),
+            UnaryOp(span, op, box a) => UnaryOp(span, op, box a.try_map_free_var_refs(f, bound)?),
+            IfElse(span, box a, box b, box c) => IfElse(
+                span,
+                box a.try_map_free_var_refs(f, bound)?,
+                box b.try_map_free_var_refs(f, bound)?,
+                box c.try_map_free_var_refs(f, bound)?,
+            ),
+            JsonAccess(span, box a, box b) => {
+                JsonAccess(span, box a.try_map_free_var_refs(f, bound)?, box b.try_map_free_var_refs(f, bound)?)
             }
+            FunctionCall(span, name, args) => FunctionCall(span, name, map_all(args)?),
+            VarRef(span, name) if bound.contains(&name) => VarRef(span, name),
             VarRef(span, name) => f(span, name)?,
-            JsonArray(span, values) => {
-                JsonArray(span, values.into_iter().map(|x| x.try_map_var_refs(f)).collect::<Result<_, _>>()?)
-            }
+            JsonArray(span, values) => JsonArray(span, map_all(values)?),
             JsonObject(span, entries) => JsonObject(
                 span,
                 entries
                     .into_iter()
-                    .map(|(k, v)| Ok((k.try_map_var_refs(f)?, v.try_map_var_refs(f)?)))
+                    .map(|(k, v)| Ok((k.try_map_free_var_refs(f, bound)?, v.try_map_free_var_refs(f, bound)?)))
                     .collect::<Result<_, _>>()?,
             ),
+            Lambda(span, param, box body) => {
+                let mut bound = bound.to_vec();
+                bound.push(param.clone());
+                Lambda(span, param, box body.try_map_free_var_refs(f, &bound)?)
+            }
             x @ Literal(..) => x,
         })
     }
@@ -135,7 +194,10 @@ impl SimplExpr {
             }
             FunctionCall(_, _, args) => args.iter().flat_map(|a| a.var_refs_with_span()).collect(),
             JsonArray(_, values) => values.iter().flat_map(|v| v.var_refs_with_span()).collect(),
-            JsonObject(_, entries) => entries.iter().flat_map(|(k, v)| k.var_refs_with_span().into_iter().chain(v.var_refs_with_span())).collect(),
+            JsonObject(_, entries) => {
+                entries.iter().flat_map(|(k, v)| k.var_refs_with_span().into_iter().chain(v.var_refs_with_span())).collect()
+            }
+            Lambda(_, param, body) => body.var_refs_with_span().into_iter().filter(|(_, name)| *name != param).collect(),
         }
     }
 
@@ -150,29 +212,37 @@ impl SimplExpr {
     }
 
     pub fn eval(&self, values: &HashMap<VarName, DynVal>) -> Result<DynVal, EvalError> {
+        self.eval_in_scope(&Scope::Variables(values))
+    }
+
+    pub(crate) fn eval_in_scope(&self, scope: &Scope) -> Result<DynVal, EvalError> {
         let span = self.span();
         let value = match self {
             SimplExpr::Literal(x) => Ok(x.clone()),
             SimplExpr::Concat(span, elems) => {
                 let mut output = String::new();
                 for elem in elems {
-                    let result = elem.eval(values)?;
-                    output.push_str(&result.0);
+                    let result = elem.eval_in_scope(scope)?;
+                    output.push_str(&result.as_str());
                 }
-                Ok(DynVal(output, *span))
-            }
-            SimplExpr::VarRef(span, ref name) => {
-                let similar_ish =
-                    values.keys().filter(|keys| levenshtein::levenshtein(&keys.0, &name.0) < 3).cloned().collect_vec();
-                Ok(values
-                    .get(name)
-                    .cloned()
-                    .ok_or_else(|| EvalError::UnknownVariable(name.clone(), similar_ish).at(*span))?
-                    .at(*span))
+                Ok(DynVal::from(output).at(*span))
             }
+            SimplExpr::VarRef(span, ref name) => Ok(scope
+                .get(name)
+                .cloned()
+                .ok_or_else(|| {
+                    let similar_ish = scope
+                        .names()
+                        .into_iter()
+                        .filter(|key| levenshtein::levenshtein(&key.0, &name.0) < 3)
+                        .cloned()
+                        .collect_vec();
+                    EvalError::UnknownVariable(name.clone(), similar_ish).at(*span)
+                })?
+                .at(*span)),
             SimplExpr::BinOp(span, a, op, b) => {
-                let a = a.eval(values)?;
-                let b = b.eval(values)?;
+                let a = a.eval_in_scope(scope)?;
+                let b = b.eval_in_scope(scope)?;
                 let dynval = match op {
                     BinOp::Equals => DynVal::from(a == b),
                     BinOp::NotEquals => DynVal::from(a != b),
@@ -188,8 +258,10 @@ impl SimplExpr {
                     BinOp::Mod => DynVal::from(a.as_f64()? % b.as_f64()?),
                     BinOp::GT => DynVal::from(a.as_f64()? > b.as_f64()?),
                     BinOp::LT => DynVal::from(a.as_f64()? < b.as_f64()?),
-                    #[allow(clippy::useless_conversion)]
-                    BinOp::Elvis => DynVal::from(if a.0.is_empty() { b } else { a }),
+                    BinOp::Elvis => match &a.0 {
+                        Value::String(s) if s.is_empty() => b,
+                        _ => a,
+                    },
                     BinOp::RegexMatch => {
                         let regex = regex::Regex::new(&b.as_string()?)?;
                         DynVal::from(regex.is_match(&a.as_string()?))
@@ -198,79 +270,133 @@ impl SimplExpr {
                 Ok(dynval.at(*span))
             }
             SimplExpr::UnaryOp(span, op, a) => {
-                let a = a.eval(values)?;
+                let a = a.eval_in_scope(scope)?;
                 Ok(match op {
                     UnaryOp::Not => DynVal::from(!a.as_bool()?).at(*span),
                 })
             }
             SimplExpr::IfElse(_, cond, yes, no) => {
-                if cond.eval(values)?.as_bool()? {
-                    yes.eval(values)
+                if cond.eval_in_scope(scope)?.as_bool()? {
+                    yes.eval_in_scope(scope)
                 } else {
-                    no.eval(values)
+                    no.eval_in_scope(scope)
                 }
             }
             SimplExpr::JsonAccess(span, val, index) => {
-                let val = val.eval(values)?;
-                let index = index.eval(values)?;
-                match val.as_json_value()? {
-                    serde_json::Value::Array(val) => {
+                let val = val.eval_in_scope(scope)?;
+                let index = index.eval_in_scope(scope)?;
+                // Plain strings (i.e. json literals in the config) still need to be parsed here.
+                let parsed;
+                let container = match &val.0 {
+                    Value::String(_) => {
+                        parsed = Value::from(val.as_json_value()?);
+                        &parsed
+                    }
+                    other => other,
+                };
+                match container {
+                    Value::Array(items) => {
                         let index = index.as_i32()?;
-                        let indexed_value = val.get(index as usize).unwrap_or(&serde_json::Value::Null);
+                        let indexed_value = items.get(index as usize).cloned().unwrap_or(Value::Null);
                         Ok(DynVal::from(indexed_value).at(*span))
                     }
-                    serde_json::Value::Object(val) => {
-                        let indexed_value = val
-                            .get(&index.as_string()?)
-                            .or_else(|| val.get(&index.as_i32().ok()?.to_string()))
-                            .unwrap_or(&serde_json::Value::Null);
+                    Value::Object(entries) => {
+                        let indexed_value = entries
+                            .get(index.as_str().as_ref())
+                            .or_else(|| entries.get(&index.as_i32().ok()?.to_string()))
+                            .cloned()
+                            .unwrap_or(Value::Null);
                         Ok(DynVal::from(indexed_value).at(*span))
                     }
                     _ => Err(EvalError::CannotIndex(format!("{}", val)).at(*span)),
                 }
             }
-            SimplExpr::FunctionCall(span, function_name, args) => {
-                let args = args.iter().map(|a| a.eval(values)).collect::<Result<_, EvalError>>()?;
-                call_expr_function(function_name, args).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+            SimplExpr::FunctionCall(span, function_name, _) if function_name == META_FUNCTION_NAME => {
+                let var_name = self.as_meta_call().ok_or_else(|| EvalError::InvalidMetaCall.at(*span))?;
+                Ok(scope
+                    .get(&meta_var_name(var_name))
+                    .cloned()
+                    .ok_or_else(|| EvalError::NoMetadata(var_name.clone()).at(*span))?)
             }
-            SimplExpr::JsonArray(span, entries) => {
-                let entries = entries
+            SimplExpr::FunctionCall(span, function_name, args) => {
+                let args = args
                     .iter()
-                    .map(|v| Ok(serde_json::Value::String(v.eval(values)?.as_string()?)))
+                    .map(|a| match a {
+                        SimplExpr::Lambda(span, param, body) => Ok(FunctionArg::Lambda(Lambda::new(*span, param, body, scope))),
+                        other => Ok(FunctionArg::Value(other.eval_in_scope(scope)?)),
+                    })
                     .collect::<Result<_, EvalError>>()?;
-                Ok(DynVal::try_from(serde_json::Value::Array(entries))?.at(*span))
+                functions::call_function(function_name, args).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+            }
+            SimplExpr::Lambda(span, ..) => Err(EvalError::UnexpectedLambda.at(*span)),
+            SimplExpr::JsonArray(span, entries) => {
+                let entries = entries.iter().map(|v| Ok(v.eval_in_scope(scope)?.0)).collect::<Result<_, EvalError>>()?;
+                Ok(DynVal::from(Value::Array(Arc::new(entries))).at(*span))
             }
             SimplExpr::JsonObject(span, entries) => {
                 let entries = entries
                     .iter()
-                    .map(|(k, v)| Ok((k.eval(values)?.as_string()?, serde_json::Value::String(v.eval(values)?.as_string()?))))
+                    .map(|(k, v)| Ok((k.eval_in_scope(scope)?.into_inner(), v.eval_in_scope(scope)?.0)))
                     .collect::<Result<_, EvalError>>()?;
-                Ok(DynVal::try_from(serde_json::Value::Object(entries))?.at(*span))
+                Ok(DynVal::from(Value::Object(Arc::new(entries))).at(*span))
             }
         };
         Ok(value?.at(span))
     }
 }
 
-fn call_expr_function(name: &str, args: Vec<DynVal>) -> Result<DynVal, EvalError> {
-    match name {
-        "round" => match args.as_slice() {
-            [num, digits] => {
-                let num = num.as_f64()?;
-                let digits = digits.as_i32()?;
-                Ok(DynVal::from(format!("{:.1$}", num, digits as usize)))
-            }
-            _ => Err(EvalError::WrongArgCount(name.to_string())),
-        },
-        "replace" => match args.as_slice() {
-            [string, pattern, replacement] => {
-                let string = string.as_string()?;
-                let pattern = regex::Regex::new(&pattern.as_string()?)?;
-                let replacement = replacement.as_string()?;
-                Ok(DynVal::from(pattern.replace_all(&string, replacement.replace("$", "$$").replace("\\", "$")).into_owned()))
-            }
-            _ => Err(EvalError::WrongArgCount(name.to_string())),
-        },
-        _ => Err(EvalError::UnknownFunction(name.to_string())),
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_string;
+
+    fn eval_with_cores(code: &str) -> String {
+        let cores = DynVal::from_string_or_json(
+            r#"[{"core": 0, "usage": 20}, {"core": 1, "usage": 5}, {"core": 2, "usage": 50}]"#.to_string(),
+        );
+        let values = std::iter::once((VarName::from("cores"), cores)).collect();
+        parse_string(0, 0, code).unwrap().eval(&values).unwrap().as_string().unwrap()
+    }
+
+    #[test]
+    fn test_higher_order_functions() {
+        assert_eq!(eval_with_cores("map(cores, c => c.usage * 2)"), "[40,10,100]");
+        assert_eq!(eval_with_cores("join(map(filter(cores, c => c.usage > 10), c => c.core), ',')"), "0,2");
+        assert_eq!(eval_with_cores("sort_by(cores, c => 0 - c.usage)[0].core"), "2");
+        assert_eq!(eval_with_cores("sum(map(cores, c => c.usage)) / length(cores)"), "25");
+        assert_eq!(eval_with_cores("max(map(cores, c => c.usage))"), "50");
+        assert_eq!(eval_with_cores("min(3, 1, 2)"), "1");
+        assert_eq!(eval_with_cores("map([1, 2], cores => cores + 1)"), "[2,3]");
+        assert_eq!(eval_with_cores("map([1, 2], x => map([10], y => x + y + length(cores)))"), "[[14],[15]]");
+        assert!(parse_string(0, 0, "max([])").unwrap().eval(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_meta() {
+        let expr = parse_string(0, 0, "meta(battery).stale ? 'stale' : battery").unwrap();
+        assert_eq!(expr.collect_var_refs(), vec![VarName::from("battery.meta"), VarName::from("battery")]);
+        assert!(expr.references_var(&VarName::from("battery.meta")));
+
+        let values = vec![
+            (VarName::from("battery"), DynVal::from(12)),
+            (VarName::from("battery.meta"), DynVal::from(serde_json::json!({ "stale": true }))),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(expr.eval(&values).unwrap().as_string().unwrap(), "stale");
+        assert!(matches!(
+            parse_string(0, 0, "meta(other)").unwrap().eval(&values),
+            Err(EvalError::Spanned(_, box EvalError::NoMetadata(_)))
+        ));
+        assert!(matches!(
+            parse_string(0, 0, "meta(1)").unwrap().eval(&values),
+            Err(EvalError::Spanned(_, box EvalError::InvalidMetaCall))
+        ));
+    }
+
+    #[test]
+    fn test_lambda_params_are_not_var_refs() {
+        let expr = parse_string(0, 0, "map(cores, c => c.usage + offset)").unwrap();
+        assert_eq!(expr.collect_var_refs(), vec![VarName::from("cores"), VarName::from("offset")]);
     }
 }
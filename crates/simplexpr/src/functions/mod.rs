@@ -0,0 +1,333 @@
+//! The functions that can be called from within simplexpr.
+//!
+//! Every function is registered with a signature, which is used to check the arguments before the function is run,
+//! and a short description that is shown in the documentation.
+//! Besides the standard library defined in [`stdlib`], applications can add their own functions via [`register_function`].
+
+mod stdlib;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use eww_shared_util::{Span, Spanned, VarName};
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+
+use crate::{
+    ast::SimplExpr,
+    dynval::{DynVal, Value, ValueType},
+    eval::{EvalError, Scope},
+};
+
+pub type FunctionImpl = dyn Fn(&mut [FunctionArg]) -> Result<DynVal, EvalError> + Send + Sync;
+
+static REGISTRY: Lazy<RwLock<FunctionRegistry>> = Lazy::new(|| {
+    let mut registry = FunctionRegistry::default();
+    stdlib::register_all(&mut registry);
+    RwLock::new(registry)
+});
+
+/// Register a function that can be called from any expression, replacing any function of the same name.
+///
+/// The signature is given in the same form it is shown in the documentation, i.e.
+/// `substring(string: string, start: number, length?: number) -> string`.
+pub fn register_function(
+    signature: &str,
+    doc: &str,
+    implementation: impl Fn(&mut [FunctionArg]) -> Result<DynVal, EvalError> + Send + Sync + 'static,
+) -> Result<(), SignatureParseError> {
+    let function = Function::new(signature, doc, implementation)?;
+    REGISTRY.write().unwrap().insert(function);
+    Ok(())
+}
+
+pub fn get_function(name: &str) -> Option<Arc<Function>> {
+    REGISTRY.read().unwrap().functions.get(name).cloned()
+}
+
+/// All registered functions, sorted by name.
+pub fn all_functions() -> Vec<Arc<Function>> {
+    REGISTRY.read().unwrap().functions.values().cloned().sorted_by(|a, b| a.name().cmp(b.name())).collect()
+}
+
+pub(crate) fn call_function(name: &str, mut args: Vec<FunctionArg>) -> Result<DynVal, EvalError> {
+    let function = get_function(name).ok_or_else(|| EvalError::UnknownFunction(name.to_string()))?;
+    function.call(&mut args)
+}
+
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Arc<Function>>,
+}
+
+impl FunctionRegistry {
+    fn insert(&mut self, function: Function) {
+        self.functions.insert(function.name().to_string(), Arc::new(function));
+    }
+
+    /// Add a function with a signature that is known to be valid.
+    fn add(
+        &mut self,
+        signature: &str,
+        doc: &str,
+        implementation: impl Fn(&mut [FunctionArg]) -> Result<DynVal, EvalError> + Send + Sync + 'static,
+    ) {
+        match Function::new(signature, doc, implementation) {
+            Ok(function) => self.insert(function),
+            Err(err) => panic!("Invalid signature for builtin function: {}", err),
+        }
+    }
+}
+
+pub struct Function {
+    pub signature: Signature,
+    pub doc: String,
+    implementation: Box<FunctionImpl>,
+}
+
+impl Function {
+    pub fn new(
+        signature: &str,
+        doc: &str,
+        implementation: impl Fn(&mut [FunctionArg]) -> Result<DynVal, EvalError> + Send + Sync + 'static,
+    ) -> Result<Self, SignatureParseError> {
+        Ok(Function { signature: signature.parse()?, doc: doc.to_string(), implementation: Box::new(implementation) })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.signature.name
+    }
+
+    /// Check the arguments against the signature, converting them to the declared types, and run the function.
+    pub fn call(&self, args: &mut [FunctionArg]) -> Result<DynVal, EvalError> {
+        if !self.signature.accepts_arg_count(args.len()) {
+            return Err(EvalError::WrongArgCount(self.signature.to_string()));
+        }
+        for (arg, param) in args.iter_mut().zip(self.signature.param_for_each_arg()) {
+            match (param.ty, arg) {
+                (ParamType::Lambda, arg) => {
+                    arg.lambda()?;
+                }
+                (ParamType::Value(ty), FunctionArg::Value(value)) => {
+                    let taken = std::mem::replace(value, DynVal::from(Value::Null));
+                    *value = taken.coerce_to(ty)?;
+                }
+                (ParamType::Value(_), FunctionArg::Lambda(lambda)) => return Err(EvalError::UnexpectedLambda.at(lambda.span)),
+            }
+        }
+        (self.implementation)(args)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    Value(ValueType),
+    Lambda,
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamType::Value(ty) => write!(f, "{}", ty),
+            ParamType::Lambda => write!(f, "lambda"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub ty: ParamType,
+    pub optional: bool,
+}
+
+/// The signature of a function, i.e. `join(array: array, separator: string) -> string`.
+///
+/// Optional parameters are marked with `?`, and may only be followed by other optional parameters.
+/// The last parameter may be marked with `...`, in which case it can be given any amount of times, but at least once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub variadic: bool,
+    pub returns: ValueType,
+}
+
+impl Signature {
+    pub fn min_args(&self) -> usize {
+        self.params.iter().filter(|p| !p.optional).count()
+    }
+
+    pub fn max_args(&self) -> Option<usize> {
+        if self.variadic {
+            None
+        } else {
+            Some(self.params.len())
+        }
+    }
+
+    pub fn accepts_arg_count(&self, count: usize) -> bool {
+        count >= self.min_args() && self.max_args().map(|max| count <= max).unwrap_or(true)
+    }
+
+    /// The parameter each argument is matched with, repeating the last one for variadic functions.
+    pub fn param_for_each_arg(&self) -> impl Iterator<Item = &Param> {
+        let repeated = if self.variadic { self.params.last() } else { None };
+        self.params.iter().chain(std::iter::repeat(repeated).flatten())
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let last = self.params.len().saturating_sub(1);
+        let params = self.params.iter().enumerate().map(|(i, p)| {
+            let marker = if p.optional {
+                "?"
+            } else if self.variadic && i == last {
+                "..."
+            } else {
+                ""
+            };
+            format!("{}{}: {}", p.name, marker, p.ty)
+        });
+        write!(f, "{}({}) -> {}", self.name, params.format(", "), self.returns)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid function signature `{signature}`: {reason}")]
+pub struct SignatureParseError {
+    pub signature: String,
+    pub reason: String,
+}
+
+impl FromStr for Signature {
+    type Err = SignatureParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fail = |reason: &str| SignatureParseError { signature: s.to_string(), reason: reason.to_string() };
+        let parse_type = |ty: &str| match ty.trim() {
+            "lambda" => Ok(ParamType::Lambda),
+            other => other.parse().map(ParamType::Value).map_err(|_| fail(&format!("unknown type `{}`", other))),
+        };
+
+        let (head, returns) = s.rsplit_once("->").ok_or_else(|| fail("missing return type"))?;
+        let returns = match parse_type(returns)? {
+            ParamType::Value(ty) => ty,
+            ParamType::Lambda => return Err(fail("functions cannot return lambdas")),
+        };
+        let (name, params) =
+            head.trim().strip_suffix(')').and_then(|x| x.split_once('(')).ok_or_else(|| fail("missing parameter list"))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(fail("missing function name"));
+        }
+
+        let mut signature = Signature { name: name.to_string(), params: Vec::new(), variadic: false, returns };
+        for param in params.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            if signature.variadic {
+                return Err(fail("only the last parameter may be variadic"));
+            }
+            let (param_name, ty) = param.split_once(':').ok_or_else(|| fail(&format!("parameter `{}` has no type", param)))?;
+            let param_name = param_name.trim();
+            let (param_name, optional) = match param_name.strip_suffix('?') {
+                Some(param_name) => (param_name, true),
+                None if signature.params.last().map(|p| p.optional).unwrap_or(false) => {
+                    return Err(fail("required parameters cannot follow optional ones"))
+                }
+                None => (param_name, false),
+            };
+            let param_name = match param_name.strip_suffix("...") {
+                Some(param_name) => {
+                    signature.variadic = true;
+                    param_name
+                }
+                None => param_name,
+            };
+            signature.params.push(Param { name: param_name.to_string(), ty: parse_type(ty)?, optional });
+        }
+        if signature.variadic && signature.params.iter().any(|p| p.optional) {
+            return Err(fail("variadic functions cannot have optional parameters"));
+        }
+        Ok(signature)
+    }
+}
+
+/// An argument passed to a function. Lambdas are only evaluated by the function they are passed to.
+pub enum FunctionArg<'a> {
+    Value(DynVal),
+    Lambda(Lambda<'a>),
+}
+
+impl<'a> FunctionArg<'a> {
+    pub fn value(&self) -> Result<&DynVal, EvalError> {
+        match self {
+            FunctionArg::Value(x) => Ok(x),
+            FunctionArg::Lambda(lambda) => Err(EvalError::UnexpectedLambda.at(lambda.span)),
+        }
+    }
+
+    pub fn lambda(&self) -> Result<&Lambda<'a>, EvalError> {
+        match self {
+            FunctionArg::Lambda(lambda) => Ok(lambda),
+            FunctionArg::Value(x) => Err(EvalError::ExpectedLambda.at(x.span())),
+        }
+    }
+}
+
+/// A lambda together with the variables that were in scope where it was written.
+pub struct Lambda<'a> {
+    span: Span,
+    param: &'a VarName,
+    body: &'a SimplExpr,
+    scope: &'a Scope<'a>,
+}
+
+impl<'a> Lambda<'a> {
+    pub(crate) fn new(span: Span, param: &'a VarName, body: &'a SimplExpr, scope: &'a Scope<'a>) -> Self {
+        Lambda { span, param, body, scope }
+    }
+
+    pub fn call(&self, arg: &Value) -> Result<DynVal, EvalError> {
+        let arg = DynVal::from(arg.clone()).at(self.span);
+        self.body.eval_in_scope(&Scope::Lambda { param: self.param, arg: &arg, outer: self.scope })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_signature() {
+        let signature: Signature = "substring(string: string, start: number, length?: number) -> string".parse().unwrap();
+        assert_eq!(signature.min_args(), 2);
+        assert_eq!(signature.max_args(), Some(3));
+        assert_eq!(signature.to_string(), "substring(string: string, start: number, length?: number) -> string");
+
+        let signature: Signature = "max(values...: any) -> any".parse().unwrap();
+        assert!(signature.variadic);
+        assert!(!signature.accepts_arg_count(0));
+        assert!(signature.accepts_arg_count(5));
+        assert_eq!(signature.to_string(), "max(values...: any) -> any");
+
+        assert!("foo(a?: number, b: number) -> any".parse::<Signature>().is_err());
+        assert!("foo(a: nope) -> any".parse::<Signature>().is_err());
+        assert!("foo(a: number)".parse::<Signature>().is_err());
+    }
+
+    #[test]
+    fn test_register_function() {
+        register_function("double(num: number) -> number", "Multiply a number by two", |args| {
+            Ok(DynVal::from(args[0].value()?.as_f64()? * 2.0))
+        })
+        .unwrap();
+        let result = crate::parse_string(0, 0, "double('21')").unwrap().eval_no_vars().unwrap();
+        assert_eq!(result.as_string().unwrap(), "42");
+        assert!(crate::parse_string(0, 0, "double(1, 2)").unwrap().eval_no_vars().is_err());
+    }
+}
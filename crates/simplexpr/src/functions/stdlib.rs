@@ -0,0 +1,358 @@
+use std::{cmp::Ordering, sync::Arc};
+
+use chrono::{format::StrftimeItems, Local, TimeZone, Utc};
+use itertools::Itertools;
+
+use super::{FunctionArg, FunctionRegistry};
+use crate::{
+    dynval::{DynVal, Value},
+    eval::EvalError,
+};
+
+pub(super) fn register_all(registry: &mut FunctionRegistry) {
+    register_math(registry);
+    register_strings(registry);
+    register_time(registry);
+    register_json(registry);
+    register_arrays(registry);
+    register_regex(registry);
+}
+
+fn array(items: Vec<Value>) -> DynVal {
+    DynVal::from(Value::Array(Arc::new(items)))
+}
+
+fn number(args: &[FunctionArg], index: usize) -> Result<f64, EvalError> {
+    Ok(args[index].value()?.as_f64()?)
+}
+
+fn string(args: &[FunctionArg], index: usize) -> Result<String, EvalError> {
+    Ok(args[index].value()?.as_string()?)
+}
+
+/// Order numbers numerically, and anything else by its string representation.
+fn compare_dynvals(a: &DynVal, b: &DynVal) -> Ordering {
+    match (a.to_f64(), b.to_f64()) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.as_str().cmp(&b.as_str()),
+    }
+}
+
+/// Find the extreme value of either a single array argument, or all of the arguments.
+fn extreme_value(args: &[FunctionArg], wanted: Ordering) -> Result<DynVal, EvalError> {
+    let candidates = match args {
+        [array] => array.value()?.as_array()?.iter().map(|x| DynVal::from(x.clone())).collect_vec(),
+        _ => args.iter().map(|x| x.value().map(DynVal::clone)).collect::<Result<_, _>>()?,
+    };
+    let mut result: Option<DynVal> = None;
+    for candidate in candidates {
+        candidate.as_f64()?;
+        result = match result {
+            Some(current) if compare_dynvals(&candidate, &current) != wanted => Some(current),
+            _ => Some(candidate),
+        };
+    }
+    result.ok_or_else(|| EvalError::InvalidArgument("expected at least one number".to_string()))
+}
+
+fn register_math(registry: &mut FunctionRegistry) {
+    registry.add("round(num: number, digits: number) -> string", "Round a number to the given amount of decimals", |args| {
+        let digits = args[1].value()?.as_i32()?;
+        Ok(DynVal::from(format!("{:.1$}", number(args, 0)?, digits.max(0) as usize)))
+    });
+    registry.add("floor(num: number) -> number", "Round a number down to the next integer", |args| {
+        Ok(DynVal::from(number(args, 0)?.floor()))
+    });
+    registry.add("ceil(num: number) -> number", "Round a number up to the next integer", |args| {
+        Ok(DynVal::from(number(args, 0)?.ceil()))
+    });
+    registry.add("abs(num: number) -> number", "The absolute value of a number", |args| Ok(DynVal::from(number(args, 0)?.abs())));
+    registry.add("pow(base: number, exponent: number) -> number", "Raise a number to the given power", |args| {
+        Ok(DynVal::from(number(args, 0)?.powf(number(args, 1)?)))
+    });
+    registry.add("sqrt(num: number) -> number", "The square root of a number", |args| Ok(DynVal::from(number(args, 0)?.sqrt())));
+    registry.add("clamp(num: number, min: number, max: number) -> number", "Limit a number to the given range", |args| {
+        Ok(DynVal::from(number(args, 0)?.max(number(args, 1)?).min(number(args, 2)?)))
+    });
+    registry.add(
+        "min(values...: any) -> number",
+        "The smallest of the given numbers. When given a single array, the smallest number in that array, which must not be \
+         empty. Values that aren't numbers are an error",
+        |args| extreme_value(args, Ordering::Less),
+    );
+    registry.add(
+        "max(values...: any) -> number",
+        "The largest of the given numbers. When given a single array, the largest number in that array, which must not be \
+         empty. Values that aren't numbers are an error",
+        |args| extreme_value(args, Ordering::Greater),
+    );
+    registry.add("sum(array: array) -> number", "Add up all the numbers in an array", |args| {
+        let items = args[0].value()?.as_array()?;
+        let sum = items.iter().map(|x| DynVal::from(x.clone()).as_f64()).sum::<Result<f64, _>>()?;
+        Ok(DynVal::from(sum))
+    });
+}
+
+fn register_strings(registry: &mut FunctionRegistry) {
+    registry.add(
+        "replace(string: string, regex: string, replacement: string) -> string",
+        "Replace all matches of a regex in a string. Capture groups can be referenced as `\\1`",
+        |args| {
+            let pattern = regex::Regex::new(&string(args, 1)?)?;
+            let replacement = string(args, 2)?;
+            let replaced = pattern.replace_all(&string(args, 0)?, replacement.replace("$", "$$").replace("\\", "$")).into_owned();
+            Ok(DynVal::from(replaced))
+        },
+    );
+    registry.add("uppercase(string: string) -> string", "Convert a string to uppercase", |args| {
+        Ok(DynVal::from(string(args, 0)?.to_uppercase()))
+    });
+    registry.add("lowercase(string: string) -> string", "Convert a string to lowercase", |args| {
+        Ok(DynVal::from(string(args, 0)?.to_lowercase()))
+    });
+    registry.add("trim(string: string) -> string", "Remove whitespace from the start and end of a string", |args| {
+        Ok(DynVal::from(string(args, 0)?.trim()))
+    });
+    registry.add(
+        "substring(string: string, start: number, length?: number) -> string",
+        "Get a part of a string, counted in characters. Without a length, everything after the start is returned",
+        |args| {
+            let text = string(args, 0)?;
+            let start = args[1].value()?.as_i32()?.max(0) as usize;
+            let length = match args.get(2) {
+                Some(length) => length.value()?.as_i32()?.max(0) as usize,
+                None => usize::MAX,
+            };
+            Ok(DynVal::from(text.chars().skip(start).take(length).collect::<String>()))
+        },
+    );
+    registry.add("split(string: string, separator: string) -> array", "Split a string into an array of strings", |args| {
+        let text = string(args, 0)?;
+        let separator = string(args, 1)?;
+        Ok(array(text.split(separator.as_str()).map(|x| Value::String(x.to_string())).collect()))
+    });
+    registry.add(
+        "contains(haystack: any, needle: any) -> bool",
+        "Check if a string contains a substring, an array contains an element, or an object contains a key",
+        |args| {
+            let needle = args[1].value()?;
+            let found = match &args[0].value()?.0 {
+                Value::Array(items) => items.iter().any(|x| &DynVal::from(x.clone()) == needle),
+                Value::Object(entries) => entries.contains_key(needle.as_str().as_ref()),
+                other => other.to_string().contains(needle.as_str().as_ref()),
+            };
+            Ok(DynVal::from(found))
+        },
+    );
+    registry.add(
+        "starts_with(string: string, prefix: string) -> bool",
+        "Check if a string starts with the given prefix",
+        |args| Ok(DynVal::from(string(args, 0)?.starts_with(&string(args, 1)?))),
+    );
+    registry.add("ends_with(string: string, suffix: string) -> bool", "Check if a string ends with the given suffix", |args| {
+        Ok(DynVal::from(string(args, 0)?.ends_with(&string(args, 1)?)))
+    });
+}
+
+fn register_time(registry: &mut FunctionRegistry) {
+    registry.add(
+        "now() -> number",
+        "The current unix timestamp in seconds. Note that expressions are only re-evaluated when a variable they use changes",
+        |_| Ok(DynVal::from(Utc::now().timestamp() as f64)),
+    );
+    registry.add(
+        "format_time(timestamp: number, format: string, utc?: bool) -> string",
+        "Format a unix timestamp using strftime-style format specifiers (i.e. `%H:%M`), in local time unless `utc` is true",
+        |args| {
+            let timestamp = number(args, 0)?;
+            let format = string(args, 1)?;
+            if StrftimeItems::new(&format).any(|item| item == chrono::format::Item::Error) {
+                return Err(EvalError::InvalidArgument(format!("`{}` is not a valid time format", format)));
+            }
+            // the fraction of a negative timestamp still counts forward from the whole second before it
+            let (secs, nanos) = (timestamp.div_euclid(1.0) as i64, ((timestamp.rem_euclid(1.0) * 1e9) as u32).min(999_999_999));
+            let utc = match args.get(2) {
+                Some(utc) => utc.value()?.as_bool()?,
+                None => false,
+            };
+            let formatted = if utc {
+                Utc.timestamp_opt(secs, nanos).single().map(|time| time.format(&format).to_string())
+            } else {
+                Local.timestamp_opt(secs, nanos).single().map(|time| time.format(&format).to_string())
+            };
+            formatted
+                .map(DynVal::from)
+                .ok_or_else(|| EvalError::InvalidArgument(format!("{} is not a valid timestamp", timestamp)))
+        },
+    );
+    registry.add(
+        "format_duration(seconds: number) -> string",
+        "Format an amount of seconds as a human readable duration, i.e. `2d 3h 15m`",
+        |args| {
+            let total = number(args, 0)?.max(0.0) as u64;
+            let units = [(total / 86400, "d"), (total % 86400 / 3600, "h"), (total % 3600 / 60, "m"), (total % 60, "s")];
+            let parts = units.iter().skip_while(|(amount, _)| *amount == 0).map(|(amount, unit)| format!("{}{}", amount, unit));
+            let formatted = parts.take(3).join(" ");
+            Ok(DynVal::from(if formatted.is_empty() { "0s".to_string() } else { formatted }))
+        },
+    );
+}
+
+fn register_json(registry: &mut FunctionRegistry) {
+    registry.add("keys(object: object) -> array", "The keys of an object, in alphabetical order", |args| {
+        let entries = args[0].value()?.as_object()?;
+        Ok(array(entries.keys().map(|k| Value::String(k.clone())).collect()))
+    });
+    registry.add("values(object: object) -> array", "The values of an object, ordered by their keys", |args| {
+        let entries = args[0].value()?.as_object()?;
+        Ok(array(entries.values().cloned().collect()))
+    });
+    registry.add(
+        "get(value: any, key: any, default?: any) -> any",
+        "Index into an array or object like `value[key]`, returning the default (or null) if there is no such element",
+        |args| {
+            let key = args[1].value()?;
+            let found = match &args[0].value()?.0 {
+                Value::Array(items) => key.as_i32().ok().and_then(|i| items.get(i as usize)).cloned(),
+                Value::Object(entries) => entries.get(key.as_str().as_ref()).cloned(),
+                _ => None,
+            };
+            match (found, args.get(2)) {
+                (Some(found), _) => Ok(DynVal::from(found)),
+                (None, Some(default)) => Ok(default.value()?.clone()),
+                (None, None) => Ok(DynVal::from(Value::Null)),
+            }
+        },
+    );
+    registry.add("to_json(value: any) -> string", "Serialize a value as json", |args| {
+        Ok(DynVal::from(args[0].value()?.0.to_json().to_string()))
+    });
+    registry.add("from_json(string: string) -> any", "Parse a json string into a value", |args| {
+        Ok(DynVal::from(serde_json::from_str::<serde_json::Value>(&string(args, 0)?)?))
+    });
+}
+
+fn register_arrays(registry: &mut FunctionRegistry) {
+    registry.add(
+        "length(value: any) -> number",
+        "The amount of elements of an array or object, or the amount of characters in a string",
+        |args| {
+            Ok(DynVal::from(match &args[0].value()?.0 {
+                Value::Array(items) => items.len() as f64,
+                Value::Object(entries) => entries.len() as f64,
+                other => other.to_string().chars().count() as f64,
+            }))
+        },
+    );
+    registry.add(
+        "join(array: array, separator: string) -> string",
+        "Join the elements of an array into a single string",
+        |args| {
+            let items = args[0].value()?.as_array()?;
+            Ok(DynVal::from(items.iter().join(&string(args, 1)?)))
+        },
+    );
+    registry.add(
+        "slice(array: array, start: number, end?: number) -> array",
+        "The elements from start up to (excluding) end. Negative indices count from the end of the array",
+        |args| {
+            let items = args[0].value()?.as_array()?;
+            let resolve =
+                |index: i32| if index < 0 { items.len().saturating_sub(index.unsigned_abs() as usize) } else { index as usize };
+            let start = resolve(args[1].value()?.as_i32()?).min(items.len());
+            let end = match args.get(2) {
+                Some(end) => resolve(end.value()?.as_i32()?).min(items.len()),
+                None => items.len(),
+            };
+            Ok(array(items.get(start..end.max(start)).unwrap_or_default().to_vec()))
+        },
+    );
+    registry.add("reverse(array: array) -> array", "Reverse the order of the elements of an array", |args| {
+        let items = args[0].value()?.as_array()?;
+        Ok(array(items.iter().rev().cloned().collect()))
+    });
+    registry.add("map(array: array, f: lambda) -> array", "Transform every element of an array", |args| {
+        let items = args[0].value()?.as_array()?;
+        let f = args[1].lambda()?;
+        let mapped = items.iter().map(|x| Ok(f.call(x)?.0)).collect::<Result<_, EvalError>>()?;
+        Ok(array(mapped))
+    });
+    registry.add(
+        "filter(array: array, f: lambda) -> array",
+        "Only keep the elements of an array for which the lambda returns true",
+        |args| {
+            let items = args[0].value()?.as_array()?;
+            let f = args[1].lambda()?;
+            let mut kept = Vec::new();
+            for item in items.iter() {
+                if f.call(item)?.as_bool()? {
+                    kept.push(item.clone());
+                }
+            }
+            Ok(array(kept))
+        },
+    );
+    registry.add(
+        "sort_by(array: array, f: lambda) -> array",
+        "Sort an array by the value the lambda returns for each element",
+        |args| {
+            let items = args[0].value()?.as_array()?;
+            let f = args[1].lambda()?;
+            let mut keyed = items.iter().map(|x| Ok((f.call(x)?, x.clone()))).collect::<Result<Vec<_>, EvalError>>()?;
+            keyed.sort_by(|(a, _), (b, _)| compare_dynvals(a, b));
+            Ok(array(keyed.into_iter().map(|(_, x)| x).collect()))
+        },
+    );
+}
+
+fn register_regex(registry: &mut FunctionRegistry) {
+    registry.add(
+        "captures(string: string, regex: string) -> array",
+        "The capture groups of the first match of a regex, starting with the whole match. Empty if the regex doesn't match",
+        |args| {
+            let regex = regex::Regex::new(&string(args, 1)?)?;
+            let text = string(args, 0)?;
+            let groups = match regex.captures(&text) {
+                Some(captures) => captures
+                    .iter()
+                    .map(|group| group.map(|m| Value::String(m.as_str().to_string())).unwrap_or(Value::Null))
+                    .collect(),
+                None => Vec::new(),
+            };
+            Ok(array(groups))
+        },
+    );
+    registry.add("matches(string: string, regex: string) -> array", "All matches of a regex in a string", |args| {
+        let regex = regex::Regex::new(&string(args, 1)?)?;
+        let text = string(args, 0)?;
+        Ok(array(regex.find_iter(&text).map(|m| Value::String(m.as_str().to_string())).collect()))
+    });
+}
+
+#[cfg(test)]
+mod test {
+    fn eval(code: &str) -> String {
+        crate::parse_string(0, 0, code).unwrap().eval_no_vars().unwrap().as_string().unwrap()
+    }
+
+    #[test]
+    fn test_stdlib() {
+        assert_eq!(eval("round(1.2345, 2)"), "1.23");
+        assert_eq!(eval("clamp(15, 0, 10)"), "10");
+        assert_eq!(eval("max([3, 12, 5])"), "12");
+        assert!(crate::parse_string(0, 0, "min(1, 'a')").unwrap().eval_no_vars().is_err());
+        assert_eq!(eval("substring('hello world', 6)"), "world");
+        assert_eq!(eval("join(split('a,b,c', ','), ' ')"), "a b c");
+        assert_eq!(eval("contains([1, 2, 3], '2')"), "true");
+        assert_eq!(eval("keys({'b': 1, 'a': 2})"), r#"["a","b"]"#);
+        assert_eq!(eval("get({'a': 1}, 'b', 'fallback')"), "fallback");
+        assert_eq!(eval("slice([1, 2, 3, 4], 0 - 2)"), "[3,4]");
+        assert_eq!(eval("to_json('hi')"), r#""hi""#);
+        assert_eq!(eval("from_json('[1, 2]')[1]"), "2");
+        assert_eq!(eval("captures('volume: 42%', '(\\\\d+)%')[1]"), "42");
+        assert_eq!(eval("matches('a1b22c333', '\\\\d+')"), r#"["1","22","333"]"#);
+        assert_eq!(eval("format_time(0, '%Y-%m-%d %H:%M', true)"), "1970-01-01 00:00");
+        assert_eq!(eval("format_time(0 - 1.5, '%Y-%m-%d %H:%M:%S%.3f', true)"), "1969-12-31 23:59:58.500");
+        assert_eq!(eval("format_duration(93784)"), "1d 2h 3m");
+    }
+}
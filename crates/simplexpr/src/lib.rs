@@ -10,7 +10,9 @@ pub mod ast;
 pub mod dynval;
 pub mod error;
 pub mod eval;
+pub mod functions;
 pub mod parser;
+pub mod typecheck;
 
 pub use ast::SimplExpr;
 
@@ -27,6 +27,7 @@ pub enum Token {
     LT,
     Elvis,
     RegexMatch,
+    Arrow,
 
     Not,
 
@@ -84,6 +85,7 @@ regex_rules! {
     escape(r"<")     => |_| Token::LT,
     escape(r"?:")    => |_| Token::Elvis,
     escape(r"=~")    => |_| Token::RegexMatch,
+    escape(r"=>")    => |_| Token::Arrow,
 
     escape(r"!" )    => |_| Token::Not,
 
@@ -8,7 +8,7 @@ use crate::{
 
 pub fn parse_string(byte_offset: usize, file_id: usize, s: &str) -> Result<SimplExpr> {
     let lexer = lexer::Lexer::new(file_id, byte_offset, s);
-    let parser = crate::simplexpr_parser::ExprParser::new();
+    let parser = crate::simplexpr_parser::ExprOrLambdaParser::new();
     parser.parse(file_id, lexer).map_err(|e| Error::from_parse_error(file_id, e))
 }
 
@@ -44,6 +44,7 @@ mod tests {
             "foo.bar[2 + 2] * asdf[foo.bar]",
             r#"[1, 2, 3 + 4, "bla", [blub, blo]]"#,
             r#"{ "key": "value", 5: 1+2, true: false }"#,
+            "sum(map(filter(cores, c => c.usage > 10), c => c.usage))",
         );
     }
 }
@@ -0,0 +1,255 @@
+//! Static type inference for expressions.
+//!
+//! This is used to report obvious type errors, like `"abc" * 2`, when a config is loaded,
+//! instead of only when the expression is evaluated.
+//! Values of unknown type (i.e. the output of scripts or the arguments of a widget) are treated as [`ValueType::Any`],
+//! and plain strings are assumed to be convertible into any other type unless their content is known,
+//! so only expressions that can never succeed are rejected.
+
+use std::collections::HashMap;
+
+use eww_shared_util::{Span, Spanned, VarName};
+
+use crate::{
+    ast::{BinOp, SimplExpr, UnaryOp, META_FUNCTION_NAME},
+    dynval::{DynVal, Value, ValueType},
+    eval::EvalError,
+    functions::{self, ParamType},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TypeError {
+    #[error("Expected a value of type `{expected}`, but got {found}")]
+    Mismatch { span: Span, expected: ValueType, found: String },
+
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+}
+
+impl Spanned for TypeError {
+    fn span(&self) -> Span {
+        match self {
+            TypeError::Mismatch { span, .. } => *span,
+            TypeError::Eval(err) => err.span(),
+        }
+    }
+}
+
+/// The inferred type of an expression, together with its value if that is already known.
+#[derive(Debug, Clone)]
+struct Inferred {
+    ty: ValueType,
+    constant: Option<DynVal>,
+}
+
+impl Inferred {
+    fn of_type(ty: ValueType) -> Self {
+        Inferred { ty, constant: None }
+    }
+
+    fn can_be(&self, expected: ValueType) -> bool {
+        match (self.ty, expected) {
+            (ValueType::Any, _) | (_, ValueType::Any) | (_, ValueType::String) => true,
+            (found, expected) if found == expected => true,
+            (ValueType::String, expected) => match &self.constant {
+                Some(value) => value.clone().coerce_to(expected).is_ok(),
+                None => true,
+            },
+            _ => false,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.constant {
+            Some(value) => format!("the {} {:?}", self.ty, value),
+            None => format!("a value of type `{}`", self.ty),
+        }
+    }
+}
+
+/// Guess the type of a variable from its initial value, i.e. the value given to a `defvar`.
+pub fn guess_type(value: &DynVal) -> ValueType {
+    match &value.0 {
+        Value::String(s) if s.trim().parse::<f64>().is_ok() => ValueType::Number,
+        Value::String(s) if s == "true" || s == "false" => ValueType::Bool,
+        other => other.value_type(),
+    }
+}
+
+/// Infer the type an expression evaluates to, given the types of the variables it may reference.
+/// Variables that are not given are assumed to be of type [`ValueType::Any`].
+pub fn infer_type(expr: &SimplExpr, vars: &HashMap<VarName, ValueType>) -> Result<ValueType, TypeError> {
+    Ok(infer(expr, vars)?.ty)
+}
+
+/// Check that an expression can evaluate to a value of the expected type.
+pub fn check_type(expr: &SimplExpr, vars: &HashMap<VarName, ValueType>, expected: ValueType) -> Result<(), TypeError> {
+    expect(expr, vars, expected).map(|_| ())
+}
+
+fn expect(expr: &SimplExpr, vars: &HashMap<VarName, ValueType>, expected: ValueType) -> Result<Inferred, TypeError> {
+    let inferred = infer(expr, vars)?;
+    if inferred.can_be(expected) {
+        Ok(inferred)
+    } else {
+        Err(TypeError::Mismatch { span: expr.span(), expected, found: inferred.describe() })
+    }
+}
+
+fn infer(expr: &SimplExpr, vars: &HashMap<VarName, ValueType>) -> Result<Inferred, TypeError> {
+    Ok(match expr {
+        SimplExpr::Literal(value) => Inferred { ty: value.0.value_type(), constant: Some(value.clone()) },
+        SimplExpr::Concat(_, elems) => {
+            for elem in elems {
+                infer(elem, vars)?;
+            }
+            Inferred::of_type(ValueType::String)
+        }
+        SimplExpr::VarRef(_, name) => Inferred::of_type(vars.get(name).copied().unwrap_or(ValueType::Any)),
+        SimplExpr::BinOp(_, a, op, b) => match op {
+            BinOp::Minus | BinOp::Times | BinOp::Div | BinOp::Mod => {
+                expect(a, vars, ValueType::Number)?;
+                expect(b, vars, ValueType::Number)?;
+                Inferred::of_type(ValueType::Number)
+            }
+            BinOp::GT | BinOp::LT => {
+                expect(a, vars, ValueType::Number)?;
+                expect(b, vars, ValueType::Number)?;
+                Inferred::of_type(ValueType::Bool)
+            }
+            BinOp::And | BinOp::Or => {
+                expect(a, vars, ValueType::Bool)?;
+                expect(b, vars, ValueType::Bool)?;
+                Inferred::of_type(ValueType::Bool)
+            }
+            BinOp::Equals | BinOp::NotEquals => {
+                infer(a, vars)?;
+                infer(b, vars)?;
+                Inferred::of_type(ValueType::Bool)
+            }
+            BinOp::Plus => {
+                let (a, b) = (infer(a, vars)?, infer(b, vars)?);
+                match (a.ty, b.ty) {
+                    (ValueType::Number, ValueType::Number) => Inferred::of_type(ValueType::Number),
+                    _ => Inferred::of_type(ValueType::Any),
+                }
+            }
+            BinOp::Elvis => {
+                let (a, b) = (infer(a, vars)?, infer(b, vars)?);
+                Inferred::of_type(if a.ty == b.ty { a.ty } else { ValueType::Any })
+            }
+            BinOp::RegexMatch => {
+                infer(a, vars)?;
+                if let Some(regex) = infer(b, vars)?.constant {
+                    regex::Regex::new(&regex.as_str()).map_err(|err| EvalError::from(err).at(b.span()))?;
+                }
+                Inferred::of_type(ValueType::Bool)
+            }
+        },
+        SimplExpr::UnaryOp(_, UnaryOp::Not, a) => {
+            expect(a, vars, ValueType::Bool)?;
+            Inferred::of_type(ValueType::Bool)
+        }
+        SimplExpr::IfElse(_, cond, yes, no) => {
+            expect(cond, vars, ValueType::Bool)?;
+            let (yes, no) = (infer(yes, vars)?, infer(no, vars)?);
+            Inferred::of_type(if yes.ty == no.ty { yes.ty } else { ValueType::Any })
+        }
+        SimplExpr::JsonAccess(span, value, index) => {
+            let container = infer(value, vars)?;
+            infer(index, vars)?;
+            match container.ty {
+                ValueType::Number | ValueType::Bool | ValueType::Null => {
+                    return Err(EvalError::CannotIndex(value.to_string()).at(*span).into())
+                }
+                _ => Inferred::of_type(ValueType::Any),
+            }
+        }
+        SimplExpr::FunctionCall(span, name, _) if name == META_FUNCTION_NAME => match expr.as_meta_call() {
+            Some(_) => Inferred::of_type(ValueType::Object),
+            None => return Err(EvalError::InvalidMetaCall.at(*span).into()),
+        },
+        SimplExpr::FunctionCall(span, name, args) => {
+            let function = functions::get_function(name).ok_or_else(|| EvalError::UnknownFunction(name.clone()).at(*span))?;
+            let signature = &function.signature;
+            if !signature.accepts_arg_count(args.len()) {
+                return Err(EvalError::WrongArgCount(signature.to_string()).at(*span).into());
+            }
+            for (arg, param) in args.iter().zip(signature.param_for_each_arg()) {
+                match (param.ty, arg) {
+                    (ParamType::Lambda, SimplExpr::Lambda(_, param, body)) => {
+                        let mut vars = vars.clone();
+                        vars.insert(param.clone(), ValueType::Any);
+                        infer(body, &vars)?;
+                    }
+                    (ParamType::Lambda, other) => return Err(EvalError::ExpectedLambda.at(other.span()).into()),
+                    (ParamType::Value(_), SimplExpr::Lambda(span, ..)) => {
+                        return Err(EvalError::UnexpectedLambda.at(*span).into())
+                    }
+                    (ParamType::Value(ty), other) => {
+                        expect(other, vars, ty)?;
+                    }
+                }
+            }
+            Inferred::of_type(signature.returns)
+        }
+        SimplExpr::Lambda(span, ..) => return Err(EvalError::UnexpectedLambda.at(*span).into()),
+        SimplExpr::JsonArray(_, values) => {
+            for value in values {
+                infer(value, vars)?;
+            }
+            Inferred::of_type(ValueType::Array)
+        }
+        SimplExpr::JsonObject(_, entries) => {
+            for (key, value) in entries {
+                infer(key, vars)?;
+                infer(value, vars)?;
+            }
+            Inferred::of_type(ValueType::Object)
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(expr: &str, expected: ValueType) -> Result<(), TypeError> {
+        let vars = vec![("count", ValueType::Number), ("visible", ValueType::Bool), ("EWW_CPU", ValueType::Object)]
+            .into_iter()
+            .map(|(name, ty)| (VarName::from(name), ty))
+            .collect();
+        check_type(&crate::parse_string(0, 0, expr).unwrap(), &vars, expected)
+    }
+
+    #[test]
+    fn test_infer_type() {
+        let vars = HashMap::new();
+        let infer = |expr: &str| infer_type(&crate::parse_string(0, 0, expr).unwrap(), &vars).unwrap();
+        assert_eq!(infer("1 + 2"), ValueType::Number);
+        assert_eq!(infer("1 < 2 && true"), ValueType::Bool);
+        assert_eq!(infer("[1, 2]"), ValueType::Array);
+        assert_eq!(infer("join(['a'], ', ')"), ValueType::String);
+        assert_eq!(infer("foo ?: 'bar'"), ValueType::Any);
+    }
+
+    #[test]
+    fn test_check_type() {
+        assert!(check("'2' * 2", ValueType::Number).is_ok());
+        assert!(check("unknown * 2", ValueType::Number).is_ok());
+        assert!(check("count > 2 ? 'a' : 'b'", ValueType::String).is_ok());
+        assert!(check("EWW_CPU.cores[0].usage * 2", ValueType::Number).is_ok());
+        assert!(check("sum(map(EWW_CPU.cores, c => c.usage))", ValueType::Number).is_ok());
+        assert!(check("meta(count).stale", ValueType::Bool).is_ok());
+
+        assert!(matches!(check("'abc' * 2", ValueType::Number), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("visible", ValueType::Number), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("count && visible", ValueType::Bool), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("count ? 1 : 2", ValueType::Number), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("count.foo", ValueType::Any), Err(TypeError::Eval(_))));
+        assert!(matches!(check("nope(1)", ValueType::Any), Err(TypeError::Eval(_))));
+        assert!(matches!(check("map(EWW_CPU.cores, 1)", ValueType::Any), Err(TypeError::Eval(_))));
+        assert!(matches!(check("'a' =~ '('", ValueType::Bool), Err(TypeError::Eval(_))));
+        assert!(matches!(check("meta('count')", ValueType::Any), Err(TypeError::Eval(_))));
+    }
+}
@@ -43,7 +43,7 @@ pub struct UnusedAttrs {
     attrs: Vec<(Span, AttrName)>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
 pub struct AttrEntry {
     pub key_span: Span,
     pub value: Ast,
@@ -56,7 +56,7 @@ impl AttrEntry {
 }
 
 // TODO maybe make this generic over the contained content
-#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
 pub struct Attributes {
     pub span: Span,
     pub attrs: HashMap<AttrName, AttrEntry>,
@@ -8,14 +8,16 @@ use simplexpr::SimplExpr;
 
 use super::{
     file_provider::{FilesError, YuckFiles},
+    magic_var_definition::MagicVarDefinition,
     script_var_definition::ScriptVarDefinition,
-    var_definition::VarDefinition,
+    settings::Settings,
+    var_definition::{ExprDefinition, VarDefinition},
     widget_definition::WidgetDefinition,
     widget_use::WidgetUse,
     window_definition::WindowDefinition,
 };
 use crate::{
-    config::script_var_definition::{ListenScriptVar, PollScriptVar},
+    config::script_var_definition::{FileScriptVar, ListenScriptVar, PollScriptVar, SocketScriptVar},
     error::{AstError, AstResult, OptionAstErrorExt},
     parser::{
         ast::Ast,
@@ -29,9 +31,14 @@ pub static TOP_LEVEL_DEFINITION_NAMES: &[&str] = &[
     WidgetDefinition::ELEMENT_NAME,
     WindowDefinition::ELEMENT_NAME,
     VarDefinition::ELEMENT_NAME,
+    ExprDefinition::ELEMENT_NAME,
     ListenScriptVar::ELEMENT_NAME,
     PollScriptVar::ELEMENT_NAME,
+    FileScriptVar::ELEMENT_NAME,
+    SocketScriptVar::ELEMENT_NAME,
+    MagicVarDefinition::ELEMENT_NAME,
     Include::ELEMENT_NAME,
+    Settings::ELEMENT_NAME,
 ];
 
 #[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
@@ -53,9 +60,12 @@ impl FromAstElementContent for Include {
 pub enum TopLevel {
     Include(Include),
     VarDefinition(VarDefinition),
-    ScriptVarDefinition(ScriptVarDefinition),
+    ExprDefinition(ExprDefinition),
+    ScriptVarDefinition(Box<ScriptVarDefinition>),
+    MagicVarDefinition(MagicVarDefinition),
     WidgetDefinition(WidgetDefinition),
     WindowDefinition(WindowDefinition),
+    Settings(Settings),
 }
 
 impl FromAst for TopLevel {
@@ -67,24 +77,37 @@ impl FromAst for TopLevel {
             x if x == Include::ELEMENT_NAME => Self::Include(Include::from_tail(span, iter)?),
             x if x == WidgetDefinition::ELEMENT_NAME => Self::WidgetDefinition(WidgetDefinition::from_tail(span, iter)?),
             x if x == VarDefinition::ELEMENT_NAME => Self::VarDefinition(VarDefinition::from_tail(span, iter)?),
+            x if x == ExprDefinition::ELEMENT_NAME => Self::ExprDefinition(ExprDefinition::from_tail(span, iter)?),
             x if x == PollScriptVar::ELEMENT_NAME => {
-                Self::ScriptVarDefinition(ScriptVarDefinition::Poll(PollScriptVar::from_tail(span, iter)?))
+                Self::ScriptVarDefinition(Box::new(ScriptVarDefinition::Poll(PollScriptVar::from_tail(span, iter)?)))
             }
             x if x == ListenScriptVar::ELEMENT_NAME => {
-                Self::ScriptVarDefinition(ScriptVarDefinition::Listen(ListenScriptVar::from_tail(span, iter)?))
+                Self::ScriptVarDefinition(Box::new(ScriptVarDefinition::Listen(ListenScriptVar::from_tail(span, iter)?)))
             }
+            x if x == FileScriptVar::ELEMENT_NAME => {
+                Self::ScriptVarDefinition(Box::new(ScriptVarDefinition::File(FileScriptVar::from_tail(span, iter)?)))
+            }
+            x if x == SocketScriptVar::ELEMENT_NAME => {
+                Self::ScriptVarDefinition(Box::new(ScriptVarDefinition::Socket(SocketScriptVar::from_tail(span, iter)?)))
+            }
+            x if x == MagicVarDefinition::ELEMENT_NAME => Self::MagicVarDefinition(MagicVarDefinition::from_tail(span, iter)?),
             x if x == WindowDefinition::ELEMENT_NAME => Self::WindowDefinition(WindowDefinition::from_tail(span, iter)?),
+            x if x == Settings::ELEMENT_NAME => Self::Settings(Settings::from_tail(span, iter)?),
             x => return Err(AstError::UnknownToplevel(sym_span, x.to_string())),
         })
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
 pub struct Config {
     pub widget_definitions: HashMap<String, WidgetDefinition>,
     pub window_definitions: HashMap<String, WindowDefinition>,
     pub var_definitions: HashMap<VarName, VarDefinition>,
+    pub expr_definitions: HashMap<VarName, ExprDefinition>,
     pub script_vars: HashMap<VarName, ScriptVarDefinition>,
+    /// Overrides for the builtin magic variables
+    pub magic_vars: HashMap<VarName, MagicVarDefinition>,
+    pub settings: Settings,
 }
 
 impl Config {
@@ -93,8 +116,14 @@ impl Config {
             TopLevel::VarDefinition(x) => {
                 self.var_definitions.insert(x.name.clone(), x);
             }
+            TopLevel::ExprDefinition(x) => {
+                self.expr_definitions.insert(x.name.clone(), x);
+            }
             TopLevel::ScriptVarDefinition(x) => {
-                self.script_vars.insert(x.name().clone(), x);
+                self.script_vars.insert(x.name().clone(), *x);
+            }
+            TopLevel::MagicVarDefinition(x) => {
+                self.magic_vars.insert(x.name.clone(), x);
             }
             TopLevel::WidgetDefinition(x) => {
                 self.widget_definitions.insert(x.name.clone(), x);
@@ -102,6 +131,7 @@ impl Config {
             TopLevel::WindowDefinition(x) => {
                 self.window_definitions.insert(x.name.clone(), x);
             }
+            TopLevel::Settings(x) => self.settings.merge(x),
             TopLevel::Include(include) => {
                 let (file_id, toplevels) = files.load_file(PathBuf::from(&include.path)).map_err(|err| match err {
                     FilesError::IoError(_) => AstError::IncludedFileNotFound(include),
@@ -120,7 +150,10 @@ impl Config {
             widget_definitions: HashMap::new(),
             window_definitions: HashMap::new(),
             var_definitions: HashMap::new(),
+            expr_definitions: HashMap::new(),
             script_vars: HashMap::new(),
+            magic_vars: HashMap::new(),
+            settings: Settings::default(),
         };
         for element in elements {
             config.append_toplevel(files, TopLevel::from_ast(element)?)?;
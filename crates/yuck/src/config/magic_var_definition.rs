@@ -0,0 +1,92 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use simplexpr::dynval::{DynVal, Value};
+
+use crate::{
+    error::{AstResult, AstResultExt},
+    parser::{
+        ast::Ast,
+        ast_iterator::AstIterator,
+        from_ast::{FromAst, FromAstElementContent},
+    },
+};
+use eww_shared_util::{Span, VarName};
+
+/// Overrides for one of the builtin magic variables, i.e. `(defmagic EWW_NET :interval "500ms" :include ["wlan0"])`.
+/// Whether the name refers to an existing magic variable is only checked by eww, as yuck doesn't know about them.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct MagicVarDefinition {
+    pub name: VarName,
+    pub interval: Option<Duration>,
+    /// The entries that are kept, if `:include` or `:exclude` were given
+    pub filter: Option<KeyFilter>,
+    pub options: MagicVarOptions,
+    pub span: Span,
+    pub name_span: Span,
+}
+
+impl FromAstElementContent for MagicVarDefinition {
+    const ELEMENT_NAME: &'static str = "defmagic";
+
+    fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
+        let result: AstResult<_> = try {
+            let (name_span, name) = iter.expect_symbol()?;
+            let mut attrs = iter.expect_key_values()?;
+            let interval = attrs.primitive_optional::<DynVal, _>("interval")?.map(|x| x.as_duration()).transpose()?;
+            let include = attrs.ast_optional::<Ast>("include")?.map(names_from_ast).transpose()?;
+            let exclude = attrs.ast_optional::<Ast>("exclude")?.map(names_from_ast).transpose()?;
+            let count = attrs.primitive_optional::<usize, _>("count")?;
+            iter.expect_done()?;
+            let filter = match (include, exclude) {
+                (None, None) => None,
+                (include, exclude) => Some(KeyFilter { include, exclude: exclude.unwrap_or_default() }),
+            };
+            Self { name: VarName(name), interval, filter, options: MagicVarOptions { count }, span, name_span }
+        };
+        result.note(r#"Expected format: `(defmagic EWW_NET :interval "500ms" :include ["wlan0"])`"#)
+    }
+}
+
+/// The options that are passed to the function computing the value of a magic variable.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, serde::Serialize)]
+pub struct MagicVarOptions {
+    /// How many entries to report, for the magic variables that list the top entries of something, i.e. `EWW_PROCESSES`
+    pub count: Option<usize>,
+}
+
+/// Read a list of names, given either as a yuck array like `["a" "b"]` or as an expression that evaluates to an array.
+fn names_from_ast(ast: Ast) -> AstResult<Vec<String>> {
+    match ast {
+        Ast::Array(_, elements) => elements.into_iter().map(String::from_ast).collect(),
+        other => {
+            let expr = other.as_simplexpr()?;
+            Ok(expr.eval_no_vars().map_err(simplexpr::error::Error::Eval)?.as_vec()?)
+        }
+    }
+}
+
+/// Selects which entries are kept of a magic variable that holds an object keyed by device, mount point or interface.
+#[derive(Debug, Default, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct KeyFilter {
+    /// If given, only these keys are kept
+    pub include: Option<Vec<String>>,
+    pub exclude: Vec<String>,
+}
+
+impl KeyFilter {
+    pub fn keeps(&self, key: &str) -> bool {
+        self.include.as_ref().map_or(true, |include| include.iter().any(|x| x == key)) && !self.exclude.iter().any(|x| x == key)
+    }
+
+    /// Remove the entries of an object that are not kept. Values that aren't objects are returned unchanged.
+    pub fn apply(&self, value: DynVal) -> DynVal {
+        match value.as_object() {
+            Ok(entries) => {
+                let entries: BTreeMap<String, Value> =
+                    entries.iter().filter(|(key, _)| self.keeps(key)).map(|(k, v)| (k.clone(), v.clone())).collect();
+                DynVal::from(Value::Object(Arc::new(entries))).at(value.1)
+            }
+            Err(_) => value,
+        }
+    }
+}
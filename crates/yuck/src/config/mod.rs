@@ -1,8 +1,11 @@
 pub mod attributes;
 pub mod backend_window_options;
+#[allow(clippy::module_inception)]
 pub mod config;
 pub mod file_provider;
+pub mod magic_var_definition;
 pub mod script_var_definition;
+pub mod settings;
 #[cfg(test)]
 mod test;
 pub mod validate;
@@ -1,8 +1,18 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    time::Duration,
+};
 
-use simplexpr::{dynval::DynVal, SimplExpr};
+use simplexpr::{dynval::DynVal, eval::EvalError, SimplExpr};
 
 use crate::{
+    config::{
+        attributes::{AttrError, Attributes},
+        magic_var_definition::MagicVarOptions,
+        window_definition::EnumParseError,
+    },
+    enum_parse,
     error::{AstError, AstResult, AstResultExt},
     parser::{
         ast::Ast,
@@ -12,10 +22,12 @@ use crate::{
 };
 use eww_shared_util::{AttrName, Span, Spanned, VarName};
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub enum ScriptVarDefinition {
     Poll(PollScriptVar),
     Listen(ListenScriptVar),
+    File(FileScriptVar),
+    Socket(SocketScriptVar),
 }
 
 impl ScriptVarDefinition {
@@ -23,6 +35,8 @@ impl ScriptVarDefinition {
         match self {
             ScriptVarDefinition::Poll(x) => x.name_span,
             ScriptVarDefinition::Listen(x) => x.name_span,
+            ScriptVarDefinition::File(x) => x.name_span,
+            ScriptVarDefinition::Socket(x) => x.name_span,
         }
     }
 
@@ -30,29 +44,99 @@ impl ScriptVarDefinition {
         match self {
             ScriptVarDefinition::Poll(x) => &x.name,
             ScriptVarDefinition::Listen(x) => &x.name,
+            ScriptVarDefinition::File(x) => &x.name,
+            ScriptVarDefinition::Socket(x) => &x.name,
+        }
+    }
+
+    /// The condition under which the variable is updated, for the kinds of variables that support `:run-while`.
+    pub fn run_while_expr(&self) -> Option<&SimplExpr> {
+        match self {
+            ScriptVarDefinition::Poll(x) => Some(&x.run_while_expr),
+            ScriptVarDefinition::Listen(x) => Some(&x.run_while_expr),
+            ScriptVarDefinition::File(_) | ScriptVarDefinition::Socket(_) => None,
+        }
+    }
+
+    pub fn run_while_var_refs(&self) -> &[VarName] {
+        match self {
+            ScriptVarDefinition::Poll(x) => &x.run_while_var_refs,
+            ScriptVarDefinition::Listen(x) => &x.run_while_var_refs,
+            ScriptVarDefinition::File(_) | ScriptVarDefinition::Socket(_) => &[],
         }
     }
 
     pub fn command_span(&self) -> Option<Span> {
         match self {
-            ScriptVarDefinition::Poll(x) => match x.command {
-                VarSource::Shell(span, ..) => Some(span),
+            ScriptVarDefinition::Poll(x) => match &x.command {
+                VarSource::Shell(span, ..) => Some(*span),
+                VarSource::Native(span, _) => Some(*span),
                 VarSource::Function(_) => None,
             },
             ScriptVarDefinition::Listen(x) => Some(x.command_span),
+            ScriptVarDefinition::File(x) => Some(x.path_span),
+            ScriptVarDefinition::Socket(x) => Some(x.path_span),
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub enum VarSource {
     // TODO allow for other executors? (python, etc)
     Shell(Span, String),
+    /// Computes the value of a magic variable, given the options configured for it with `defmagic`
     #[serde(skip)]
-    Function(fn() -> Result<DynVal, Box<dyn std::error::Error + Sync + Send + 'static>>),
+    Function(fn(MagicVarOptions) -> Result<DynVal, Box<dyn std::error::Error + Sync + Send + 'static>>),
+    /// A source implemented by eww itself, selected with `:source`
+    Native(Span, NativeVarSource),
 }
 
+/// A source of values that eww reads directly, without running a shell script, i.e. `(defpoll x :source "file" :path "...")`.
 #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum NativeVarSource {
+    /// The last line appended to a file since it was last read
+    File { path: String },
+    /// Connects to a unix socket, writes the request followed by a newline if there is one, and reads the response until
+    /// the other side closes the connection
+    UnixSocket { path: String, request: Option<String> },
+    /// The current unix timestamp in seconds
+    Time,
+}
+
+impl NativeVarSource {
+    /// Read the source selected with `:source` from the remaining attributes of the `defpoll`.
+    /// Any attributes the source doesn't know are reported, as they would otherwise be silently ignored.
+    fn from_attrs(kind_span: Span, kind: &str, attrs: &mut Attributes) -> AstResult<Self> {
+        let source = match kind {
+            "file" => NativeVarSource::File { path: attrs.primitive_required("path")? },
+            "unix-socket" => NativeVarSource::UnixSocket {
+                path: attrs.primitive_required("path")?,
+                request: attrs.primitive_optional("request")?,
+            },
+            "time" => NativeVarSource::Time,
+            _ => {
+                let msg = format!("Unknown source `{}`. Possible values are: \"file\", \"unix-socket\", \"time\"", kind);
+                return Err(AttrError::Other(kind_span, msg.into()).into());
+            }
+        };
+        if let Some((name, entry)) = attrs.attrs.iter().next() {
+            let msg = format!("The `{}` source has no attribute `:{}`", kind, name);
+            return Err(AttrError::Other(entry.key_span.to(entry.value.span()), msg.into()).into());
+        }
+        Ok(source)
+    }
+
+    /// The name the source is selected with
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NativeVarSource::File { .. } => "file",
+            NativeVarSource::UnixSocket { .. } => "unix-socket",
+            NativeVarSource::Time => "time",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub struct PollScriptVar {
     pub name: VarName,
     pub run_while_expr: SimplExpr,
@@ -60,6 +144,9 @@ pub struct PollScriptVar {
     pub command: VarSource,
     pub initial_value: Option<DynVal>,
     pub interval: std::time::Duration,
+    /// How long the script may run before it is killed. Falls back to the `:poll-timeout` of `defsettings`.
+    pub timeout: Option<std::time::Duration>,
+    pub script_env: ScriptEnvironment,
     pub name_span: Span,
 }
 
@@ -72,32 +159,55 @@ impl FromAstElementContent for PollScriptVar {
             let mut attrs = iter.expect_key_values()?;
             let initial_value = Some(attrs.primitive_optional("initial")?.unwrap_or_else(|| DynVal::from_string(String::new())));
             let interval = attrs.primitive_required::<DynVal, _>("interval")?.as_duration()?;
-            let (script_span, script) = iter.expect_literal()?;
+            let timeout = attrs.primitive_optional::<DynVal, _>("timeout")?.map(|x| x.as_duration()).transpose()?;
+            let script_env = ScriptEnvironment::from_attrs(&mut attrs)?;
 
             let run_while_expr =
                 attrs.ast_optional::<SimplExpr>("run-while")?.unwrap_or_else(|| SimplExpr::Literal(DynVal::from(true)));
             let run_while_var_refs = run_while_expr.collect_var_refs();
 
+            let command = match attrs.ast_optional::<SimplExpr>("source")? {
+                Some(kind) => {
+                    // All the remaining attributes configure the source
+                    let span = kind.span();
+                    let kind = kind.eval_no_vars().map_err(|err| AttrError::EvaluationError(span, err))?.as_string()?;
+                    VarSource::Native(span, NativeVarSource::from_attrs(span, &kind, &mut attrs)?)
+                }
+                None => {
+                    let (script_span, script) = iter.expect_literal()?;
+                    VarSource::Shell(script_span, script.to_string())
+                }
+            };
+
             iter.expect_done()?;
             Self {
                 name_span,
                 name: VarName(name),
                 run_while_expr,
                 run_while_var_refs,
-                command: VarSource::Shell(script_span, script.to_string()),
+                command,
                 initial_value,
                 interval,
+                timeout,
+                script_env,
             }
         };
-        result.note(r#"Expected format: `(defpoll name :interval "10s" "echo 'a shell script'")`"#)
+        result.note(r#"Expected format: `(defpoll name :interval "10s" "echo 'a shell script'")` or `(defpoll name :interval "10s" :source "file" :path "/some/file")`"#)
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub struct ListenScriptVar {
     pub name: VarName,
     pub command: String,
     pub initial_value: DynVal,
+    pub framing: ListenFraming,
+    pub transform: Option<ListenTransform>,
+    pub restart: RestartPolicy,
+    /// The script is only running while this is true, and killed whenever it becomes false
+    pub run_while_expr: SimplExpr,
+    pub run_while_var_refs: Vec<VarName>,
+    pub script_env: ScriptEnvironment,
     pub command_span: Span,
     pub name_span: Span,
 }
@@ -109,10 +219,276 @@ impl FromAstElementContent for ListenScriptVar {
             let (name_span, name) = iter.expect_symbol()?;
             let mut attrs = iter.expect_key_values()?;
             let initial_value = attrs.primitive_optional("initial")?.unwrap_or_else(|| DynVal::from_string(String::new()));
+            let framing = ListenFraming::from_attrs(&mut attrs)?;
+            let transform = attrs.ast_optional("transform")?;
+            let restart = RestartPolicy {
+                mode: attrs.primitive_optional("restart")?.unwrap_or(RestartMode::Never),
+                max_restarts: attrs.primitive_optional("max-restarts")?,
+                delay: attrs
+                    .primitive_optional::<DynVal, _>("restart-delay")?
+                    .map(|x| x.as_duration())
+                    .transpose()?
+                    .unwrap_or_else(|| Duration::from_secs(1)),
+                max_delay: attrs
+                    .primitive_optional::<DynVal, _>("max-restart-delay")?
+                    .map(|x| x.as_duration())
+                    .transpose()?
+                    .unwrap_or_else(|| Duration::from_secs(60)),
+            };
+            let run_while_expr =
+                attrs.ast_optional::<SimplExpr>("run-while")?.unwrap_or_else(|| SimplExpr::Literal(DynVal::from(true)));
+            let run_while_var_refs = run_while_expr.collect_var_refs();
+            let script_env = ScriptEnvironment::from_attrs(&mut attrs)?;
             let (command_span, script) = iter.expect_literal()?;
             iter.expect_done()?;
-            Self { name_span, name: VarName(name), command: script.to_string(), initial_value, command_span }
+            Self {
+                name_span,
+                name: VarName(name),
+                command: script.to_string(),
+                initial_value,
+                framing,
+                transform,
+                restart,
+                run_while_expr,
+                run_while_var_refs,
+                script_env,
+                command_span,
+            }
         };
         result.note(r#"Expected format: `(deflisten name :initial "0" "tail -f /tmp/example")`"#)
     }
 }
+
+/// A variable that holds the content of a file, updated whenever the file changes.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct FileScriptVar {
+    pub name: VarName,
+    pub path: String,
+    pub initial_value: DynVal,
+    /// Remove all whitespace around the content, rather than just a trailing newline
+    pub trim: bool,
+    /// Parse the content as json
+    pub json: bool,
+    pub path_span: Span,
+    pub name_span: Span,
+}
+
+impl FromAstElementContent for FileScriptVar {
+    const ELEMENT_NAME: &'static str = "deffile";
+
+    fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
+        let result: AstResult<_> = try {
+            let (name_span, name) = iter.expect_symbol()?;
+            let mut attrs = iter.expect_key_values()?;
+            let initial_value = attrs.primitive_optional("initial")?.unwrap_or_else(|| DynVal::from_string(String::new()));
+            let trim = attrs.primitive_optional("trim")?.unwrap_or(false);
+            let json = attrs.primitive_optional("json")?.unwrap_or(false);
+            let (path_span, path) = iter.expect_literal()?;
+            iter.expect_done()?;
+            Self { name_span, name: VarName(name), path: path.to_string(), initial_value, trim, json, path_span }
+        };
+        result.note(r#"Expected format: `(deffile name :trim true "/path/to/file")`"#)
+    }
+}
+
+/// A variable that subscribes to a unix socket, turning every message it receives into a new value.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct SocketScriptVar {
+    pub name: VarName,
+    pub path: String,
+    /// Written to the socket after every connect followed by a newline, i.e. to subscribe to events
+    pub request: Option<String>,
+    pub initial_value: DynVal,
+    pub framing: ListenFraming,
+    pub transform: Option<ListenTransform>,
+    /// The time to wait before reconnecting after the connection was lost. This doubles with every failed attempt,
+    /// up to `max_reconnect_delay`.
+    pub reconnect_delay: Duration,
+    pub max_reconnect_delay: Duration,
+    pub path_span: Span,
+    pub name_span: Span,
+}
+
+impl FromAstElementContent for SocketScriptVar {
+    const ELEMENT_NAME: &'static str = "defsocket";
+
+    fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
+        let result: AstResult<_> = try {
+            let (name_span, name) = iter.expect_symbol()?;
+            let mut attrs = iter.expect_key_values()?;
+            let initial_value = attrs.primitive_optional("initial")?.unwrap_or_else(|| DynVal::from_string(String::new()));
+            let request = attrs.primitive_optional("request")?;
+            let framing = ListenFraming::from_attrs(&mut attrs)?;
+            let transform = attrs.ast_optional("transform")?;
+            let reconnect_delay = attrs
+                .primitive_optional::<DynVal, _>("reconnect-delay")?
+                .map(|x| x.as_duration())
+                .transpose()?
+                .unwrap_or_else(|| Duration::from_secs(1));
+            let max_reconnect_delay = attrs
+                .primitive_optional::<DynVal, _>("max-reconnect-delay")?
+                .map(|x| x.as_duration())
+                .transpose()?
+                .unwrap_or_else(|| Duration::from_secs(60));
+            let (path_span, path) = iter.expect_literal()?;
+            iter.expect_done()?;
+            Self {
+                name_span,
+                name: VarName(name),
+                path: path.to_string(),
+                request,
+                initial_value,
+                framing,
+                transform,
+                reconnect_delay,
+                max_reconnect_delay,
+                path_span,
+            }
+        };
+        result.note(r#"Expected format: `(defsocket name :request "subscribe" "/path/to/socket")`"#)
+    }
+}
+
+/// How a script is run, as configured with `:shell`, `:env` and `:cwd`.
+/// Anything that isn't given falls back to the defaults set in `defsettings`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ScriptEnvironment {
+    /// The program the script is passed to with `-c`. Defaults to `/bin/sh`.
+    pub shell: Option<String>,
+    /// Environment variables set in addition to the ones inherited from eww
+    pub env: BTreeMap<String, String>,
+    /// The working directory of the script. Relative paths are relative to the config directory.
+    pub cwd: Option<String>,
+}
+
+impl ScriptEnvironment {
+    pub fn from_attrs(attrs: &mut Attributes) -> AstResult<Self> {
+        let shell = attrs.primitive_optional("shell")?;
+        let cwd = attrs.primitive_optional("cwd")?;
+        let env = match attrs.ast_optional::<SimplExpr>("env")? {
+            Some(env_expr) => {
+                let env = env_expr.eval_no_vars().map_err(|err| AttrError::EvaluationError(env_expr.span(), err))?;
+                env.as_object()?.iter().map(|(name, value)| (name.clone(), value.to_string())).collect()
+            }
+            None => BTreeMap::new(),
+        };
+        Ok(ScriptEnvironment { shell, env, cwd })
+    }
+
+    /// Fill in everything that wasn't set here from the given defaults.
+    /// Environment variables are merged, preferring the ones set here.
+    pub fn with_defaults(mut self, defaults: &ScriptEnvironment) -> Self {
+        self.shell = self.shell.or_else(|| defaults.shell.clone());
+        self.cwd = self.cwd.or_else(|| defaults.cwd.clone());
+        for (name, value) in &defaults.env {
+            self.env.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+        self
+    }
+}
+
+/// How the output of a `deflisten` script is split up into separate values.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum ListenFraming {
+    /// Every line is a new value
+    Line,
+    /// Values are separated by NUL bytes, and may thus span multiple lines
+    Nul,
+    /// The output is a stream of json values, each of which may span any amount of lines
+    Json,
+    /// Every match of the regex is a new value. If the regex contains a capture group, only the first group is used.
+    Regex(String),
+}
+
+impl ListenFraming {
+    /// Read the framing from the `:framing` or `:regex` attribute, defaulting to one value per line.
+    fn from_attrs(attrs: &mut Attributes) -> AstResult<Self> {
+        let framing = attrs.primitive_optional("framing")?;
+        Ok(match attrs.ast_optional::<SimplExpr>("regex")? {
+            Some(regex_expr) if framing.is_some() => {
+                return Err(AttrError::Other(regex_expr.span(), "`:regex` can't be combined with `:framing`".into()).into())
+            }
+            Some(regex_expr) => {
+                let regex = regex_expr.eval_no_vars().map_err(|err| AttrError::EvaluationError(regex_expr.span(), err))?;
+                regex::Regex::new(&regex.as_string()?).map_err(|err| AttrError::Other(regex_expr.span(), Box::new(err)))?;
+                ListenFraming::Regex(regex.as_string()?)
+            }
+            None => framing.unwrap_or(ListenFraming::Line),
+        })
+    }
+}
+
+impl FromStr for ListenFraming {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "framing", s,
+            "line" => ListenFraming::Line,
+            "nul" => ListenFraming::Nul,
+            "json" => ListenFraming::Json,
+        }
+    }
+}
+
+/// Whether the script of a `deflisten` is started again after it exited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum RestartMode {
+    Never,
+    /// Only restart the script if it exited with a non-zero exit code, or was killed
+    OnFailure,
+    Always,
+}
+
+impl FromStr for RestartMode {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "restart mode", s,
+            "never" => RestartMode::Never,
+            "on-failure" => RestartMode::OnFailure,
+            "always" => RestartMode::Always,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct RestartPolicy {
+    pub mode: RestartMode,
+    /// How often the script may be restarted in a row. Unlimited if not given.
+    pub max_restarts: Option<u32>,
+    /// The time to wait before the first restart. This doubles with every restart in a row, up to `max_delay`.
+    /// Once the script keeps running for longer than `max_delay`, the delay and the count of restarts are reset.
+    pub delay: Duration,
+    pub max_delay: Duration,
+}
+
+/// A lambda that is applied to every value read by a `deflisten`, i.e. `{song => song.metadata.title}`.
+/// As this runs outside of any widget, the lambda can only reference its own parameter.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct ListenTransform {
+    pub span: Span,
+    pub param: VarName,
+    pub body: Box<SimplExpr>,
+}
+
+impl ListenTransform {
+    pub fn apply(&self, value: DynVal) -> Result<DynVal, EvalError> {
+        self.body.eval(&maplit::hashmap! { self.param.clone() => value })
+    }
+}
+
+impl FromAst for ListenTransform {
+    fn from_ast(e: Ast) -> AstResult<Self> {
+        match SimplExpr::from_ast(e)? {
+            SimplExpr::Lambda(span, param, body) => {
+                if let Some(var_name) = body.collect_var_refs().into_iter().find(|var_name| var_name != &param) {
+                    return Err(
+                        simplexpr::error::Error::Eval(EvalError::UnknownVariable(var_name, Vec::new()).at(body.span())).into()
+                    );
+                }
+                Ok(ListenTransform { span, param, body })
+            }
+            other => Err(simplexpr::error::Error::Eval(EvalError::ExpectedLambda.at(other.span())).into()),
+        }
+    }
+}
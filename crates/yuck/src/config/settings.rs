@@ -0,0 +1,73 @@
+use std::{str::FromStr, time::Duration};
+
+use simplexpr::dynval::DynVal;
+
+use crate::{
+    config::{script_var_definition::ScriptEnvironment, window_definition::EnumParseError},
+    enum_parse,
+    error::{AstResult, AstResultExt},
+    parser::{ast::Ast, ast_iterator::AstIterator, from_ast::FromAstElementContent},
+};
+use eww_shared_util::Span;
+
+/// Global defaults for the variables defined in the config, set with `(defsettings ...)`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct Settings {
+    /// How long the script of a `defpoll` may run before it is killed, unless the variable sets its own `:timeout`.
+    pub poll_timeout: Option<Duration>,
+    /// The shell, environment and working directory used for all scripts that don't set their own.
+    pub script_env: ScriptEnvironment,
+    /// How the commands run by widgets receive values like the clicked button. Defaults to [`PlaceholderMode::Replace`].
+    pub command_placeholders: Option<PlaceholderMode>,
+}
+
+impl Settings {
+    /// Apply the settings given in another `defsettings`, overriding the ones that were set in both.
+    pub fn merge(&mut self, other: Settings) {
+        self.poll_timeout = other.poll_timeout.or(self.poll_timeout);
+        self.script_env = other.script_env.with_defaults(&self.script_env);
+        self.command_placeholders = other.command_placeholders.or(self.command_placeholders);
+    }
+}
+
+impl FromAstElementContent for Settings {
+    const ELEMENT_NAME: &'static str = "defsettings";
+
+    fn from_tail<I: Iterator<Item = Ast>>(_span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
+        let result: AstResult<_> = try {
+            let mut attrs = iter.expect_key_values()?;
+            let poll_timeout = attrs.primitive_optional::<DynVal, _>("poll-timeout")?.map(|x| x.as_duration()).transpose()?;
+            let script_env = ScriptEnvironment::from_attrs(&mut attrs)?;
+            let command_placeholders = attrs.primitive_optional("command-placeholders")?;
+            iter.expect_done()?;
+            Settings { poll_timeout, script_env, command_placeholders }
+        };
+        result.note(r#"Expected format: `(defsettings :poll-timeout "10s" :shell "/bin/bash")`"#)
+    }
+}
+
+/// How placeholders like `{value}` or `{button}` are passed to the commands run by widgets, such as `:onclick`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum PlaceholderMode {
+    /// The placeholders in the command are replaced by the shell-quoted values
+    Replace,
+    /// The command is left as is, and the values are passed as environment variables like `EWW_VALUE` instead
+    Env,
+}
+
+impl Default for PlaceholderMode {
+    fn default() -> Self {
+        PlaceholderMode::Replace
+    }
+}
+
+impl FromStr for PlaceholderMode {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "placeholder mode", s,
+            "replace" => PlaceholderMode::Replace,
+            "env" => PlaceholderMode::Env,
+        }
+    }
+}
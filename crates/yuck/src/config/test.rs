@@ -1,5 +1,5 @@
 use crate::{
-    config::config::Config,
+    config::{config::Config, widget_use::WidgetUse},
     parser::{self, ast::Ast, from_ast::FromAst, lexer::Lexer},
 };
 
@@ -28,3 +28,334 @@ fn test_config() {
         insta::assert_ron_snapshot!(config.unwrap());
     });
 }
+
+#[test]
+fn test_let_bindings() {
+    let input = r#"
+        (defwidget bar [arg]
+            (let [a "${arg}!"
+                  b {a + "?"}]
+                (foo :text b)))
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    // every binding gets its own let, nested inside the one of the binding before it
+    let outer_let = &config.widget_definitions.get("bar").unwrap().widget;
+    assert_eq!(outer_let.name, "let");
+    assert_eq!(outer_let.children.len(), 1);
+    let inner_let = &outer_let.children[0];
+    assert_eq!(inner_let.name, "let");
+    assert_eq!(inner_let.children[0].name, "foo");
+    let binding =
+        |widget: &WidgetUse, name: &str| widget.attrs.attrs.get(name).unwrap().value.as_simplexpr().unwrap().to_string();
+    assert_eq!(binding(outer_let, "a"), r#""${arg}!""#);
+    assert_eq!(binding(inner_let, "b"), r#"(a + "?")"#);
+
+    // a lambda parameter with the same name as a variable used by an earlier binding doesn't capture it
+    let input = r#"
+        (defwidget baz [arg items]
+            (let [a arg
+                  b {map(items, arg => a)}]
+                (foo :text b)))
+    "#;
+    let (_, asts) = files.load_str("config3.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    let outer_let = &config.widget_definitions.get("baz").unwrap().widget;
+    assert_eq!(binding(&outer_let.children[0], "b"), "map(items, arg => a)");
+
+    let input = r#"(defwidget bar [] (let [a] (foo)))"#;
+    let (_, asts) = files.load_str("config2.yuck".to_string(), input.to_string()).unwrap();
+    assert!(Config::generate(&mut files, asts).is_err());
+}
+
+#[test]
+fn test_expr_definitions() {
+    use super::validate::{expr_definitions_in_dependency_order, ValidationError};
+
+    let input = r#"
+        (defvar count 1)
+        (defexpr quadrupled {doubled * 2})
+        (defexpr doubled {count * 2})
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    let order = expr_definitions_in_dependency_order(&config.expr_definitions).unwrap();
+    assert_eq!(order.iter().map(|def| def.name.0.as_str()).collect::<Vec<_>>(), vec!["doubled", "quadrupled"]);
+
+    let input = r#"
+        (defexpr a {b + 1})
+        (defexpr b {a + 1})
+    "#;
+    let (_, asts) = files.load_str("config2.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    assert!(matches!(
+        expr_definitions_in_dependency_order(&config.expr_definitions),
+        Err(ValidationError::RecursiveExprDefinition { .. })
+    ));
+}
+
+#[test]
+fn test_listen_framing() {
+    use super::script_var_definition::{ListenFraming, RestartMode, RestartPolicy, ScriptVarDefinition};
+    use simplexpr::dynval::DynVal;
+    use std::time::Duration;
+
+    let input = r#"
+        (deflisten lines "tail -f stuff")
+        (deflisten records :framing "nul" "find -print0")
+        (deflisten songs :framing "json" :transform {song => song.title} "player --follow")
+        (deflisten numbers :regex "value=(\\d+)" "watch-values")
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    let listen_var = |name: &str| match config.script_vars.get(name) {
+        Some(ScriptVarDefinition::Listen(var)) => var.clone(),
+        _ => panic!("{} is not a listen var", name),
+    };
+    assert_eq!(listen_var("lines").framing, ListenFraming::Line);
+    assert_eq!(listen_var("records").framing, ListenFraming::Nul);
+    assert_eq!(listen_var("numbers").framing, ListenFraming::Regex(r"value=(\d+)".to_string()));
+    let songs = listen_var("songs");
+    assert_eq!(songs.framing, ListenFraming::Json);
+    let song = DynVal::from(serde_json::json!({ "title": "foo", "artist": "bar" }));
+    assert_eq!(songs.transform.unwrap().apply(song).unwrap(), DynVal::from("foo"));
+
+    let restarting = r#"(deflisten restarting :restart "on-failure" :max-restarts 5 :max-restart-delay "30s" "cmd")"#;
+    let (_, asts) = files.load_str("restart.yuck".to_string(), restarting.to_string()).unwrap();
+    match Config::generate(&mut files, asts).unwrap().script_vars.get("restarting") {
+        Some(ScriptVarDefinition::Listen(var)) => assert_eq!(
+            var.restart,
+            RestartPolicy {
+                mode: RestartMode::OnFailure,
+                max_restarts: Some(5),
+                delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(30)
+            }
+        ),
+        _ => panic!("restarting is not a listen var"),
+    }
+
+    for invalid in [
+        r#"(deflisten foo :restart "sometimes" "cmd")"#,
+        r#"(deflisten foo :framing "lines" "cmd")"#,
+        r#"(deflisten foo :framing "nul" :regex "a" "cmd")"#,
+        r#"(deflisten foo :regex "(" "cmd")"#,
+        r#"(deflisten foo :transform {other_var} "cmd")"#,
+        r#"(deflisten foo :transform {x => x + other_var} "cmd")"#,
+    ] {
+        let (_, asts) = files.load_str("invalid.yuck".to_string(), invalid.to_string()).unwrap();
+        assert!(Config::generate(&mut files, asts).is_err(), "{} should not be valid", invalid);
+    }
+}
+
+#[test]
+fn test_poll_timeout() {
+    use super::script_var_definition::ScriptVarDefinition;
+    use eww_shared_util::VarName;
+    use std::time::Duration;
+
+    let input = r#"
+        (defsettings :poll-timeout "5s")
+        (defpoll with-timeout :interval "1s" :timeout "500ms" "date")
+        (defpoll without-timeout :interval "1s" "date")
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    assert_eq!(config.settings.poll_timeout, Some(Duration::from_secs(5)));
+    let timeout_of = |name: &str| match config.script_vars.get(&VarName::from(name)) {
+        Some(ScriptVarDefinition::Poll(var)) => var.timeout,
+        _ => panic!("no poll var named {}", name),
+    };
+    assert_eq!(timeout_of("with-timeout"), Some(Duration::from_millis(500)));
+    assert_eq!(timeout_of("without-timeout"), None);
+}
+
+#[test]
+fn test_poll_source() {
+    use super::script_var_definition::{NativeVarSource, ScriptVarDefinition, VarSource};
+    use eww_shared_util::VarName;
+
+    let input = r#"
+        (defpoll log :interval "1s" :source "file" :path "/tmp/some.log")
+        (defpoll status :interval "1s" :timeout "1s" :source "unix-socket" :path "/tmp/status.sock" :request "status")
+        (defpoll now :interval "1s" :source "time")
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    let source_of = |name: &str| match config.script_vars.get(&VarName::from(name)) {
+        Some(ScriptVarDefinition::Poll(var)) => match &var.command {
+            VarSource::Native(_, source) => source.clone(),
+            other => panic!("expected a native source, got {:?}", other),
+        },
+        other => panic!("expected a poll var, got {:?}", other),
+    };
+    assert_eq!(source_of("log"), NativeVarSource::File { path: "/tmp/some.log".to_string() });
+    assert_eq!(
+        source_of("status"),
+        NativeVarSource::UnixSocket { path: "/tmp/status.sock".to_string(), request: Some("status".to_string()) }
+    );
+    assert_eq!(source_of("now"), NativeVarSource::Time);
+
+    let invalid_inputs = [
+        // a source and a script
+        r#"(defpoll x :interval "1s" :source "file" :path "/some/file" "cat /some/file")"#,
+        r#"(defpoll x :interval "1s" :source "nope")"#,
+        r#"(defpoll x :interval "1s" :source "file")"#,
+        r#"(defpoll x :interval "1s" :source "time" :path "/some/file")"#,
+    ];
+    for (i, input) in invalid_inputs.iter().enumerate() {
+        let (_, asts) = files.load_str(format!("invalid{}.yuck", i), input.to_string()).unwrap();
+        assert!(Config::generate(&mut files, asts).is_err(), "{} should be invalid", input);
+    }
+}
+
+#[test]
+fn test_file_var() {
+    use super::script_var_definition::ScriptVarDefinition;
+    use eww_shared_util::VarName;
+
+    let input = r#"
+        (deffile brightness :trim true "/sys/class/backlight/x/brightness")
+        (deffile state :json true :initial "{}" "/tmp/state.json")
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    let file_var = |name: &str| match config.script_vars.get(&VarName::from(name)) {
+        Some(ScriptVarDefinition::File(var)) => var.clone(),
+        other => panic!("expected a file var, got {:?}", other),
+    };
+    assert_eq!(file_var("brightness").path, "/sys/class/backlight/x/brightness");
+    assert!(file_var("brightness").trim && !file_var("brightness").json);
+    assert!(file_var("state").json && !file_var("state").trim);
+    assert_eq!(file_var("state").initial_value.as_string().unwrap(), "{}");
+}
+
+#[test]
+fn test_socket_var() {
+    use super::script_var_definition::{ListenFraming, ScriptVarDefinition};
+    use eww_shared_util::VarName;
+    use std::time::Duration;
+
+    let input = r#"
+        (defsocket events :request "subscribe" :framing "json" :reconnect-delay "500ms" "/tmp/events.sock")
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    match config.script_vars.get(&VarName::from("events")) {
+        Some(ScriptVarDefinition::Socket(var)) => {
+            assert_eq!(var.path, "/tmp/events.sock");
+            assert_eq!(var.request.as_deref(), Some("subscribe"));
+            assert_eq!(var.framing, ListenFraming::Json);
+            assert_eq!(var.reconnect_delay, Duration::from_millis(500));
+            assert_eq!(var.max_reconnect_delay, Duration::from_secs(60));
+        }
+        other => panic!("expected a socket var, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_magic_var() {
+    use eww_shared_util::VarName;
+    use std::time::Duration;
+
+    let input = r#"
+        (defmagic EWW_NET :interval "500ms" :include ["wlan0" "eth0"])
+        (defmagic EWW_DISK :exclude ["/boot"])
+        (defmagic EWW_CPU :interval "1s")
+        (defmagic EWW_PROCESSES :count 10)
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+
+    let net = &config.magic_vars[&VarName::from("EWW_NET")];
+    assert_eq!(net.interval, Some(Duration::from_millis(500)));
+    let net_filter = net.filter.as_ref().unwrap();
+    assert!(net_filter.keeps("wlan0"));
+    assert!(!net_filter.keeps("lo"));
+
+    let disk = &config.magic_vars[&VarName::from("EWW_DISK")];
+    assert_eq!(disk.interval, None);
+    let disk_filter = disk.filter.as_ref().unwrap();
+    assert!(disk_filter.keeps("/"));
+    assert!(!disk_filter.keeps("/boot"));
+
+    let cpu = &config.magic_vars[&VarName::from("EWW_CPU")];
+    assert_eq!(cpu.filter, None);
+    assert_eq!(cpu.options.count, None);
+
+    let processes = &config.magic_vars[&VarName::from("EWW_PROCESSES")];
+    assert_eq!(processes.options.count, Some(10));
+
+    let value = simplexpr::dynval::DynVal::from_string_or_json(r#"{"wlan0": 1, "lo": 2}"#.to_string());
+    assert_eq!(net_filter.apply(value).as_object().unwrap().keys().collect::<Vec<_>>(), vec!["wlan0"]);
+}
+
+#[test]
+fn test_listen_run_while() {
+    use super::script_var_definition::ScriptVarDefinition;
+    use eww_shared_util::VarName;
+
+    let input = r#"
+        (defvar music-visible false)
+        (deflisten song :run-while music-visible "playerctl --follow metadata")
+        (deflisten always "tail -F /tmp/log")
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    let song = &config.script_vars[&VarName::from("song")];
+    assert!(matches!(song, ScriptVarDefinition::Listen(_)));
+    assert_eq!(song.run_while_var_refs(), &[VarName::from("music-visible")]);
+    assert_eq!(config.script_vars[&VarName::from("always")].run_while_var_refs(), &[] as &[VarName]);
+}
+
+#[test]
+fn test_script_environment() {
+    use super::{
+        script_var_definition::{ScriptEnvironment, ScriptVarDefinition},
+        settings::PlaceholderMode,
+    };
+    use eww_shared_util::VarName;
+
+    let input = r#"
+        (defsettings :shell "/bin/bash" :env '{"LANG": "C", "MODE": "default"}')
+        (defsettings :cwd "scripts" :command-placeholders "env")
+        (deflisten song :shell "/usr/bin/zsh" :env '{"MODE": "song", "COUNT": 2}' "playerctl --follow metadata")
+    "#;
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    let config = Config::generate(&mut files, asts).unwrap();
+    assert_eq!(
+        config.settings.script_env,
+        ScriptEnvironment {
+            shell: Some("/bin/bash".to_string()),
+            env: maplit::btreemap! { "LANG".to_string() => "C".to_string(), "MODE".to_string() => "default".to_string() },
+            cwd: Some("scripts".to_string()),
+        }
+    );
+    assert_eq!(config.settings.command_placeholders, Some(PlaceholderMode::Env));
+    let song = match &config.script_vars[&VarName::from("song")] {
+        ScriptVarDefinition::Listen(x) => x.script_env.clone(),
+        _ => panic!("song should be a deflisten"),
+    };
+    assert_eq!(
+        song.with_defaults(&config.settings.script_env),
+        ScriptEnvironment {
+            shell: Some("/usr/bin/zsh".to_string()),
+            env: maplit::btreemap! {
+                "LANG".to_string() => "C".to_string(),
+                "MODE".to_string() => "song".to_string(),
+                "COUNT".to_string() => "2".to_string(),
+            },
+            cwd: Some("scripts".to_string()),
+        }
+    );
+}
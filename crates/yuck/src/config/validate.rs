@@ -1,13 +1,23 @@
 use std::collections::{HashMap, HashSet};
 
-use simplexpr::SimplExpr;
+use itertools::Itertools;
+
+use simplexpr::{
+    ast::meta_var_name,
+    dynval::ValueType,
+    typecheck::{self, TypeError},
+    SimplExpr,
+};
 
 use crate::{
     error::AstResult,
     parser::{ast::Ast, ast_iterator::AstIterator, from_ast::FromAst},
 };
 
-use super::{widget_definition::WidgetDefinition, widget_use::WidgetUse, Config};
+use super::{
+    script_var_definition::ScriptVarDefinition, var_definition::ExprDefinition, widget_definition::WidgetDefinition,
+    widget_use::WidgetUse, Config,
+};
 use eww_shared_util::{AttrName, Span, Spanned, VarName};
 
 #[derive(Debug, thiserror::Error)]
@@ -25,6 +35,12 @@ pub enum ValidationError {
         /// True if the error occurred inside a widget definition, false if it occurred in a window definition
         in_definition: bool,
     },
+
+    #[error("The value of `{name}` depends on itself")]
+    RecursiveExprDefinition { span: Span, name: VarName },
+
+    #[error(transparent)]
+    TypeError(#[from] TypeError),
 }
 
 impl Spanned for ValidationError {
@@ -33,6 +49,8 @@ impl Spanned for ValidationError {
             ValidationError::UnknownWidget(span, _) => *span,
             ValidationError::MissingAttr { use_span, .. } => *use_span,
             ValidationError::UnknownVariable { span, .. } => *span,
+            ValidationError::RecursiveExprDefinition { span, .. } => *span,
+            ValidationError::TypeError(err) => err.span(),
         }
     }
 }
@@ -41,8 +59,15 @@ pub fn validate(config: &Config, additional_globals: Vec<VarName>) -> Result<(),
     let var_names = std::iter::empty()
         .chain(additional_globals.iter().cloned())
         .chain(config.script_vars.keys().cloned())
+        .chain(config.script_vars.keys().map(meta_var_name))
         .chain(config.var_definitions.keys().cloned())
-        .collect();
+        .chain(config.expr_definitions.keys().cloned())
+        .collect::<HashSet<_>>();
+    for def in expr_definitions_in_dependency_order(&config.expr_definitions)? {
+        if let Some((span, name)) = def.expr.var_refs_with_span().into_iter().find(|(_, name)| !var_names.contains(*name)) {
+            return Err(ValidationError::UnknownVariable { span, name: name.clone(), in_definition: false });
+        }
+    }
     for window in config.window_definitions.values() {
         validate_variables_in_widget_use(&config.widget_definitions, &var_names, &window.widget, false)?;
     }
@@ -52,6 +77,41 @@ pub fn validate(config: &Config, additional_globals: Vec<VarName>) -> Result<(),
     Ok(())
 }
 
+/// Order the `defexpr`s such that every expression is listed after all of the expressions it references.
+/// Fails if an expression references itself, either directly or through other expressions.
+pub fn expr_definitions_in_dependency_order(
+    defs: &HashMap<VarName, ExprDefinition>,
+) -> Result<Vec<&ExprDefinition>, ValidationError> {
+    fn visit<'a>(
+        defs: &'a HashMap<VarName, ExprDefinition>,
+        def: &'a ExprDefinition,
+        visiting: &mut HashSet<VarName>,
+        sorted: &mut Vec<&'a ExprDefinition>,
+    ) -> Result<(), ValidationError> {
+        if sorted.iter().any(|x| x.name == def.name) {
+            return Ok(());
+        }
+        if !visiting.insert(def.name.clone()) {
+            return Err(ValidationError::RecursiveExprDefinition { span: def.span, name: def.name.clone() });
+        }
+        for var_ref in def.expr.collect_var_refs() {
+            if let Some(dependency) = defs.get(&var_ref) {
+                visit(defs, dependency, visiting, sorted)?;
+            }
+        }
+        visiting.remove(&def.name);
+        sorted.push(def);
+        Ok(())
+    }
+
+    let mut sorted = Vec::new();
+    // go through the definitions by name, so that the result doesn't depend on the order of the hashmap
+    for def in defs.values().sorted_by(|a, b| a.name.0.cmp(&b.name.0)) {
+        visit(defs, def, &mut HashSet::new(), &mut sorted)?;
+    }
+    Ok(sorted)
+}
+
 pub fn validate_widget_definition(
     other_defs: &HashMap<String, WidgetDefinition>,
     globals: &HashSet<VarName>,
@@ -100,9 +160,82 @@ pub fn validate_variables_in_widget_use(
         return Err(ValidationError::UnknownVariable { span, name: var, in_definition: is_in_definition });
     }
 
-    for child in widget.children.iter() {
-        let _ = validate_variables_in_widget_use(defs, variables, child, is_in_definition)?;
+    if widget.name == "let" {
+        // the bindings of a let are only available to its child
+        let mut variables = variables.clone();
+        variables.extend(widget.attrs.attrs.keys().map(|name| VarName(name.to_string())));
+        for child in widget.children.iter() {
+            validate_variables_in_widget_use(defs, &variables, child, is_in_definition)?;
+        }
+    } else {
+        for child in widget.children.iter() {
+            let _ = validate_variables_in_widget_use(defs, variables, child, is_in_definition)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the types of all expressions in the config.
+///
+/// `global_types` contains the types of globals that are not defined in the config itself, such as the magic variables,
+/// and `widget_prop_types` maps the names of builtin widgets to the types their properties expect.
+/// The types of `defvar`s are guessed from their initial values, while script variables may contain anything.
+pub fn typecheck(
+    config: &Config,
+    global_types: HashMap<VarName, ValueType>,
+    widget_prop_types: &HashMap<String, HashMap<AttrName, ValueType>>,
+) -> Result<(), ValidationError> {
+    let mut globals = global_types;
+    globals.extend(config.script_vars.keys().map(|name| (name.clone(), ValueType::Any)));
+    globals.extend(config.var_definitions.values().map(|var| (var.name.clone(), typecheck::guess_type(&var.initial_value))));
+    for def in expr_definitions_in_dependency_order(&config.expr_definitions)? {
+        let ty = typecheck::infer_type(&def.expr, &globals)?;
+        globals.insert(def.name.clone(), ty);
     }
 
+    for run_while_expr in config.script_vars.values().filter_map(|var| var.run_while_expr()) {
+        typecheck::check_type(run_while_expr, &globals, ValueType::Bool)?;
+    }
+    for window in config.window_definitions.values() {
+        typecheck_widget_use(widget_prop_types, &globals, &window.widget)?;
+    }
+    for def in config.widget_definitions.values() {
+        let mut variables = globals.clone();
+        variables.extend(def.expected_args.iter().map(|arg| (VarName(arg.name.to_string()), ValueType::Any)));
+        typecheck_widget_use(widget_prop_types, &variables, &def.widget)?;
+    }
+    Ok(())
+}
+
+fn typecheck_widget_use(
+    widget_prop_types: &HashMap<String, HashMap<AttrName, ValueType>>,
+    variables: &HashMap<VarName, ValueType>,
+    widget: &WidgetUse,
+) -> Result<(), ValidationError> {
+    if widget.name == "let" {
+        // the bindings are evaluated in the surrounding scope, but only available to the child
+        let mut child_variables = variables.clone();
+        for (name, entry) in widget.attrs.attrs.iter() {
+            if let Ok(expr) = entry.value.as_simplexpr() {
+                child_variables.insert(VarName(name.to_string()), typecheck::infer_type(&expr, variables)?);
+            }
+        }
+        for child in widget.children.iter() {
+            typecheck_widget_use(widget_prop_types, &child_variables, child)?;
+        }
+        return Ok(());
+    }
+
+    let prop_types = widget_prop_types.get(&widget.name);
+    for (name, entry) in widget.attrs.attrs.iter() {
+        if let Ok(expr) = entry.value.as_simplexpr() {
+            let expected = prop_types.and_then(|props| props.get(name)).copied().unwrap_or(ValueType::Any);
+            typecheck::check_type(&expr, variables, expected)?;
+        }
+    }
+    for child in widget.children.iter() {
+        typecheck_widget_use(widget_prop_types, variables, child)?;
+    }
     Ok(())
 }
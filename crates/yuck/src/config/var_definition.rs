@@ -12,7 +12,7 @@ use crate::{
 };
 use eww_shared_util::{AttrName, Span, VarName};
 
-#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
 pub struct VarDefinition {
     pub name: VarName,
     pub initial_value: DynVal,
@@ -32,3 +32,25 @@ impl FromAstElementContent for VarDefinition {
         result.note(r#"Expected format: `(defvar name "initial-value")`"#)
     }
 }
+
+/// A global variable whose value is computed from an expression, and updated whenever a variable it references changes.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct ExprDefinition {
+    pub name: VarName,
+    pub expr: SimplExpr,
+    pub span: Span,
+}
+
+impl FromAstElementContent for ExprDefinition {
+    const ELEMENT_NAME: &'static str = "defexpr";
+
+    fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
+        let result: AstResult<_> = try {
+            let (_, name) = iter.expect_symbol()?;
+            let expr = iter.expect_any()?.as_simplexpr()?;
+            iter.expect_done()?;
+            Self { name: VarName(name), expr, span }
+        };
+        result.note(r#"Expected format: `(defexpr name {some_var * 2})`"#)
+    }
+}
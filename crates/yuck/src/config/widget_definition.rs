@@ -14,7 +14,7 @@ use eww_shared_util::{AttrName, Span, Spanned, VarName};
 
 use super::widget_use::WidgetUse;
 
-#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
 pub struct AttrSpec {
     pub name: AttrName,
     pub optional: bool,
@@ -30,7 +30,7 @@ impl FromAst for AttrSpec {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
 pub struct WidgetDefinition {
     pub name: String,
     pub expected_args: Vec<AttrSpec>,
@@ -1,17 +1,17 @@
-use std::collections::HashMap;
-
 use simplexpr::SimplExpr;
 
+use itertools::Itertools;
+
 use crate::{
     config::attributes::AttrEntry,
-    error::{AstError, AstResult},
+    error::{AstError, AstResult, AstResultExt, FormFormatError},
     parser::{ast::Ast, ast_iterator::AstIterator, from_ast::FromAst},
 };
-use eww_shared_util::{AttrName, Span, Spanned, VarName};
+use eww_shared_util::{AttrName, Span, Spanned};
 
 use super::attributes::Attributes;
 
-#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
 pub struct WidgetUse {
     pub name: String,
     pub attrs: Attributes,
@@ -38,6 +38,9 @@ impl FromAst for WidgetUse {
         } else {
             let mut iter = e.try_ast_iter()?;
             let (name_span, name) = iter.expect_symbol()?;
+            if name == "let" {
+                return parse_let(span, name_span, iter);
+            }
             let attrs = iter.expect_key_values()?;
             let children = iter.map(WidgetUse::from_ast).collect::<AstResult<Vec<_>>>()?;
             Ok(Self { name, attrs, children, span, name_span })
@@ -45,6 +48,42 @@ impl FromAst for WidgetUse {
     }
 }
 
+/// Parse a `(let [name "expression" ...] child)` form into nested [`WidgetUse`]s named `let`,
+/// each with a single binding as its attribute, and the given child as the only child of the innermost one.
+///
+/// Bindings may refer to the bindings before them. As every binding gets its own `let`,
+/// it is evaluated in a scope that already contains the ones before it.
+fn parse_let<I: Iterator<Item = Ast>>(span: Span, name_span: Span, mut iter: AstIterator<I>) -> AstResult<WidgetUse> {
+    let (bindings_span, bindings) = iter
+        .expect_array()
+        .wrong_expr_type_to(|_, _| Some(FormFormatError::LetBindingsMissing(name_span.point_span_at_end())))
+        .note(EXPECTED_LET_FORMAT)?;
+    if bindings.len() % 2 != 0 {
+        return Err(FormFormatError::LetBindingMissingValue(bindings.last().unwrap().span()).into());
+    }
+
+    let mut attrs = Vec::new();
+    for (name, value) in bindings.into_iter().tuples() {
+        let key_span = name.span();
+        let name = name.as_symbol().note(EXPECTED_LET_FORMAT)?;
+        let value_span = value.span();
+        let expr = value.as_simplexpr()?;
+        attrs.push((AttrName(name), AttrEntry::new(key_span, Ast::SimplExpr(value_span, expr))));
+    }
+
+    let child = iter.expect_any().note(EXPECTED_LET_FORMAT).and_then(WidgetUse::from_ast)?;
+    iter.expect_done().map_err(|e| FormFormatError::LetMultipleChildren(e.span()))?;
+    Ok(attrs.into_iter().rev().fold(child, |child, (name, entry)| WidgetUse {
+        name: "let".to_string(),
+        attrs: Attributes::new(bindings_span, maplit::hashmap! { name => entry }),
+        children: vec![child],
+        span,
+        name_span,
+    }))
+}
+
+static EXPECTED_LET_FORMAT: &str = r#"Expected format: `(let [name "expression" ...] (contained-widget))`"#;
+
 impl Spanned for WidgetUse {
     fn span(&self) -> Span {
         self.span
@@ -170,6 +170,15 @@ pub enum FormFormatError {
 
     #[error("Widget definition has more than one child widget")]
     WidgetDefMultipleChildren(Span),
+
+    #[error("Let expression missing list of bindings")]
+    LetBindingsMissing(Span),
+
+    #[error("Let binding has no value")]
+    LetBindingMissingValue(Span),
+
+    #[error("Let expression has more than one child widget")]
+    LetMultipleChildren(Span),
 }
 
 impl Spanned for FormFormatError {
@@ -177,6 +186,9 @@ impl Spanned for FormFormatError {
         match self {
             FormFormatError::WidgetDefArglistMissing(span) => *span,
             FormFormatError::WidgetDefMultipleChildren(span) => *span,
+            FormFormatError::LetBindingsMissing(span) => *span,
+            FormFormatError::LetBindingMissingValue(span) => *span,
+            FormFormatError::LetMultipleChildren(span) => *span,
         }
     }
 }
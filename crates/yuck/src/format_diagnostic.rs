@@ -194,6 +194,25 @@ impl ToDiagnostic for ValidationError {
 
                 diag.with_notes(extra_notes)
             }
+            ValidationError::RecursiveExprDefinition { span, .. } => gen_diagnostic! {
+                msg = self,
+                label = span => "Defined here",
+                note = "Hint: Make sure that the expressions of your `defexpr`s don't reference each other in a cycle",
+            },
+            ValidationError::TypeError(source) => source.to_diagnostic(),
+        }
+    }
+}
+
+impl ToDiagnostic for simplexpr::typecheck::TypeError {
+    fn to_diagnostic(&self) -> Diagnostic<usize> {
+        use simplexpr::typecheck::TypeError::*;
+        match self {
+            Mismatch { span, expected, .. } => gen_diagnostic! {
+                msg = self,
+                label = span => format!("Expected a `{}` here", expected),
+            },
+            Eval(source) => source.to_diagnostic(),
         }
     }
 }
@@ -249,10 +268,10 @@ impl ToDiagnostic for simplexpr::eval::EvalError {
             NoVariablesAllowed(name) => gen_diagnostic!(self),
             UnknownVariable(name, similar) => {
                 let mut notes = Vec::new();
-                if similar.len() == 1 {
-                    notes.push(format!("Did you mean `{}`?", similar.first().unwrap()))
-                } else if similar.len() > 1 {
-                    notes.push(format!("Did you mean one of: {}?", similar.iter().map(|x| format!("`{}`", x)).join(", ")))
+                match similar.as_slice() {
+                    [] => {}
+                    [similar] => notes.push(format!("Did you mean `{}`?", similar)),
+                    _ => notes.push(format!("Did you mean one of: {}?", similar.iter().map(|x| format!("`{}`", x)).join(", "))),
                 }
                 // TODO the note here is confusing when it's an unknown variable being used _within_ a string literal / simplexpr
                 // it only really makes sense on top-level symbols
@@ -298,6 +317,21 @@ impl ToDiagnostic for FormFormatError {
                         To include multiple elements, wrap these elements in a single container widget such as `box`.\n\
                         This is necessary as eww can't know how you want these elements to be layed out otherwise."
             },
+            FormFormatError::LetBindingsMissing(span) => gen_diagnostic! {
+                msg = self,
+                label = span => "Insert the bindings (e.g.: `[name \"expression\"]`) here",
+            },
+            FormFormatError::LetBindingMissingValue(span) => gen_diagnostic! {
+                msg = self,
+                label = span => "No value given for this binding",
+                note = "Every name in the list of bindings needs to be followed by the expression it is bound to.",
+            },
+            FormFormatError::LetMultipleChildren(span) => gen_diagnostic! {
+                msg = self,
+                label = span => "Found more than one child element here.",
+                note = "A let expression may only contain one child element.\n\
+                        To include multiple elements, wrap these elements in a single container widget such as `box`."
+            },
         }
     }
 }
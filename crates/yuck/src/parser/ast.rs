@@ -34,7 +34,7 @@ impl Display for AstType {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(PartialEq, Clone, serde::Serialize)]
 pub enum Ast {
     List(Span, Vec<Ast>),
     Array(Span, Vec<Ast>),